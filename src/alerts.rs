@@ -0,0 +1,460 @@
+//! Threshold alerts evaluated against every snapshot.
+//!
+//! Rules and actions come from a TOML file:
+//!
+//! ```toml
+//! [[rule]]
+//! name = "cpu-hot"
+//! kind = "cpu"           # cpu, memory, load, process_cpu, process_rss,
+//!                        # process_missing or zombies
+//! above = 90             # percent, load, count, or a size such as "8GiB"
+//! for = 60               # seconds the condition must hold (default 0)
+//!
+//! [[rule]]
+//! name = "no-sshd"
+//! kind = "process_missing"
+//! process = "sshd"
+//!
+//! [actions]
+//! hook = "notify-send \"$ALERT_RULE\" \"$ALERT_MESSAGE\""
+//! log = "/var/log/system_info-alerts.log"
+//! syslog = true
+//! ```
+//!
+//! `process_cpu` and `process_rss` look at every process unless `process`
+//! names one. An alert fires once when its condition has held for `for`
+//! seconds and resolves when the condition stops holding.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::net::UnixDatagram;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::thread;
+
+use chrono::{Local, TimeZone};
+use serde::Deserialize;
+
+use crate::snapshot::Snapshot;
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleKind {
+    Cpu,
+    Memory,
+    Load,
+    ProcessCpu,
+    ProcessRss,
+    ProcessMissing,
+    Zombies,
+}
+
+/// A threshold given either as a number or, for sizes, as a string with a
+/// binary or decimal unit such as `"512MiB"` or `"8GB"`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Threshold {
+    Number(f64),
+    Text(String),
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub name: String,
+    pub kind: RuleKind,
+    pub above: Option<Threshold>,
+    #[serde(rename = "for", default)]
+    pub for_secs: u64,
+    pub process: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Actions {
+    /// Shell command run with `ALERT_RULE`, `ALERT_STATE` and
+    /// `ALERT_MESSAGE` set.
+    pub hook: Option<String>,
+    /// File each alert is appended to.
+    pub log: Option<PathBuf>,
+    /// Sends alerts to the local syslog daemon through `/dev/log`.
+    #[serde(default)]
+    pub syslog: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertConfig {
+    #[serde(rename = "rule", default)]
+    pub rules: Vec<Rule>,
+    #[serde(default)]
+    pub actions: Actions,
+}
+
+impl AlertConfig {
+
+    pub fn load(path: &Path) -> io::Result<AlertConfig> {
+        let text = fs::read_to_string(path)?;
+        let invalid = |message: String| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message))
+        };
+        let config: AlertConfig = toml::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+        for rule in &config.rules {
+            rule.validate().map_err(|e| invalid(format!("rule '{}': {}", rule.name, e)))?;
+        }
+        Ok(config)
+    }
+}
+
+impl Rule {
+
+    fn validate(&self) -> Result<(), String> {
+        match self.kind {
+            RuleKind::ProcessMissing => {
+                if self.process.is_none() {
+                    return Err(String::from("process_missing needs `process`"));
+                }
+            }
+            _ => {
+                self.threshold()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The threshold in the unit the rule's metric uses; kB for RSS.
+    fn threshold(&self) -> Result<f64, String> {
+        match self.above {
+            None => Err(String::from("missing `above`")),
+            Some(Threshold::Number(value)) => Ok(value),
+            Some(Threshold::Text(ref text)) if self.kind == RuleKind::ProcessRss => {
+                parse_size(text).map(|bytes| bytes / 1024.0)
+            }
+            Some(Threshold::Text(ref text)) => text.trim_end_matches('%').trim().parse()
+                .map_err(|_| format!("invalid threshold '{}'", text)),
+        }
+    }
+
+    /// Describes the violation if the rule's condition holds for `snapshot`.
+    fn check(&self, snapshot: &Snapshot) -> Option<String> {
+        let threshold = self.threshold().unwrap_or(f64::INFINITY);
+        let matches_name = |name: &str| self.process.as_ref().is_none_or(|p| p == name);
+        match self.kind {
+            RuleKind::Cpu if snapshot.cpu_percent > threshold => {
+                Some(format!("CPU at {:.1}% (above {}%)", snapshot.cpu_percent, threshold))
+            }
+            RuleKind::Memory if snapshot.mem_percent > threshold => {
+                Some(format!("memory at {:.1}% (above {}%)", snapshot.mem_percent, threshold))
+            }
+            RuleKind::Load if snapshot.load_average.one > threshold => {
+                Some(format!("load at {:.2} (above {})", snapshot.load_average.one, threshold))
+            }
+            RuleKind::ProcessCpu => snapshot.processes.iter()
+                .filter(|p| matches_name(&p.process_name) && p.cpu_percent > threshold)
+                .max_by(|a, b| a.cpu_percent.partial_cmp(&b.cpu_percent).unwrap())
+                .map(|p| format!("{} ({}) at {:.1}% CPU (above {}%)", p.process_name, p.pid, p.cpu_percent, threshold)),
+            RuleKind::ProcessRss => snapshot.processes.iter()
+                .filter(|p| matches_name(&p.process_name) && p.rss > threshold)
+                .max_by(|a, b| a.rss.partial_cmp(&b.rss).unwrap())
+                .map(|p| format!("{} ({}) has {:.0} kB resident (above {:.0} kB)", p.process_name, p.pid, p.rss, threshold)),
+            RuleKind::ProcessMissing => {
+                let name = self.process.as_ref()?;
+                if snapshot.processes.iter().any(|p| &p.process_name == name) {
+                    None
+                } else {
+                    Some(format!("{} is not running", name))
+                }
+            }
+            RuleKind::Zombies => {
+                let zombies = snapshot.processes.iter().filter(|p| p.state == "Z").count();
+                if zombies as f64 > threshold {
+                    Some(format!("{} zombie processes (above {})", zombies, threshold))
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Parses `"8GiB"`, `"512 MB"` or a plain byte count into bytes.
+pub fn parse_size(text: &str) -> Result<f64, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("invalid size '{}'", text))?;
+    let multiplier = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1.0,
+        "k" | "kb" => 1e3,
+        "m" | "mb" => 1e6,
+        "g" | "gb" => 1e9,
+        "t" | "tb" => 1e12,
+        "kib" => 1024.0,
+        "mib" => 1024.0 * 1024.0,
+        "gib" => 1024.0 * 1024.0 * 1024.0,
+        "tib" => 1024.0 * 1024.0 * 1024.0 * 1024.0,
+        _ => return Err(format!("invalid size unit in '{}'", text)),
+    };
+    Ok(number * multiplier)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+impl AlertState {
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AlertState::Firing => "firing",
+            AlertState::Resolved => "resolved",
+        }
+    }
+}
+
+/// A rule starting or stopping to fire.
+#[derive(Debug, Clone)]
+pub struct AlertEvent {
+    pub rule: String,
+    pub state: AlertState,
+    pub message: String,
+    pub timestamp: u64,
+}
+
+#[derive(Debug, Default)]
+struct RuleState {
+    /// When the condition started holding, in ms.
+    pending_since: Option<u64>,
+    /// The message of the firing alert.
+    firing: Option<String>,
+}
+
+pub struct AlertEngine {
+    rules: Vec<Rule>,
+    states: Vec<RuleState>,
+}
+
+impl AlertEngine {
+
+    pub fn new(rules: Vec<Rule>) -> AlertEngine {
+        let states = rules.iter().map(|_| RuleState::default()).collect();
+        AlertEngine {
+            rules,
+            states,
+        }
+    }
+
+    /// Checks every rule against `snapshot` and returns the alerts that
+    /// started or stopped firing.
+    pub fn evaluate(&mut self, snapshot: &Snapshot) -> Vec<AlertEvent> {
+        let now = snapshot.timestamp;
+        let mut events = vec![];
+        for (rule, state) in self.rules.iter().zip(self.states.iter_mut()) {
+            match rule.check(snapshot) {
+                Some(message) => {
+                    let since = *state.pending_since.get_or_insert(now);
+                    let held = now.saturating_sub(since) >= rule.for_secs * 1000;
+                    if held && state.firing.is_none() {
+                        events.push(AlertEvent {
+                            rule: rule.name.clone(),
+                            state: AlertState::Firing,
+                            message: message.clone(),
+                            timestamp: now,
+                        });
+                    }
+                    if held {
+                        state.firing = Some(message);
+                    }
+                }
+                None => {
+                    state.pending_since = None;
+                    if let Some(message) = state.firing.take() {
+                        events.push(AlertEvent {
+                            rule: rule.name.clone(),
+                            state: AlertState::Resolved,
+                            message,
+                            timestamp: now,
+                        });
+                    }
+                }
+            }
+        }
+        events
+    }
+
+    /// `(rule, message)` for every alert currently firing.
+    pub fn active(&self) -> Vec<(&str, &str)> {
+        self.rules.iter()
+            .zip(self.states.iter())
+            .filter_map(|(rule, state)| state.firing.as_ref().map(|m| (rule.name.as_str(), m.as_str())))
+            .collect()
+    }
+}
+
+/// Path of the local syslog socket.
+const SYSLOG_SOCKET: &str = "/dev/log";
+/// Syslog facility `user`.
+const SYSLOG_FACILITY: u8 = 1;
+
+impl Actions {
+
+    /// Runs every configured action for `event`. Each action is attempted
+    /// even if an earlier one fails; the first error is returned.
+    pub fn dispatch(&self, event: &AlertEvent) -> io::Result<()> {
+        let mut result = Ok(());
+        if let Some(ref hook) = self.hook {
+            result = result.and(run_hook(hook, event));
+        }
+        if let Some(ref path) = self.log {
+            result = result.and(append_log(path, event));
+        }
+        if self.syslog {
+            result = result.and(send_syslog(event));
+        }
+        result
+    }
+}
+
+fn run_hook(hook: &str, event: &AlertEvent) -> io::Result<()> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(hook)
+        .env("ALERT_RULE", &event.rule)
+        .env("ALERT_STATE", event.state.name())
+        .env("ALERT_MESSAGE", &event.message)
+        .spawn()?;
+    // Reap the hook without holding up collection.
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+fn append_log(path: &Path, event: &AlertEvent) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(
+        file,
+        "{} {} {}: {}",
+        Local.timestamp_millis(event.timestamp as i64).to_rfc3339(),
+        event.state.name().to_uppercase(),
+        event.rule,
+        event.message,
+    )
+}
+
+fn send_syslog(event: &AlertEvent) -> io::Result<()> {
+    // Warning while firing, notice once resolved.
+    let severity = match event.state {
+        AlertState::Firing => 4,
+        AlertState::Resolved => 5,
+    };
+    let message = format!(
+        "<{}>system_info[{}]: {} {}: {}",
+        SYSLOG_FACILITY * 8 + severity,
+        process::id(),
+        event.state.name(),
+        event.rule,
+        event.message,
+    );
+    let socket = UnixDatagram::unbound()?;
+    socket.send_to(message.as_bytes(), SYSLOG_SOCKET)
+        .map(|_| ())
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", SYSLOG_SOCKET, e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CpuBreakdown, LoadAverage, MemBreakdown};
+
+    fn snapshot(timestamp: u64, cpu_percent: f64) -> Snapshot {
+        Snapshot {
+            timestamp,
+            cpu_percent,
+            mem_percent: 0.0,
+            total_mem: 0.0,
+            cpu_breakdown: CpuBreakdown::default(),
+            mem_breakdown: MemBreakdown::default(),
+            load_average: LoadAverage::default(),
+            processes: vec![],
+        }
+    }
+
+    fn rule(kind: RuleKind, above: Threshold, for_secs: u64) -> Rule {
+        Rule {
+            name: String::from("test"),
+            kind,
+            above: Some(above),
+            for_secs,
+            process: None,
+        }
+    }
+
+    fn states(events: &[AlertEvent]) -> Vec<AlertState> {
+        events.iter().map(|e| e.state).collect()
+    }
+
+    #[test]
+    fn fires_after_holding_once_and_rearms() {
+        let mut engine = AlertEngine::new(vec![rule(RuleKind::Cpu, Threshold::Number(90.0), 10)]);
+        assert!(engine.evaluate(&snapshot(0, 95.0)).is_empty());
+        assert!(engine.evaluate(&snapshot(9_999, 95.0)).is_empty());
+        assert!(engine.active().is_empty());
+
+        let fired = engine.evaluate(&snapshot(10_000, 96.0));
+        assert_eq!(states(&fired), [AlertState::Firing]);
+        assert_eq!(fired[0].message, "CPU at 96.0% (above 90%)");
+        assert!(engine.evaluate(&snapshot(11_000, 97.0)).is_empty());
+        assert_eq!(engine.active(), [("test", "CPU at 97.0% (above 90%)")]);
+
+        let resolved = engine.evaluate(&snapshot(12_000, 50.0));
+        assert_eq!(states(&resolved), [AlertState::Resolved]);
+        assert!(engine.active().is_empty());
+
+        // The hold starts over after resolving.
+        assert!(engine.evaluate(&snapshot(13_000, 95.0)).is_empty());
+        assert!(engine.evaluate(&snapshot(22_000, 95.0)).is_empty());
+        assert_eq!(states(&engine.evaluate(&snapshot(23_000, 95.0))), [AlertState::Firing]);
+    }
+
+    #[test]
+    fn a_dip_restarts_the_hold() {
+        let mut engine = AlertEngine::new(vec![rule(RuleKind::Cpu, Threshold::Number(90.0), 5)]);
+        assert!(engine.evaluate(&snapshot(0, 95.0)).is_empty());
+        assert!(engine.evaluate(&snapshot(3_000, 10.0)).is_empty());
+        assert!(engine.evaluate(&snapshot(6_000, 95.0)).is_empty());
+        assert!(engine.evaluate(&snapshot(10_000, 95.0)).is_empty());
+        assert_eq!(states(&engine.evaluate(&snapshot(11_000, 95.0))), [AlertState::Firing]);
+    }
+
+    #[test]
+    fn rss_thresholds_are_kilobytes() {
+        let text = |s: &str| Threshold::Text(String::from(s));
+        assert_eq!(rule(RuleKind::ProcessRss, text("512MiB"), 0).threshold(), Ok(524_288.0));
+        assert_eq!(rule(RuleKind::ProcessRss, text("1 GB"), 0).threshold(), Ok(976_562.5));
+        assert_eq!(rule(RuleKind::ProcessRss, Threshold::Number(2048.0), 0).threshold(), Ok(2048.0));
+        assert_eq!(rule(RuleKind::Cpu, text("80%"), 0).threshold(), Ok(80.0));
+        assert!(rule(RuleKind::Cpu, text("8GiB"), 0).threshold().is_err());
+    }
+
+    #[test]
+    fn parses_sizes() {
+        let cases = [
+            ("1024", 1024.0),
+            ("2b", 2.0),
+            ("1.5k", 1500.0),
+            ("512 MB", 512e6),
+            ("512MiB", 512.0 * 1024.0 * 1024.0),
+            ("8GiB", 8.0 * 1024.0 * 1024.0 * 1024.0),
+            (" 2 TB ", 2e12),
+            ("1tib", 1024f64.powi(4)),
+        ];
+        for &(text, bytes) in &cases {
+            assert_eq!(parse_size(text), Ok(bytes), "{}", text);
+        }
+        for &text in &["", "MB", "12 parsecs", "1.2.3 GB", "-5MB"] {
+            assert!(parse_size(text).is_err(), "{}", text);
+        }
+    }
+}
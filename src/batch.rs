@@ -0,0 +1,132 @@
+use std::io::{self, Write};
+
+use crate::columns::{self, ColumnSpec};
+use crate::format::Format;
+use crate::snapshot::now_millis;
+use crate::{CPUUsage, MemInfo, Process};
+
+/// Controls what a batch snapshot prints for each iteration.
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    pub columns: Vec<ColumnSpec>,
+    pub limit: Option<usize>,
+    pub format: Format,
+}
+
+impl Default for BatchOptions {
+    fn default() -> BatchOptions {
+        BatchOptions {
+            columns: columns::default_columns(),
+            limit: None,
+            format: Format::default(),
+        }
+    }
+}
+
+pub fn write_summary<W: Write>(out: &mut W, cpu: f64, mem: f64, format: &Format) -> io::Result<()> {
+    writeln!(out, "CPU: {}%  Memory: {}%", format.percent(cpu), format.percent(mem))
+}
+
+pub fn write_process_table<W: Write>(
+    out: &mut W,
+    processes: &[Process],
+    now: u64,
+    options: &BatchOptions,
+) -> io::Result<()> {
+    let header: Vec<String> = options.columns.iter()
+        .map(|spec| pad(spec.column.title(), spec))
+        .collect();
+    writeln!(out, "{}", header.join(" ").trim_end())?;
+    let limit = options.limit.unwrap_or(processes.len());
+    for process in processes.iter().take(limit) {
+        let row: Vec<String> = options.columns.iter()
+            .map(|spec| pad(&spec.column.value(process, now, &options.format), spec))
+            .collect();
+        writeln!(out, "{}", row.join(" ").trim_end())?;
+    }
+    Ok(())
+}
+
+/// Writes one iteration of batch output: a summary line followed by the
+/// process table and a blank separator line.
+pub fn write_snapshot<W: Write>(
+    out: &mut W,
+    cpu_usage: &CPUUsage,
+    mem_info: &MemInfo,
+    processes: &[Process],
+    options: &BatchOptions,
+) -> io::Result<()> {
+    write_summary(out, cpu_usage.get_current_cpu(), mem_info.get_current_mem(), &options.format)?;
+    writeln!(out)?;
+    write_process_table(out, processes, now_millis(), options)?;
+    writeln!(out)?;
+    out.flush()
+}
+
+/// Pads `value` to the column width. Text is cut to fit; numbers never
+/// are, since raw values may be longer than the formatted ones.
+fn pad(value: &str, spec: &ColumnSpec) -> String {
+    let width = spec.width();
+    if spec.column.is_numeric() {
+        format!("{:>width$}", value, width = width)
+    } else {
+        let value: String = value.chars().take(width).collect();
+        format!("{:<width$}", value, width = width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::columns::{Column, ColumnSpec};
+
+    fn output(processes: &[Process], options: &BatchOptions) -> String {
+        let mut out = vec![];
+        write_process_table(&mut out, processes, 0, options).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    fn processes() -> Vec<Process> {
+        let mut busy = Process::new(42, String::from("R"), String::from("a-very-long-process-name"), 0.0, 0.0, 2048.0);
+        busy.set_cpu_percent(12.345);
+        vec![busy, Process::new(1, String::from("S"), String::from("init"), 0.0, 0.0, 512.0)]
+    }
+
+    #[test]
+    fn prints_aligned_rows_up_to_the_limit() {
+        let options = BatchOptions {
+            columns: vec![
+                ColumnSpec::auto(Column::Pid),
+                ColumnSpec { column: Column::Name, width: Some(10) },
+                ColumnSpec::auto(Column::Rss),
+                ColumnSpec::auto(Column::CpuPercent),
+            ],
+            limit: Some(1),
+            format: Format::default(),
+        };
+        assert_eq!(
+            output(&processes(), &options),
+            concat!(
+                "    PID Process Na        RSS     CPU %\n",
+                "     42 a-very-lon    2.0 MiB     12.35\n",
+            ),
+        );
+    }
+
+    #[test]
+    fn raw_values_are_never_cut() {
+        let options = BatchOptions {
+            columns: vec![ColumnSpec { column: Column::Rss, width: Some(3) }, ColumnSpec::auto(Column::CpuPercent)],
+            limit: None,
+            format: Format::raw(),
+        };
+        assert_eq!(output(&processes(), &options), "RSS     CPU %\n2048    12.345\n512         0\n");
+    }
+
+    #[test]
+    fn summarizes_cpu_and_memory() {
+        let mut out = vec![];
+        write_summary(&mut out, 12.345, 50.0, &Format::default()).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "CPU: 12.35%  Memory: 50.00%\n");
+    }
+}
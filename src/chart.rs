@@ -0,0 +1,238 @@
+use chrono::{Local, TimeZone};
+use system_info::format::Format;
+use system_info::history::Sample;
+use termion::event::Key;
+
+/// Selectable chart widths in ms, with the label shown in the chart title.
+const ZOOM_LEVELS: [(u64, &str); 4] = [
+    (60_000, "1 min"),
+    (300_000, "5 min"),
+    (3_600_000, "1 h"),
+    (86_400_000, "24 h"),
+];
+
+const LABEL_COUNT: u64 = 5;
+
+/// The time range shown on the CPU/Memory chart. The window normally ends
+/// at the latest sample; it can be panned back through history or frozen.
+pub struct ChartWindow {
+    zoom: usize,
+    pan: u64,
+    frozen_at: Option<u64>,
+}
+
+impl ChartWindow {
+
+    pub fn new() -> ChartWindow {
+        ChartWindow {
+            zoom: 1,
+            pan: 0,
+            frozen_at: None,
+        }
+    }
+
+    pub fn span(&self) -> u64 {
+        ZOOM_LEVELS[self.zoom].0
+    }
+
+    /// Handles the chart keys: `1`-`4` zoom, `[`/`]` pan back and forward,
+    /// `f` freezes scrolling. Panning stops once the window starts at
+    /// `oldest`, the first sample still kept. Returns whether the key was used.
+    pub fn handle_key(&mut self, key: Key, now: u64, oldest: Option<u64>) -> bool {
+        match key {
+            Key::Char(c @ '1'..='4') => {
+                self.zoom = c as usize - '1' as usize;
+            }
+            Key::Char('[') => {
+                self.pan += self.span() / 4;
+            }
+            Key::Char(']') => {
+                self.pan = self.pan.saturating_sub(self.span() / 4);
+            }
+            Key::Char('f') => {
+                self.frozen_at = match self.frozen_at {
+                    Some(_) => None,
+                    None => Some(now),
+                };
+            }
+            _ => return false,
+        }
+        let base = self.frozen_at.unwrap_or(now);
+        let history = oldest.map_or(0, |oldest| base.saturating_sub(oldest));
+        self.pan = self.pan.min(history.saturating_sub(self.span()));
+        true
+    }
+
+    /// The timestamp at the right edge of the chart.
+    pub fn end(&self, now: u64) -> u64 {
+        self.frozen_at.unwrap_or(now).saturating_sub(self.pan)
+    }
+
+    pub fn start(&self, now: u64) -> u64 {
+        self.end(now).saturating_sub(self.span())
+    }
+
+    /// Converts samples to chart points, with x in seconds relative to the
+    /// right edge of the window.
+    pub fn points(&self, samples: &[Sample], now: u64) -> Vec<(f64, f64)> {
+        let end = self.end(now) as f64;
+        samples.iter()
+            .map(|s| ((s.timestamp as f64 - end) / 1000.0, s.value))
+            .collect()
+    }
+
+    pub fn x_bounds(&self) -> [f64; 2] {
+        [-(self.span() as f64) / 1000.0, 0.0]
+    }
+
+    /// Evenly spaced axis labels. Windows under an hour are labelled relative
+    /// to now ("-30s"), longer ones with the local clock time ("14:02").
+    pub fn x_labels(&self, now: u64) -> Vec<String> {
+        let end = self.end(now);
+        let step = self.span() / (LABEL_COUNT - 1);
+        (0..LABEL_COUNT).rev()
+            .map(|i| {
+                let at = end.saturating_sub(step * i);
+                if self.span() >= 3_600_000 {
+                    Local.timestamp_millis(at as i64).format("%H:%M").to_string()
+                } else {
+                    format_relative(now.saturating_sub(at))
+                }
+            })
+            .collect()
+    }
+
+    /// Describes the window for the chart title, e.g. "5 min, frozen".
+    pub fn describe(&self) -> String {
+        let mut description = String::from(ZOOM_LEVELS[self.zoom].1);
+        if self.pan > 0 {
+            description.push_str(&format!(", {} back", format_relative(self.pan).trim_start_matches('-')));
+        }
+        if self.frozen_at.is_some() {
+            description.push_str(", frozen");
+        }
+        description
+    }
+}
+
+/// What a chart line measures, which decides the scale of its y axis.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Measure {
+    Percent,
+    /// A size in kB.
+    Kilobytes,
+}
+
+impl Measure {
+
+    /// The title of the y axis.
+    pub fn title(self) -> &'static str {
+        match self {
+            Measure::Percent => "%",
+            Measure::Kilobytes => "Size",
+        }
+    }
+}
+
+/// Y axis bounds and labels for `datasets`. Percentages use a fixed 0-100
+/// scale; sizes scale to a round ceiling above the largest value shown, in
+/// the unit `format` writes it in.
+pub fn y_axis(datasets: &[&[(f64, f64)]], measure: Measure, format: &Format) -> ([f64; 2], Vec<String>) {
+    if measure == Measure::Percent {
+        let labels = ["0", "20", "40", "60", "80", "100"].iter().map(|l| l.to_string()).collect();
+        return ([0.0, 100.0], labels);
+    }
+    let max = datasets.iter()
+        .flat_map(|data| data.iter())
+        .map(|&(_, y)| y)
+        .filter(|y| y.is_finite())
+        .fold(0.0, f64::max);
+    let (scale, suffix) = if format.raw { (1.0, "") } else { format.size_unit(max) };
+    let top = nice_ceiling(max / scale);
+    let labels = (0..=LABEL_COUNT)
+        .map(|i| {
+            let value = (top * i as f64 / LABEL_COUNT as f64 * 10.0).round() / 10.0;
+            if i == 0 || suffix.is_empty() {
+                value.to_string()
+            } else {
+                format!("{} {}", value, suffix)
+            }
+        })
+        .collect();
+    ([0.0, top * scale], labels)
+}
+
+/// Rounds up to 1, 2 or 5 times a power of ten so axis labels stay readable.
+fn nice_ceiling(value: f64) -> f64 {
+    if value <= 0.0 {
+        return 1.0;
+    }
+    let magnitude = 10f64.powf(value.log10().floor());
+    [1.0, 2.0, 5.0, 10.0].iter()
+        .map(|m| m * magnitude)
+        .find(|&candidate| candidate >= value)
+        .unwrap_or(10.0 * magnitude)
+}
+
+fn format_relative(ms: u64) -> String {
+    let seconds = ms / 1000;
+    if seconds == 0 {
+        String::from("now")
+    } else if seconds < 60 {
+        format!("-{}s", seconds)
+    } else if seconds < 3_600 {
+        match seconds % 60 {
+            0 => format!("-{}m", seconds / 60),
+            s => format!("-{}m{}s", seconds / 60, s),
+        }
+    } else {
+        match (seconds % 3_600) / 60 {
+            0 => format!("-{}h", seconds / 3_600),
+            m => format!("-{}h{}m", seconds / 3_600, m),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pan_stops_at_oldest_sample() {
+        let now = 10_000_000;
+        let mut window = ChartWindow::new();
+        window.handle_key(Key::Char('1'), now, Some(now - 90_000));
+        for _ in 0..10 {
+            window.handle_key(Key::Char('['), now, Some(now - 90_000));
+        }
+        assert_eq!(window.start(now), now - 90_000);
+
+        // Zooming out past the history brings the window back to now.
+        window.handle_key(Key::Char('2'), now, Some(now - 90_000));
+        assert_eq!(window.end(now), now);
+
+        window.handle_key(Key::Char('['), now, None);
+        assert_eq!(window.end(now), now);
+    }
+
+    #[test]
+    fn sizes_scale_to_a_round_ceiling() {
+        const GIB: f64 = 1024.0 * 1024.0;
+        let data = [(0.0, 1.5 * GIB), (1.0, 3.7 * GIB)];
+        let (bounds, labels) = y_axis(&[&data], Measure::Kilobytes, &Format::default());
+        assert_eq!(bounds, [0.0, 5.0 * GIB]);
+        assert_eq!(labels, ["0", "1 GiB", "2 GiB", "3 GiB", "4 GiB", "5 GiB"]);
+
+        let data = [(0.0, 130.0)];
+        let (bounds, labels) = y_axis(&[&data], Measure::Kilobytes, &Format::default());
+        assert_eq!(bounds, [0.0, 200.0]);
+        assert_eq!(labels[1], "40 KiB");
+
+        let (bounds, labels) = y_axis(&[&data], Measure::Kilobytes, &Format::raw());
+        assert_eq!((bounds, &labels[5][..]), ([0.0, 200.0], "200"));
+
+        let (bounds, labels) = y_axis(&[&data], Measure::Percent, &Format::default());
+        assert_eq!((bounds, &labels[5][..]), ([0.0, 100.0], "100"));
+        assert_eq!(y_axis(&[], Measure::Kilobytes, &Format::raw()).0, [0.0, 1.0]);
+    }
+}
@@ -0,0 +1,376 @@
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::format::Format;
+use crate::Process;
+
+/// A field of `Process` that can be shown in the process table or used as a
+/// sort key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Column {
+    Pid,
+    Name,
+    State,
+    Priority,
+    Nice,
+    Policy,
+    RtPriority,
+    Affinity,
+    LastCpu,
+    Start,
+    Age,
+    UTime,
+    STime,
+    TotalTime,
+    Rss,
+    MemPercent,
+    CpuPercent,
+}
+
+impl Column {
+
+    pub const ALL: [Column; 17] = [
+        Column::Pid,
+        Column::Name,
+        Column::State,
+        Column::Priority,
+        Column::Nice,
+        Column::Policy,
+        Column::RtPriority,
+        Column::Affinity,
+        Column::LastCpu,
+        Column::Start,
+        Column::Age,
+        Column::UTime,
+        Column::STime,
+        Column::TotalTime,
+        Column::Rss,
+        Column::MemPercent,
+        Column::CpuPercent,
+    ];
+
+    /// The short name used on the command line, e.g. `--sort cpu`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Column::Pid => "pid",
+            Column::Name => "name",
+            Column::State => "state",
+            Column::Priority => "pri",
+            Column::Nice => "ni",
+            Column::Policy => "policy",
+            Column::RtPriority => "rtprio",
+            Column::Affinity => "affinity",
+            Column::LastCpu => "psr",
+            Column::Start => "start",
+            Column::Age => "age",
+            Column::UTime => "utime",
+            Column::STime => "stime",
+            Column::TotalTime => "time",
+            Column::Rss => "rss",
+            Column::MemPercent => "mem",
+            Column::CpuPercent => "cpu",
+        }
+    }
+
+    pub fn title(self) -> &'static str {
+        match self {
+            Column::Pid => "PID",
+            Column::Name => "Process Name",
+            Column::State => "State",
+            Column::Priority => "PRI",
+            Column::Nice => "NI",
+            Column::Policy => "Sched",
+            Column::RtPriority => "RTPRIO",
+            Column::Affinity => "Affinity",
+            Column::LastCpu => "PSR",
+            Column::Start => "Started",
+            Column::Age => "Age",
+            Column::UTime => "UTime",
+            Column::STime => "STime",
+            Column::TotalTime => "Total Time",
+            Column::Rss => "RSS",
+            Column::MemPercent => "Memory %",
+            Column::CpuPercent => "CPU %",
+        }
+    }
+
+    /// Width used when the column is printed as plain text.
+    pub fn width(self) -> usize {
+        match self {
+            Column::Pid => 7,
+            Column::Name => 20,
+            Column::State => 6,
+            Column::Priority | Column::Nice | Column::LastCpu => 4,
+            Column::Policy => 8,
+            Column::RtPriority => 6,
+            Column::Affinity => 10,
+            Column::Start => 8,
+            Column::Age => 9,
+            Column::UTime | Column::STime | Column::TotalTime => 9,
+            Column::Rss => 10,
+            Column::MemPercent | Column::CpuPercent => 9,
+        }
+    }
+
+    pub fn is_numeric(self) -> bool {
+        !matches!(self, Column::Name | Column::State | Column::Policy | Column::Affinity)
+    }
+
+    /// The cell for `process`, with ages as of `now` in ms since the epoch,
+    /// the time the processes were read.
+    pub fn value(self, process: &Process, now: u64, format: &Format) -> String {
+        match self {
+            Column::Pid => process.pid.to_string(),
+            Column::Name => process.process_name.clone(),
+            Column::State => process.state.clone(),
+            Column::Priority => process.priority.to_string(),
+            Column::Nice => process.nice.to_string(),
+            Column::Policy if process.policy.is_realtime() => {
+                format!("{}/{}", process.policy, process.rt_priority)
+            }
+            Column::Policy => process.policy.to_string(),
+            Column::RtPriority => process.rt_priority.to_string(),
+            Column::Affinity => process.cpus_allowed.clone(),
+            Column::LastCpu => process.last_cpu.to_string(),
+            Column::Start if format.raw => process.start_time.to_string(),
+            Column::Start => format_start(process.start_time),
+            Column::Age => format.duration(process.age(now) / 1000),
+            Column::UTime => format.cpu_time(process.utime),
+            Column::STime => format.cpu_time(process.stime),
+            Column::TotalTime => format.cpu_time(process.total_time),
+            Column::Rss => format.kilobytes(process.rss),
+            Column::MemPercent => format.percent(process.mem_percent),
+            Column::CpuPercent => format.percent(process.cpu_percent),
+        }
+    }
+
+    /// Orders two processes by this column. Measurements sort highest first,
+    /// PIDs and text columns in ascending order.
+    pub fn compare(self, a: &Process, b: &Process) -> Ordering {
+        let by_f64 = |x: f64, y: f64| y.partial_cmp(&x).unwrap_or(Ordering::Equal);
+        match self {
+            Column::Pid => a.pid.cmp(&b.pid),
+            Column::Name => a.process_name.cmp(&b.process_name),
+            Column::State => a.state.cmp(&b.state),
+            Column::Priority => b.priority.cmp(&a.priority),
+            Column::Nice => b.nice.cmp(&a.nice),
+            Column::Policy => a.policy.name().cmp(b.policy.name()).then(b.rt_priority.cmp(&a.rt_priority)),
+            Column::RtPriority => b.rt_priority.cmp(&a.rt_priority),
+            Column::Affinity => a.cpus_allowed.cmp(&b.cpus_allowed),
+            Column::LastCpu => a.last_cpu.cmp(&b.last_cpu),
+            // Most recently started first, for both.
+            Column::Start | Column::Age => b.start_time.cmp(&a.start_time),
+            Column::UTime => by_f64(a.utime, b.utime),
+            Column::STime => by_f64(a.stime, b.stime),
+            Column::TotalTime => by_f64(a.total_time, b.total_time),
+            Column::Rss => by_f64(a.rss, b.rss),
+            Column::MemPercent => by_f64(a.mem_percent, b.mem_percent),
+            Column::CpuPercent => by_f64(a.cpu_percent, b.cpu_percent),
+        }
+    }
+}
+
+impl FromStr for Column {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Column, String> {
+        Column::ALL.iter()
+            .cloned()
+            .find(|c| c.name() == s)
+            .ok_or_else(|| format!("unknown column '{}'", s))
+    }
+}
+
+/// A column of the process table and how wide it is drawn, written `name`
+/// or `name:width`, such as `name:24`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ColumnSpec {
+    pub column: Column,
+    /// Fixed width, or None to fit the values shown.
+    pub width: Option<usize>,
+}
+
+impl ColumnSpec {
+
+    pub fn auto(column: Column) -> ColumnSpec {
+        ColumnSpec { column, width: None }
+    }
+
+    /// The fixed width, or the column's usual width when it has none.
+    pub fn width(&self) -> usize {
+        self.width.unwrap_or_else(|| self.column.width())
+    }
+}
+
+impl fmt::Display for ColumnSpec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.width {
+            Some(width) => write!(f, "{}:{}", self.column.name(), width),
+            None => write!(f, "{}", self.column.name()),
+        }
+    }
+}
+
+impl FromStr for ColumnSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ColumnSpec, String> {
+        match s.split_once(':') {
+            Some((name, width)) => {
+                let width = width.trim().parse::<usize>()
+                    .ok()
+                    .filter(|&width| width > 0)
+                    .ok_or_else(|| format!("invalid width '{}' for column '{}'", width, name))?;
+                Ok(ColumnSpec { column: name.trim().parse()?, width: Some(width) })
+            }
+            None => Ok(ColumnSpec::auto(s.trim().parse()?)),
+        }
+    }
+}
+
+impl TryFrom<String> for ColumnSpec {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<ColumnSpec, String> {
+        s.parse()
+    }
+}
+
+impl From<ColumnSpec> for String {
+    fn from(spec: ColumnSpec) -> String {
+        spec.to_string()
+    }
+}
+
+/// The columns shown when none are chosen: all but the real time priority,
+/// which the scheduling column already includes.
+pub fn default_columns() -> Vec<ColumnSpec> {
+    Column::ALL.iter()
+        .filter(|&&column| column != Column::RtPriority)
+        .map(|&column| ColumnSpec::auto(column))
+        .collect()
+}
+
+/// Parses a comma separated list of columns such as `pid,name:24,cpu`.
+pub fn parse_columns(list: &str) -> Result<Vec<ColumnSpec>, String> {
+    let columns = list.split(',')
+        .map(str::parse)
+        .collect::<Result<Vec<ColumnSpec>, String>>()?;
+    check_columns(&columns)?;
+    Ok(columns)
+}
+
+fn check_columns(columns: &[ColumnSpec]) -> Result<(), String> {
+    if columns.is_empty() {
+        return Err(String::from("no columns"));
+    }
+    let mut seen = HashSet::new();
+    for spec in columns {
+        if !seen.insert(spec.column) {
+            return Err(format!("column '{}' is listed twice", spec.column.name()));
+        }
+    }
+    Ok(())
+}
+
+/// Narrowest the name column is squeezed to on small terminals.
+const MIN_NAME_WIDTH: usize = 8;
+
+/// Widths for drawing `columns` across `available` cells, one cell apart.
+/// Columns without a fixed width fit their title and the widest value among
+/// `processes`, with ages as of `now`. When the table is too wide the name
+/// column gives up space first; columns still past the edge are left for the
+/// table to cut off.
+pub fn fit_widths(columns: &[ColumnSpec], processes: &[Process], now: u64, format: &Format, available: usize) -> Vec<usize> {
+    let mut widths: Vec<usize> = columns.iter()
+        .map(|spec| match spec.width {
+            Some(width) => width,
+            None => processes.iter()
+                .map(|process| spec.column.value(process, now, format).chars().count())
+                .fold(spec.column.title().len(), usize::max),
+        })
+        .collect();
+    let total = widths.iter().sum::<usize>() + widths.len().saturating_sub(1);
+    let name = columns.iter().position(|spec| spec.column == Column::Name && spec.width.is_none());
+    if let (true, Some(i)) = (total > available, name) {
+        let floor = MIN_NAME_WIDTH.min(widths[i]);
+        widths[i] = widths[i].saturating_sub(total - available).max(floor);
+    }
+    widths
+}
+
+/// The local time a process started: the time of day if it started today,
+/// otherwise the date.
+fn format_start(start_time: u64) -> String {
+    use chrono::{Local, TimeZone};
+
+    let start = Local.timestamp_millis(start_time as i64);
+    if start.date() == Local::now().date() {
+        start.format("%H:%M:%S").to_string()
+    } else {
+        start.format("%b %d").to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn age_counts_to_the_given_time() {
+        let mut process = Process::new(1, String::from("S"), String::from("init"), 0.0, 0.0, 0.0);
+        process.set_start_time(1_000_000);
+        let format = Format { raw: true, ..Format::default() };
+        assert_eq!(Column::Age.value(&process, 1_090_000, &format), "90");
+        assert_eq!(Column::Age.value(&process, 500_000, &format), "0");
+    }
+
+    fn process(pid: i32, name: &str) -> Process {
+        Process::new(pid, String::from("S"), String::from(name), 0.0, 0.0, 0.0)
+    }
+
+    #[test]
+    fn parses_column_lists() {
+        let columns = parse_columns("pid, name:24,cpu").unwrap();
+        assert_eq!(columns, [
+            ColumnSpec::auto(Column::Pid),
+            ColumnSpec { column: Column::Name, width: Some(24) },
+            ColumnSpec::auto(Column::CpuPercent),
+        ]);
+        let written: Vec<String> = columns.iter().map(ColumnSpec::to_string).collect();
+        assert_eq!(written.join(","), "pid,name:24,cpu");
+
+        assert_eq!(parse_columns("pid,cpu,pid"), Err(String::from("column 'pid' is listed twice")));
+        assert_eq!(parse_columns("pid,bogus"), Err(String::from("unknown column 'bogus'")));
+        assert!(parse_columns("name:0").is_err());
+        assert!(parse_columns("name:wide").is_err());
+        assert!(parse_columns("").is_err());
+        assert!(!default_columns().contains(&ColumnSpec::auto(Column::RtPriority)));
+    }
+
+    #[test]
+    fn fits_widths_to_titles_and_values() {
+        let columns = [
+            ColumnSpec::auto(Column::Pid),
+            ColumnSpec::auto(Column::Name),
+            ColumnSpec { column: Column::State, width: Some(3) },
+        ];
+        let processes = [process(1, "init"), process(123_456_789, "a-rather-long-process-name")];
+        let format = Format::default();
+        assert_eq!(fit_widths(&columns, &processes, 0, &format, 80), [9, 26, 3]);
+        assert_eq!(fit_widths(&columns, &processes[..1], 0, &format, 80), [3, 12, 3]);
+
+        // The name column shrinks to make room, but no further than 8.
+        assert_eq!(fit_widths(&columns, &processes, 0, &format, 30), [9, 16, 3]);
+        assert_eq!(fit_widths(&columns, &processes, 0, &format, 10), [9, 8, 3]);
+        let fixed_name = [ColumnSpec { column: Column::Name, width: Some(30) }];
+        assert_eq!(fit_widths(&fixed_name, &processes, 0, &format, 10), [30]);
+    }
+}
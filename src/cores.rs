@@ -0,0 +1,196 @@
+use std::path::Path;
+
+use system_info::details;
+use system_info::scheduling::{self, SchedPolicy, MAX_RT_PRIORITY};
+use system_info::Process;
+use termion::event::Key;
+
+use crate::ui::Dialog;
+
+/// CPUs shown per row of the picker.
+const CPUS_PER_ROW: usize = 8;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Cores,
+    Policy,
+    RtPriority,
+}
+
+/// Picks the CPUs one process may run on and its scheduling policy.
+pub struct CorePicker {
+    pid: i32,
+    /// Start time of the process when the dialog opened, telling it apart
+    /// from a later one reusing its PID.
+    start: Option<u64>,
+    name: String,
+    last_cpu: i32,
+    cpus: Vec<bool>,
+    cursor: usize,
+    policy: SchedPolicy,
+    rt_priority: u32,
+    /// The settings when the picker opened; only changed ones are applied.
+    original: (Vec<bool>, SchedPolicy, u32),
+    field: Field,
+    error: Option<String>,
+}
+
+impl CorePicker {
+
+    pub fn open(proc_path: &Path, process: &Process) -> CorePicker {
+        let allowed = scheduling::get_affinity(process.pid)
+            .or_else(|_| scheduling::parse_cpu_list(&process.cpus_allowed))
+            .unwrap_or_default();
+        let count = allowed.iter().map(|&cpu| cpu + 1).max().unwrap_or(0).max(scheduling::cpu_count());
+        let cpus: Vec<bool> = (0..count).map(|cpu| allowed.contains(&cpu)).collect();
+        let rt_priority = process.rt_priority.max(1);
+        CorePicker {
+            pid: process.pid,
+            start: details::start_time(proc_path, process.pid),
+            name: process.process_name.clone(),
+            last_cpu: process.last_cpu,
+            original: (cpus.clone(), process.policy, rt_priority),
+            cpus,
+            cursor: 0,
+            policy: process.policy,
+            rt_priority,
+            field: Field::Cores,
+            error: None,
+        }
+    }
+
+    /// Handles a key meant for the picker. Returns false when it should
+    /// close, either cancelled or after the change was applied.
+    pub fn handle_key(&mut self, key: Key, proc_path: &Path) -> bool {
+        match (key, self.field) {
+            (Key::Esc, _) => return false,
+            (Key::Char('\n'), _) => match self.apply(proc_path) {
+                Ok(()) => return false,
+                Err(e) => self.error = Some(e),
+            },
+            (Key::Char('\t'), _) => {
+                self.field = match self.field {
+                    Field::Cores => Field::Policy,
+                    Field::Policy if self.policy.is_realtime() => Field::RtPriority,
+                    _ => Field::Cores,
+                };
+            }
+            (Key::Left, Field::Cores) | (Key::Char('h'), Field::Cores) => {
+                self.cursor = self.cursor.saturating_sub(1);
+            }
+            (Key::Right, Field::Cores) | (Key::Char('l'), Field::Cores) => {
+                self.cursor = (self.cursor + 1).min(self.cpus.len().saturating_sub(1));
+            }
+            (Key::Up, Field::Cores) | (Key::Char('k'), Field::Cores) => {
+                self.cursor = self.cursor.saturating_sub(CPUS_PER_ROW);
+            }
+            (Key::Down, Field::Cores) | (Key::Char('j'), Field::Cores) => {
+                self.cursor = (self.cursor + CPUS_PER_ROW).min(self.cpus.len().saturating_sub(1));
+            }
+            (Key::Char(' '), Field::Cores) => {
+                if let Some(cpu) = self.cpus.get_mut(self.cursor) {
+                    *cpu = !*cpu;
+                }
+            }
+            (Key::Char('a'), Field::Cores) => {
+                let all = self.cpus.iter().all(|&cpu| cpu);
+                self.cpus.iter_mut().for_each(|cpu| *cpu = !all);
+            }
+            (Key::Left, Field::Policy) | (Key::Right, Field::Policy) => {
+                let policies = &SchedPolicy::ALL;
+                let i = policies.iter().position(|&p| p == self.policy).unwrap_or(0);
+                let next = if key == Key::Left { i + policies.len() - 1 } else { i + 1 };
+                self.policy = policies[next % policies.len()];
+            }
+            (Key::Left, Field::RtPriority) | (Key::Char('-'), Field::RtPriority) => {
+                self.rt_priority = self.rt_priority.saturating_sub(1).max(1);
+            }
+            (Key::Right, Field::RtPriority) | (Key::Char('+'), Field::RtPriority) => {
+                self.rt_priority = (self.rt_priority + 1).min(MAX_RT_PRIORITY);
+            }
+            _ => {}
+        }
+        true
+    }
+
+    fn selected(&self) -> Vec<usize> {
+        self.cpus.iter().enumerate().filter(|(_, &on)| on).map(|(cpu, _)| cpu).collect()
+    }
+
+    fn apply(&self, proc_path: &Path) -> Result<(), String> {
+        if self.start.is_none() || details::start_time(proc_path, self.pid) != self.start {
+            return Err(format!("process {} has exited", self.pid));
+        }
+        if self.cpus != self.original.0 {
+            scheduling::set_affinity(self.pid, &self.selected()).map_err(|e| format!("affinity: {}", e))?;
+        }
+        let rt_changed = self.policy.is_realtime() && self.rt_priority != self.original.2;
+        if self.policy != self.original.1 || rt_changed {
+            scheduling::set_policy(self.pid, self.policy, self.rt_priority)
+                .map_err(|e| format!("policy: {}", e))?;
+        }
+        Ok(())
+    }
+
+    pub fn view(&self) -> Dialog {
+        let marker = |field: Field| if self.field == field { ">" } else { " " };
+        let mut lines = vec![format!(
+            "{} CPUs: {}   (last ran on CPU {})",
+            marker(Field::Cores),
+            scheduling::format_cpu_list(&self.selected()),
+            self.last_cpu,
+        )];
+        for (row, cpus) in self.cpus.chunks(CPUS_PER_ROW).enumerate() {
+            let cells: Vec<String> = cpus.iter()
+                .enumerate()
+                .map(|(i, &on)| {
+                    let cpu = row * CPUS_PER_ROW + i;
+                    let (open, close) = if cpu == self.cursor && self.field == Field::Cores { ('<', '>') } else { ('[', ']') };
+                    format!("{}{}{}{:<3}", open, if on { 'x' } else { ' ' }, close, cpu)
+                })
+                .collect();
+            lines.push(format!("    {}", cells.join(" ")));
+        }
+        lines.push(format!("{} Policy: {}", marker(Field::Policy), self.policy));
+        if self.policy.is_realtime() {
+            lines.push(format!("{} RT priority (1 to {}): {}", marker(Field::RtPriority), MAX_RT_PRIORITY, self.rt_priority));
+        }
+        lines.push(String::new());
+        lines.push(String::from("[tab] field [arrows] move/change [space] toggle [a] all"));
+        lines.push(String::from("[enter] apply [esc] cancel"));
+        if let Some(ref error) = self.error {
+            lines.push(error.clone());
+        }
+        Dialog {
+            title: format!("CPU affinity and scheduling of {} ({})", self.pid, self.name),
+            lines,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn refuses_a_reused_pid() {
+        let proc_path = std::env::temp_dir().join(format!("system_info-cores-{}", std::process::id()));
+        let dir = proc_path.join("4242");
+        fs::create_dir_all(&dir).unwrap();
+        let stat = |start: u64| format!("4242 (app) S 1 4242 4242 0 0 0 0 0 0 0 0 0 0 0 20 0 1 0 {} 0 10", start);
+        fs::write(dir.join("stat"), stat(100)).unwrap();
+        let process = Process::new(4242, String::from("S"), String::from("app"), 0.0, 0.0, 0.0);
+        let mut dialog = CorePicker::open(&proc_path, &process);
+        assert_eq!(dialog.start, Some(100));
+
+        fs::write(dir.join("stat"), stat(900)).unwrap();
+        dialog.policy = SchedPolicy::Batch;
+        assert!(dialog.handle_key(Key::Char('\n'), &proc_path));
+        assert_eq!(dialog.error.as_deref(), Some("process 4242 has exited"));
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(dialog.apply(&proc_path).is_err());
+        let _ = fs::remove_dir_all(&proc_path);
+    }
+}
@@ -0,0 +1,208 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::snapshot::Snapshot;
+
+/// One row of the system-level CSV stream. Field order defines the header.
+#[derive(Debug, Serialize)]
+pub struct SystemRow {
+    pub timestamp: u64,
+    pub cpu_percent: f64,
+    pub mem_percent: f64,
+    pub cpu_user: f64,
+    pub cpu_nice: f64,
+    pub cpu_system: f64,
+    pub cpu_idle: f64,
+    pub cpu_iowait: f64,
+    pub cpu_irq: f64,
+    pub cpu_softirq: f64,
+    pub cpu_steal: f64,
+    pub mem_total_kb: f64,
+    pub mem_free_kb: f64,
+    pub mem_available_kb: f64,
+    pub mem_buffers_kb: f64,
+    pub mem_cached_kb: f64,
+}
+
+/// One row of the per-process CSV stream. Field order defines the header.
+#[derive(Debug, Serialize)]
+pub struct ProcessRow<'a> {
+    pub timestamp: u64,
+    pub pid: i32,
+    pub name: &'a str,
+    pub state: &'a str,
+    pub cpu_percent: f64,
+    pub mem_percent: f64,
+    pub rss_kb: f64,
+    pub utime: f64,
+    pub stime: f64,
+    pub total_time: f64,
+    pub priority: i64,
+    pub nice: i64,
+    pub policy: &'a str,
+    pub rt_priority: u32,
+    pub cpus_allowed: &'a str,
+    pub last_cpu: i32,
+    pub start_time: u64,
+}
+
+impl SystemRow {
+
+    pub fn from_snapshot(snapshot: &Snapshot) -> SystemRow {
+        let cpu = snapshot.cpu_breakdown;
+        let mem = snapshot.mem_breakdown;
+        SystemRow {
+            timestamp: snapshot.timestamp,
+            cpu_percent: snapshot.cpu_percent,
+            mem_percent: snapshot.mem_percent,
+            cpu_user: cpu.user,
+            cpu_nice: cpu.nice,
+            cpu_system: cpu.system,
+            cpu_idle: cpu.idle,
+            cpu_iowait: cpu.iowait,
+            cpu_irq: cpu.irq,
+            cpu_softirq: cpu.softirq,
+            cpu_steal: cpu.steal,
+            mem_total_kb: mem.total,
+            mem_free_kb: mem.free,
+            mem_available_kb: mem.available,
+            mem_buffers_kb: mem.buffers,
+            mem_cached_kb: mem.cached,
+        }
+    }
+}
+
+/// Writes snapshots as two CSV streams: one system row per snapshot and one
+/// row per process. Either stream may be left out. Headers are written once,
+/// before the first row.
+pub struct CsvExporter {
+    system: Option<csv::Writer<Box<dyn Write>>>,
+    processes: Option<csv::Writer<Box<dyn Write>>>,
+}
+
+impl CsvExporter {
+
+    pub fn new(system: Option<Box<dyn Write>>, processes: Option<Box<dyn Write>>) -> CsvExporter {
+        CsvExporter {
+            system: system.map(csv::Writer::from_writer),
+            processes: processes.map(csv::Writer::from_writer),
+        }
+    }
+
+    pub fn write_snapshot(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        if let Some(writer) = self.system.as_mut() {
+            writer.serialize(SystemRow::from_snapshot(snapshot))?;
+            writer.flush()?;
+        }
+        if let Some(writer) = self.processes.as_mut() {
+            for process in snapshot.processes.iter() {
+                writer.serialize(ProcessRow {
+                    timestamp: snapshot.timestamp,
+                    pid: process.pid,
+                    name: &process.process_name,
+                    state: &process.state,
+                    cpu_percent: process.cpu_percent,
+                    mem_percent: process.mem_percent,
+                    rss_kb: process.rss,
+                    utime: process.utime,
+                    stime: process.stime,
+                    total_time: process.total_time,
+                    priority: process.priority,
+                    nice: process.nice,
+                    policy: process.policy.name(),
+                    rt_priority: process.rt_priority,
+                    cpus_allowed: &process.cpus_allowed,
+                    last_cpu: process.last_cpu,
+                    start_time: process.start_time,
+                })?;
+            }
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{CpuBreakdown, LoadAverage, MemBreakdown, Process};
+
+    /// A buffer the test can read after handing a writer to the exporter.
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.borrow().clone()).unwrap().lines().map(String::from).collect()
+        }
+    }
+
+    fn snapshot(timestamp: u64) -> Snapshot {
+        let mut process = Process::new(42, String::from("S"), String::from("my \"app\", v2"), 3.0, 4.0, 2048.0);
+        process.cpus_allowed = String::from("0-3");
+        Snapshot {
+            timestamp,
+            cpu_percent: 12.5,
+            mem_percent: 50.0,
+            total_mem: 0.0,
+            cpu_breakdown: CpuBreakdown::default(),
+            mem_breakdown: MemBreakdown::default(),
+            load_average: LoadAverage::default(),
+            processes: vec![process],
+        }
+    }
+
+    #[test]
+    fn writes_each_header_once_and_quotes_names() {
+        let (system, processes) = (Shared::default(), Shared::default());
+        let mut exporter = CsvExporter::new(Some(Box::new(system.clone())), Some(Box::new(processes.clone())));
+        exporter.write_snapshot(&snapshot(1_000)).unwrap();
+        exporter.write_snapshot(&snapshot(2_000)).unwrap();
+
+        let system = system.lines();
+        assert_eq!(system.len(), 3);
+        assert_eq!(
+            system[0],
+            "timestamp,cpu_percent,mem_percent,cpu_user,cpu_nice,cpu_system,cpu_idle,cpu_iowait,\
+             cpu_irq,cpu_softirq,cpu_steal,mem_total_kb,mem_free_kb,mem_available_kb,\
+             mem_buffers_kb,mem_cached_kb",
+        );
+        assert!(system[1].starts_with("1000,12.5,50.0,"));
+        assert!(system[2].starts_with("2000,12.5,50.0,"));
+
+        let processes = processes.lines();
+        assert_eq!(processes.len(), 3);
+        assert_eq!(
+            processes[0],
+            "timestamp,pid,name,state,cpu_percent,mem_percent,rss_kb,utime,stime,total_time,\
+             priority,nice,policy,rt_priority,cpus_allowed,last_cpu,start_time",
+        );
+        assert_eq!(
+            processes[1],
+            "1000,42,\"my \"\"app\"\", v2\",S,0.0,0.0,2048.0,3.0,4.0,7.0,0,0,other,0,0-3,0,0",
+        );
+        assert!(processes[2].starts_with("2000,42,"));
+    }
+
+    #[test]
+    fn leaves_out_streams_without_a_writer() {
+        let processes = Shared::default();
+        let mut exporter = CsvExporter::new(None, Some(Box::new(processes.clone())));
+        exporter.write_snapshot(&snapshot(1_000)).unwrap();
+        assert_eq!(processes.lines().len(), 2);
+    }
+}
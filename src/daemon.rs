@@ -0,0 +1,448 @@
+//! Background collection shared by several clients.
+//!
+//! The daemon samples on its own schedule, publishes every snapshot to a
+//! `SharedState` and answers clients on a Unix domain socket using the
+//! protocol described in `protocol`.
+
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::history::TimeSeries;
+use crate::protocol::{self, Request, Response, PROTOCOL_VERSION};
+use crate::snapshot::Snapshot;
+
+struct State {
+    generation: u64,
+    snapshot: Option<Snapshot>,
+    cpu: TimeSeries,
+    mem: TimeSeries,
+}
+
+/// The latest snapshot and the history built from all earlier ones.
+pub struct SharedState {
+    state: Mutex<State>,
+    updated: Condvar,
+}
+
+impl SharedState {
+
+    pub fn new() -> SharedState {
+        SharedState {
+            state: Mutex::new(State {
+                generation: 0,
+                snapshot: None,
+                cpu: TimeSeries::new(),
+                mem: TimeSeries::new(),
+            }),
+            updated: Condvar::new(),
+        }
+    }
+
+    pub fn publish(&self, snapshot: Snapshot) {
+        let mut state = self.state.lock().unwrap();
+        state.cpu.push(snapshot.timestamp, snapshot.cpu_percent);
+        state.mem.push(snapshot.timestamp, snapshot.mem_percent);
+        state.snapshot = Some(snapshot);
+        state.generation += 1;
+        self.updated.notify_all();
+    }
+
+    pub fn latest(&self) -> Option<Snapshot> {
+        self.state.lock().unwrap().snapshot.clone()
+    }
+
+    pub fn history(&self) -> (TimeSeries, TimeSeries) {
+        let state = self.state.lock().unwrap();
+        (state.cpu.clone(), state.mem.clone())
+    }
+
+    /// Blocks until a snapshot newer than `generation` is published.
+    pub fn wait_for_update(&self, generation: u64) -> (u64, Snapshot) {
+        let mut state = self.state.lock().unwrap();
+        loop {
+            if state.generation > generation {
+                if let Some(snapshot) = state.snapshot.clone() {
+                    return (state.generation, snapshot);
+                }
+            }
+            state = self.updated.wait(state).unwrap();
+        }
+    }
+}
+
+impl Default for SharedState {
+    fn default() -> SharedState {
+        SharedState::new()
+    }
+}
+
+/// Answers one client until it disconnects. When `token` is set the client
+/// must present it before anything else. `authenticated` runs once the
+/// client may send requests.
+pub fn handle_client<R: BufRead, W: Write, F: FnOnce() -> io::Result<()>>(
+    reader: &mut R,
+    writer: &mut W,
+    state: &SharedState,
+    token: Option<&str>,
+    authenticated: F,
+) -> io::Result<()> {
+    protocol::write_message(writer, &Response::Hello {
+        version: PROTOCOL_VERSION,
+        auth_required: token.is_some(),
+    })?;
+    if let Some(token) = token {
+        match protocol::read_message::<_, Request>(reader) {
+            Ok(Some(Request::Auth { token: ref given })) if tokens_match(given, token) => {
+                protocol::write_message(writer, &Response::Authenticated)?;
+            }
+            Ok(None) => return Ok(()),
+            _ => {
+                let message = String::from("authentication failed");
+                protocol::write_message(writer, &Response::Error { message })?;
+                return Err(io::Error::new(io::ErrorKind::PermissionDenied, "client failed to authenticate"));
+            }
+        }
+    }
+    authenticated()?;
+    loop {
+        let request = match protocol::read_message::<_, Request>(reader) {
+            Ok(Some(request)) => request,
+            Ok(None) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
+                protocol::write_message(writer, &Response::Error { message: e.to_string() })?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+        match request {
+            Request::Auth { .. } => {
+                let message = String::from("already authenticated");
+                protocol::write_message(writer, &Response::Error { message })?;
+            }
+            Request::Snapshot => {
+                let response = match state.latest() {
+                    Some(snapshot) => Response::Snapshot { snapshot: Box::new(snapshot) },
+                    None => Response::Error { message: String::from("no snapshot collected yet") },
+                };
+                protocol::write_message(writer, &response)?;
+            }
+            Request::History => {
+                let (cpu, mem) = state.history();
+                protocol::write_message(writer, &Response::History { cpu, mem })?;
+            }
+            Request::Subscribe => {
+                let mut generation = 0;
+                loop {
+                    let (latest, snapshot) = state.wait_for_update(generation);
+                    generation = latest;
+                    protocol::write_message(writer, &Response::Snapshot { snapshot: Box::new(snapshot) })?;
+                }
+            }
+        }
+    }
+}
+
+/// Accepts clients on `listener`, each on its own thread.
+pub fn serve(listener: UnixListener, state: Arc<SharedState>) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let stream = stream?;
+        spawn_client(stream.try_clone()?, stream, Arc::clone(&state), None, || Ok(()));
+    }
+    Ok(())
+}
+
+/// Answers a client on its own thread, reporting errors other than the
+/// client going away.
+pub fn spawn_client<R, W, F>(reader: R, mut writer: W, state: Arc<SharedState>, token: Option<String>, authenticated: F)
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+    F: FnOnce() -> io::Result<()> + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut reader = BufReader::new(reader);
+        if let Err(e) = handle_client(&mut reader, &mut writer, &state, token.as_deref(), authenticated) {
+            match e.kind() {
+                io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset => {}
+                _ => eprintln!("Client error: {}", e),
+            }
+        }
+    });
+}
+
+/// Compares tokens without stopping at the first difference.
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given.bytes().zip(expected.bytes()).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// Binds the daemon socket, replacing a stale socket file left by a daemon
+/// that is no longer running.
+pub fn bind(path: &Path) -> io::Result<UnixListener> {
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("a daemon is already listening on {}", path.display()),
+            ));
+        }
+        fs::remove_file(path)?;
+    }
+    UnixListener::bind(path)
+}
+
+/// `$XDG_RUNTIME_DIR/system_info.sock`, or a per-user path under `/tmp`.
+pub fn default_socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => Path::new(&dir).join("system_info.sock"),
+        None => PathBuf::from(format!("/tmp/system_info-{}.sock", env::var("USER").unwrap_or_default())),
+    }
+}
+
+/// The client side of the protocol.
+pub struct Client<R, W> {
+    reader: BufReader<R>,
+    writer: W,
+}
+
+impl Client<UnixStream, UnixStream> {
+
+    pub fn connect(path: &Path) -> io::Result<Client<UnixStream, UnixStream>> {
+        let stream = UnixStream::connect(path)?;
+        Client::new(stream.try_clone()?, stream, None)
+    }
+}
+
+impl<R: Read, W: Write> Client<R, W> {
+
+    /// Wraps a connection, checks the server's protocol version and
+    /// presents `token` if the server asks for one.
+    pub fn new(reader: R, writer: W, token: Option<&str>) -> io::Result<Client<R, W>> {
+        let mut client = Client {
+            reader: BufReader::new(reader),
+            writer,
+        };
+        let auth_required = match client.receive()? {
+            Response::Hello { version, auth_required } if version == PROTOCOL_VERSION => auth_required,
+            Response::Hello { version, .. } => return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("server speaks protocol version {}, expected {}", version, PROTOCOL_VERSION),
+            )),
+            _ => return Err(unexpected_response()),
+        };
+        if auth_required {
+            let token = token.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::PermissionDenied, "server requires a token")
+            })?;
+            client.send(&Request::Auth { token: token.to_string() })?;
+            match client.receive() {
+                Ok(Response::Authenticated) => {}
+                Ok(_) => return Err(unexpected_response()),
+                Err(e) => return Err(io::Error::new(io::ErrorKind::PermissionDenied, e.to_string())),
+            }
+        }
+        Ok(client)
+    }
+
+    pub fn snapshot(&mut self) -> io::Result<Snapshot> {
+        self.send(&Request::Snapshot)?;
+        match self.receive()? {
+            Response::Snapshot { snapshot } => Ok(*snapshot),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    pub fn history(&mut self) -> io::Result<(TimeSeries, TimeSeries)> {
+        self.send(&Request::History)?;
+        match self.receive()? {
+            Response::History { cpu, mem } => Ok((cpu, mem)),
+            _ => Err(unexpected_response()),
+        }
+    }
+
+    /// Asks for every future snapshot. The connection can only be used to
+    /// read snapshots afterwards.
+    pub fn subscribe(mut self) -> io::Result<Subscription<R>> {
+        self.send(&Request::Subscribe)?;
+        Ok(Subscription {
+            reader: self.reader,
+        })
+    }
+
+    pub fn send(&mut self, request: &Request) -> io::Result<()> {
+        protocol::write_message(&mut self.writer, request)
+    }
+
+    /// Reads the next response, turning protocol errors into `io::Error`s.
+    pub fn receive(&mut self) -> io::Result<Response> {
+        match protocol::read_message(&mut self.reader)? {
+            Some(Response::Error { message }) => Err(io::Error::other(message)),
+            Some(response) => Ok(response),
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "server closed the connection")),
+        }
+    }
+}
+
+/// Snapshots pushed by the server after a `subscribe` request.
+pub struct Subscription<R> {
+    reader: BufReader<R>,
+}
+
+impl<R: Read> Iterator for Subscription<R> {
+    type Item = io::Result<Snapshot>;
+
+    fn next(&mut self) -> Option<io::Result<Snapshot>> {
+        match protocol::read_message(&mut self.reader) {
+            Ok(Some(Response::Snapshot { snapshot })) => Some(Ok(*snapshot)),
+            Ok(Some(Response::Error { message })) => Some(Err(io::Error::other(message))),
+            Ok(Some(_)) => Some(Err(unexpected_response())),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// What a watched server reports to `watch`.
+#[derive(Debug)]
+pub enum Update {
+    /// A (re)connection succeeded; carries the server's history and latest
+    /// snapshot so the client can replace what it had.
+    Connected {
+        cpu: TimeSeries,
+        mem: TimeSeries,
+        snapshot: Box<Snapshot>,
+    },
+    Snapshot(Box<Snapshot>),
+    /// The connection failed or was lost; `watch` retries after `retry_in`.
+    Disconnected {
+        error: io::Error,
+        retry_in: Duration,
+    },
+}
+
+/// First and longest wait between reconnection attempts.
+pub const MIN_RETRY_DELAY: Duration = Duration::from_secs(1);
+pub const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Follows a server on a background thread, reconnecting with exponential
+/// backoff whenever the connection fails. Stops once the receiver is dropped.
+pub fn watch<R, W, F>(connect: F) -> Receiver<Update>
+where
+    R: Read + Send + 'static,
+    W: Write + Send + 'static,
+    F: Fn() -> io::Result<Client<R, W>> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut retry_in = MIN_RETRY_DELAY;
+        loop {
+            let error = match follow(&connect, &tx, &mut retry_in) {
+                Ok(()) => return,
+                Err(error) => error,
+            };
+            if tx.send(Update::Disconnected { error, retry_in }).is_err() {
+                return;
+            }
+            thread::sleep(retry_in);
+            retry_in = (retry_in * 2).min(MAX_RETRY_DELAY);
+        }
+    });
+    rx
+}
+
+/// Streams updates from one connection. Returns `Ok` only when the receiver
+/// has gone away.
+fn follow<R, W, F>(connect: &F, tx: &Sender<Update>, retry_in: &mut Duration) -> io::Result<()>
+where
+    R: Read,
+    W: Write,
+    F: Fn() -> io::Result<Client<R, W>>,
+{
+    let mut client = connect()?;
+    let (cpu, mem) = client.history()?;
+    let snapshot = Box::new(client.snapshot()?);
+    if tx.send(Update::Connected { cpu, mem, snapshot }).is_err() {
+        return Ok(());
+    }
+    *retry_in = MIN_RETRY_DELAY;
+    for snapshot in client.subscribe()? {
+        if tx.send(Update::Snapshot(Box::new(snapshot?))).is_err() {
+            return Ok(());
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::UnexpectedEof, "server closed the connection"))
+}
+
+fn unexpected_response() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "unexpected response from server")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CpuBreakdown, LoadAverage, MemBreakdown};
+
+    fn snapshot(timestamp: u64) -> Snapshot {
+        Snapshot {
+            timestamp,
+            cpu_percent: 25.0,
+            mem_percent: 40.0,
+            total_mem: 1024.0,
+            cpu_breakdown: CpuBreakdown::default(),
+            mem_breakdown: MemBreakdown::default(),
+            load_average: LoadAverage::default(),
+            processes: vec![],
+        }
+    }
+
+    fn responses(written: &[u8]) -> Vec<Response> {
+        let mut reader = written;
+        let mut responses = vec![];
+        while let Some(response) = protocol::read_message(&mut reader).unwrap() {
+            responses.push(response);
+        }
+        responses
+    }
+
+    /// Fails every read as a dropped connection would.
+    struct Broken;
+
+    impl Read for Broken {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::new(io::ErrorKind::ConnectionReset, "connection reset"))
+        }
+    }
+
+    #[test]
+    fn answers_malformed_requests_and_carries_on() {
+        let state = SharedState::new();
+        state.publish(snapshot(1_000));
+        let mut reader: &[u8] = b"not json\n{\"type\":\"snapshot\"}\n";
+        let mut written = vec![];
+        handle_client(&mut reader, &mut written, &state, None, || Ok(())).unwrap();
+        match responses(&written).as_slice() {
+            [Response::Hello { version, auth_required: false }, Response::Error { .. }, Response::Snapshot { snapshot }] => {
+                assert_eq!(*version, PROTOCOL_VERSION);
+                assert_eq!(snapshot.timestamp, 1_000);
+            }
+            other => panic!("unexpected responses {:?}", other),
+        }
+    }
+
+    #[test]
+    fn read_errors_end_the_client() {
+        let state = SharedState::new();
+        let mut reader = BufReader::new(Broken);
+        let mut written = vec![];
+        let error = handle_client(&mut reader, &mut written, &state, None, || Ok(())).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionReset);
+        assert_eq!(responses(&written).len(), 1);
+    }
+}
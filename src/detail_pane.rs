@@ -0,0 +1,181 @@
+use std::collections::VecDeque;
+use std::path::Path;
+
+use chrono::{Local, TimeZone};
+use system_info::details::ProcessDetails;
+use system_info::format::Format;
+use system_info::leaks::RssHistory;
+use system_info::Process;
+use termion::event::Key;
+
+use crate::ui::DetailView;
+
+/// Samples kept for the detail pane sparklines.
+const SPARKLINE_POINTS: usize = 300;
+
+/// The full-screen view of one process, refreshed every tick.
+pub struct DetailPane {
+    pid: i32,
+    name: String,
+    show_environ: bool,
+    scroll: u16,
+    cpu: VecDeque<u64>,
+    rss: VecDeque<u64>,
+    details: Option<ProcessDetails>,
+}
+
+impl DetailPane {
+
+    /// Opens the pane for `process`, seeding the RSS sparkline from what
+    /// `ProcessInfo` already recorded.
+    pub fn open(proc_path: &Path, process: &Process, rss_history: &RssHistory) -> DetailPane {
+        let rss = rss_history.samples(process.pid)
+            .map(|samples| samples.iter().map(|s| s.value as u64).collect())
+            .unwrap_or_default();
+        let mut pane = DetailPane {
+            pid: process.pid,
+            name: process.process_name.clone(),
+            show_environ: false,
+            scroll: 0,
+            cpu: VecDeque::new(),
+            rss,
+            details: None,
+        };
+        pane.refresh(proc_path, std::slice::from_ref(process));
+        pane
+    }
+
+    /// Re-reads the details and adds the process's latest CPU and RSS.
+    pub fn refresh(&mut self, proc_path: &Path, processes: &[Process]) {
+        self.details = ProcessDetails::read(proc_path, self.pid, self.show_environ).ok();
+        if let Some(process) = processes.iter().find(|p| p.pid == self.pid) {
+            push_bounded(&mut self.cpu, process.cpu_percent.max(0.0).round() as u64);
+            push_bounded(&mut self.rss, process.rss as u64);
+        }
+    }
+
+    /// Handles a key meant for the pane. Returns false when the pane should
+    /// close.
+    pub fn handle_key(&mut self, key: Key, proc_path: &Path) -> bool {
+        match key {
+            Key::Esc | Key::Backspace | Key::Left => return false,
+            Key::Up | Key::Char('k') => self.scroll = self.scroll.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.scroll = self.scroll.saturating_add(1),
+            Key::PageUp => self.scroll = self.scroll.saturating_sub(10),
+            Key::PageDown => self.scroll = self.scroll.saturating_add(10),
+            Key::Char('e') => {
+                self.show_environ = !self.show_environ;
+                self.details = ProcessDetails::read(proc_path, self.pid, self.show_environ).ok();
+            }
+            _ => {}
+        }
+        true
+    }
+
+    pub fn view(&self, format: &Format) -> DetailView {
+        let cpu_now = self.cpu.back().copied().unwrap_or(0);
+        let rss_now = self.rss.back().copied().unwrap_or(0);
+        DetailView {
+            title: format!(
+                "Process {} ({}) [up/down] scroll [e] environment [esc] back",
+                self.pid, self.name,
+            ),
+            cpu_title: format!("CPU {}%", cpu_now),
+            cpu: self.cpu.iter().copied().collect(),
+            rss_title: format!("RSS {}", format.kilobytes(rss_now as f64)),
+            rss: self.rss.iter().copied().collect(),
+            lines: match self.details {
+                Some(ref details) => detail_lines(details, self.show_environ, format),
+                None => vec![String::from("The process has exited.")],
+            },
+            scroll: self.scroll,
+        }
+    }
+}
+
+fn push_bounded(values: &mut VecDeque<u64>, value: u64) {
+    if values.len() == SPARKLINE_POINTS {
+        values.pop_front();
+    }
+    values.push_back(value);
+}
+
+fn detail_lines(details: &ProcessDetails, show_environ: bool, format: &Format) -> Vec<String> {
+    let unknown = || String::from("?");
+    let path = |path: &Option<std::path::PathBuf>| {
+        path.as_ref().map_or_else(unknown, |p| p.display().to_string())
+    };
+    let number = |value: Option<i64>| value.map_or_else(unknown, |v| v.to_string());
+    let mut lines = vec![
+        format!("Command line:  {}", if details.cmdline.is_empty() {
+            format!("[{}]", details.name)
+        } else {
+            details.cmdline.join(" ")
+        }),
+        format!("Executable:    {}", path(&details.exe)),
+        format!("Working dir:   {}", path(&details.cwd)),
+        format!("User:          {} ({})",
+            details.user.clone().unwrap_or_else(unknown),
+            details.uid.map_or_else(unknown, |uid| uid.to_string())),
+        format!("Started:       {}", details.start_time.map_or_else(unknown, |ms| {
+            Local.timestamp_millis(ms as i64).format("%Y-%m-%d %H:%M:%S").to_string()
+        })),
+        format!("Parents:       {}", details.parents.iter()
+            .map(|(pid, name)| format!("{} ({})", pid, name))
+            .collect::<Vec<String>>()
+            .join(" < ")),
+        format!("Threads:       {}", details.threads.map_or_else(unknown, |t| t.to_string())),
+        format!("Nice/priority: {} / {}", number(details.nice), number(details.priority)),
+        format!("CPU affinity:  {}", details.cpus_allowed.clone().unwrap_or_else(unknown)),
+    ];
+    lines.push(String::new());
+    lines.push(String::from("Cgroups:"));
+    lines.extend(details.cgroups.iter().map(|cgroup| format!("  {}", cgroup)));
+
+    lines.push(String::new());
+    lines.push(String::from("I/O:"));
+    if details.io.is_empty() {
+        lines.push(String::from("  (unreadable)"));
+    }
+    lines.extend(details.io.iter().map(|(key, value)| format!("  {:<24} {}", key, value)));
+
+    lines.push(String::new());
+    match details.fds {
+        Some(ref fds) => lines.push(format!(
+            "Open fds:      {} ({} files, {} sockets, {} pipes, {} devices, {} anon inodes)",
+            fds.total, fds.files, fds.sockets, fds.pipes, fds.devices, fds.anon_inodes,
+        )),
+        None => lines.push(String::from("Open fds:      (unreadable)")),
+    }
+    match details.maps {
+        Some(ref maps) => {
+            lines.push(format!(
+                "Memory maps:   {} regions, {} file backed, {} anonymous, {} heap, {} stack",
+                maps.regions,
+                format.kilobytes(maps.file_backed as f64),
+                format.kilobytes(maps.anonymous as f64),
+                format.kilobytes(maps.heap as f64),
+                format.kilobytes(maps.stack as f64),
+            ));
+            lines.extend(maps.largest_files.iter().map(|(file, kb)| format!("  {:>10}  {}", format.kilobytes(*kb as f64), file)));
+        }
+        None => lines.push(String::from("Memory maps:   (unreadable)")),
+    }
+
+    lines.push(String::new());
+    lines.push(format!("{:<26} {:<20} {:<20} {}", "Limit", "Soft", "Hard", "Units"));
+    lines.extend(details.limits.iter().map(|limit| {
+        format!("{:<26} {:<20} {:<20} {}", limit.name, limit.soft, limit.hard, limit.units)
+    }));
+
+    lines.push(String::new());
+    match (show_environ, &details.environ) {
+        (false, _) => lines.push(String::from("Environment:   hidden, press [e] to show")),
+        (true, None) => lines.push(String::from("Environment:   (unreadable)")),
+        (true, Some(environ)) => {
+            lines.push(String::from("Environment:"));
+            lines.extend(environ.iter().map(|variable| format!("  {}", variable)));
+        }
+    }
+    lines
+}
@@ -0,0 +1,313 @@
+//! Everything `/proc` tells about one process, for the detail pane.
+//!
+//! Each part is read independently and left empty when the kernel does not
+//! let us read it, which is common for other users' processes.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::users;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Limit {
+    pub name: String,
+    pub soft: String,
+    pub hard: String,
+    pub units: String,
+}
+
+/// Sizes in kB of the mappings in `/proc/[pid]/maps`, by kind.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MapsSummary {
+    pub regions: usize,
+    pub file_backed: u64,
+    pub anonymous: u64,
+    pub heap: u64,
+    pub stack: u64,
+    /// Mapped files by total mapped size, largest first.
+    pub largest_files: Vec<(String, u64)>,
+}
+
+/// Open file descriptors by what they point at.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FdSummary {
+    pub total: usize,
+    pub files: usize,
+    pub sockets: usize,
+    pub pipes: usize,
+    pub anon_inodes: usize,
+    pub devices: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProcessDetails {
+    pub pid: i32,
+    pub name: String,
+    pub cmdline: Vec<String>,
+    pub cwd: Option<PathBuf>,
+    pub exe: Option<PathBuf>,
+    /// Only read when asked for, since it often holds secrets.
+    pub environ: Option<Vec<String>>,
+    /// Start time in ms since the Unix epoch.
+    pub start_time: Option<u64>,
+    /// The parent, its parent and so on up to init, as `(pid, name)`.
+    pub parents: Vec<(i32, String)>,
+    pub uid: Option<u32>,
+    pub user: Option<String>,
+    pub threads: Option<u64>,
+    pub nice: Option<i64>,
+    pub priority: Option<i64>,
+    pub cpus_allowed: Option<String>,
+    pub cgroups: Vec<String>,
+    pub limits: Vec<Limit>,
+    pub maps: Option<MapsSummary>,
+    pub fds: Option<FdSummary>,
+    /// `/proc/[pid]/io` counters by name.
+    pub io: BTreeMap<String, u64>,
+}
+
+/// Mapped files listed in `MapsSummary::largest_files`.
+const LARGEST_FILES: usize = 5;
+
+impl ProcessDetails {
+
+    /// Reads the details of `pid`. Fails only if the process does not exist.
+    pub fn read(proc_path: &Path, pid: i32, include_environ: bool) -> io::Result<ProcessDetails> {
+        let dir = proc_path.join(pid.to_string());
+        let stat = StatFields::read(&dir)?;
+        let status = fs::read_to_string(dir.join("status")).unwrap_or_default();
+        let uid = status_value(&status, "Uid").and_then(|v| v.split_whitespace().next()?.parse().ok());
+        Ok(ProcessDetails {
+            pid,
+            name: stat.name.clone(),
+            cmdline: read_nul_separated(&dir.join("cmdline")).unwrap_or_default(),
+            cwd: fs::read_link(dir.join("cwd")).ok(),
+            exe: fs::read_link(dir.join("exe")).ok(),
+            environ: if include_environ { read_nul_separated(&dir.join("environ")) } else { None },
+            start_time: stat.field(22).and_then(|ticks| ticks_to_epoch_millis(ticks.parse().ok()?)),
+            parents: read_parents(proc_path, &stat),
+            uid,
+            user: uid.and_then(users::user_name),
+            threads: status_value(&status, "Threads").and_then(|v| v.parse().ok()),
+            nice: stat.field(19).and_then(|v| v.parse().ok()),
+            priority: stat.field(18).and_then(|v| v.parse().ok()),
+            cpus_allowed: status_value(&status, "Cpus_allowed_list").map(String::from),
+            cgroups: fs::read_to_string(dir.join("cgroup"))
+                .map(|text| text.lines().map(String::from).collect())
+                .unwrap_or_default(),
+            limits: fs::read_to_string(dir.join("limits")).map(|text| parse_limits(&text)).unwrap_or_default(),
+            maps: fs::read_to_string(dir.join("maps")).ok().map(|text| summarize_maps(&text)),
+            fds: summarize_fds(&dir.join("fd")),
+            io: fs::read_to_string(dir.join("io")).map(|text| parse_io(&text)).unwrap_or_default(),
+        })
+    }
+}
+
+/// The fields of `/proc/[pid]/stat`, numbered from 1 as in proc(5).
+pub struct StatFields {
+    pub pid: i32,
+    pub name: String,
+    /// Fields 3 onwards, after the parenthesised name.
+    rest: Vec<String>,
+}
+
+impl StatFields {
+
+    pub fn read(dir: &Path) -> io::Result<StatFields> {
+        StatFields::parse(&fs::read_to_string(dir.join("stat"))?)
+    }
+
+    /// Splits `stat`, taking the name from between the first `(` and the
+    /// last `)` since it may contain spaces and parentheses.
+    pub fn parse(stat: &str) -> io::Result<StatFields> {
+        let malformed = || io::Error::new(io::ErrorKind::InvalidData, "malformed stat");
+        let open = stat.find('(').ok_or_else(malformed)?;
+        let close = stat.rfind(')').ok_or_else(malformed)?;
+        Ok(StatFields {
+            pid: stat[..open].trim().parse().map_err(|_| malformed())?,
+            name: stat.get(open + 1..close).ok_or_else(malformed)?.to_string(),
+            rest: stat[close + 1..].split_whitespace().map(String::from).collect(),
+        })
+    }
+
+    /// Field `number` (3 or more) as text.
+    pub fn field(&self, number: usize) -> Option<&str> {
+        self.rest.get(number.checked_sub(3)?).map(String::as_str)
+    }
+
+    /// Field `number` (3 or more) parsed as a `T`.
+    pub fn value<T: FromStr>(&self, number: usize) -> io::Result<T> {
+        self.field(number)
+            .and_then(|field| field.parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("malformed stat field {}", number)))
+    }
+}
+
+/// The start time of `pid` in clock ticks after boot, field 22 of `stat`,
+/// which tells a process apart from a later one reusing its PID. None when
+/// no process has that PID.
+pub fn start_time(proc_path: &Path, pid: i32) -> Option<u64> {
+    StatFields::read(&proc_path.join(pid.to_string())).ok()?.value(22).ok()
+}
+
+/// Clock ticks per second, the unit of the times in `stat`.
+pub fn clock_ticks_per_second() -> u64 {
+    // SAFETY: sysconf has no preconditions.
+    let ticks = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+    if ticks > 0 { ticks as u64 } else { 100 }
+}
+
+/// Boot time in seconds since the Unix epoch, the `btime` of `/proc/stat`.
+pub fn boot_time() -> Option<u64> {
+    let stat = fs::read_to_string("/proc/stat").ok()?;
+    stat.lines()
+        .find(|line| line.starts_with("btime"))?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+/// Converts clock ticks since boot to ms since the Unix epoch.
+pub fn ticks_to_epoch_millis(ticks: u64) -> Option<u64> {
+    Some(boot_time()? * 1000 + ticks * 1000 / clock_ticks_per_second())
+}
+
+fn read_parents(proc_path: &Path, stat: &StatFields) -> Vec<(i32, String)> {
+    let mut parents = vec![];
+    let mut ppid: i32 = stat.field(4).and_then(|v| v.parse().ok()).unwrap_or(0);
+    while ppid > 0 && parents.len() < 64 {
+        match StatFields::read(&proc_path.join(ppid.to_string())) {
+            Ok(parent) => {
+                parents.push((ppid, parent.name.clone()));
+                ppid = parent.field(4).and_then(|v| v.parse().ok()).unwrap_or(0);
+            }
+            Err(_) => break,
+        }
+    }
+    parents
+}
+
+fn read_nul_separated(path: &Path) -> Option<Vec<String>> {
+    let bytes = fs::read(path).ok()?;
+    Some(bytes.split(|&b| b == 0)
+        .filter(|part| !part.is_empty())
+        .map(|part| String::from_utf8_lossy(part).into_owned())
+        .collect())
+}
+
+fn status_value<'a>(status: &'a str, key: &str) -> Option<&'a str> {
+    status.lines()
+        .filter_map(|line| line.split_once(':'))
+        .find(|(name, _)| *name == key)
+        .map(|(_, value)| value.trim())
+}
+
+/// Splits `/proc/[pid]/limits`, whose columns line up with its header.
+fn parse_limits(text: &str) -> Vec<Limit> {
+    let mut lines = text.lines();
+    let header = match lines.next() {
+        Some(header) => header,
+        None => return vec![],
+    };
+    let column = |title: &str| header.find(title).unwrap_or(header.len());
+    let (soft, hard, units) = (column("Soft Limit"), column("Hard Limit"), column("Units"));
+    let cell = |line: &str, start: usize, end: usize| {
+        line.get(start..end.min(line.len())).unwrap_or("").trim().to_string()
+    };
+    lines
+        .map(|line| Limit {
+            name: cell(line, 0, soft),
+            soft: cell(line, soft, hard),
+            hard: cell(line, hard, units),
+            units: cell(line, units, line.len()),
+        })
+        .collect()
+}
+
+fn summarize_maps(text: &str) -> MapsSummary {
+    let mut summary = MapsSummary::default();
+    let mut files: BTreeMap<String, u64> = BTreeMap::new();
+    for line in text.lines() {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let size = fields.first()
+            .and_then(|range| {
+                let mut bounds = range.split('-').map(|b| u64::from_str_radix(b, 16));
+                match (bounds.next(), bounds.next()) {
+                    (Some(Ok(start)), Some(Ok(end))) => Some((end - start) / 1024),
+                    _ => None,
+                }
+            })
+            .unwrap_or(0);
+        summary.regions += 1;
+        match fields.get(5) {
+            Some(&"[heap]") => summary.heap += size,
+            Some(path) if path.starts_with("[stack") => summary.stack += size,
+            Some(path) if path.starts_with('/') => {
+                summary.file_backed += size;
+                *files.entry(path.to_string()).or_insert(0) += size;
+            }
+            _ => summary.anonymous += size,
+        }
+    }
+    let mut largest: Vec<(String, u64)> = files.into_iter().collect();
+    largest.sort_by_key(|(_, size)| Reverse(*size));
+    largest.truncate(LARGEST_FILES);
+    summary.largest_files = largest;
+    summary
+}
+
+fn summarize_fds(dir: &Path) -> Option<FdSummary> {
+    let mut summary = FdSummary::default();
+    for entry in fs::read_dir(dir).ok()? {
+        let target = match entry.ok().and_then(|e| fs::read_link(e.path()).ok()) {
+            Some(target) => target.to_string_lossy().into_owned(),
+            None => continue,
+        };
+        summary.total += 1;
+        if target.starts_with("socket:") {
+            summary.sockets += 1;
+        } else if target.starts_with("pipe:") {
+            summary.pipes += 1;
+        } else if target.starts_with("anon_inode:") {
+            summary.anon_inodes += 1;
+        } else if target.starts_with("/dev/") {
+            summary.devices += 1;
+        } else {
+            summary.files += 1;
+        }
+    }
+    Some(summary)
+}
+
+fn parse_io(text: &str) -> BTreeMap<String, u64> {
+    text.lines()
+        .filter_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            Some((key.trim().to_string(), value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stat_fields_survive_names_with_spaces() {
+        let stat = StatFields::parse("4242 (tmux: server (1)) S 1 4242 4242 0 -1 4194560 90 0 0 0 7 3 0 0 20 -5 1").unwrap();
+        assert_eq!(stat.pid, 4242);
+        assert_eq!(stat.name, "tmux: server (1)");
+        assert_eq!(stat.field(3), Some("S"));
+        assert_eq!(stat.value::<u64>(14).unwrap(), 7);
+        assert_eq!(stat.value::<i64>(19).unwrap(), -5);
+        assert!(stat.value::<u64>(22).is_err());
+        assert!(StatFields::parse("No such file or directory (os error 2)").is_err());
+    }
+}
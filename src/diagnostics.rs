@@ -0,0 +1,174 @@
+//! Diagnostics for zombies and processes in uninterruptible sleep.
+//!
+//! `ProcessInfo` remembers since when each process has been in its current
+//! state in a `StateHistory`. `diagnose` lists zombies with the parent that
+//! has yet to reap them, and `D` state processes with the kernel function
+//! they wait in and, where readable, their kernel stack.
+
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::details::StatFields;
+use crate::Process;
+
+pub const ZOMBIE: &str = "Z";
+pub const UNINTERRUPTIBLE: &str = "D";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StateEntry {
+    state: String,
+    since: u64,
+}
+
+/// When each running process entered its current state.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct StateHistory {
+    entries: HashMap<i32, StateEntry>,
+}
+
+impl StateHistory {
+
+    pub fn new() -> StateHistory {
+        StateHistory::default()
+    }
+
+    /// Notes the state of `processes` at `timestamp` and forgets processes
+    /// that exited.
+    pub fn record(&mut self, timestamp: u64, processes: &[Process]) {
+        let running: HashSet<i32> = processes.iter().map(|p| p.pid).collect();
+        self.entries.retain(|pid, _| running.contains(pid));
+        for process in processes {
+            let entry = self.entries.entry(process.pid).or_insert_with(|| StateEntry {
+                state: process.state.clone(),
+                since: timestamp,
+            });
+            if entry.state != process.state {
+                entry.state = process.state.clone();
+                entry.since = timestamp;
+            }
+        }
+    }
+
+    /// When `pid` entered its current state, as far as has been observed.
+    pub fn since(&self, pid: i32) -> Option<u64> {
+        self.entries.get(&pid).map(|entry| entry.since)
+    }
+}
+
+/// A zombie or `D` state process and what is known about why.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Diagnosis {
+    pub pid: i32,
+    pub name: String,
+    pub state: String,
+    /// How long the process has been observed in this state, in ms.
+    pub duration: u64,
+    pub parent_pid: Option<i32>,
+    pub parent_name: Option<String>,
+    /// Kernel function the process sleeps in, from `/proc/[pid]/wchan`.
+    pub wchan: Option<String>,
+    /// Kernel stack frames, innermost first; usually only readable by root.
+    pub stack: Option<Vec<String>>,
+}
+
+/// Diagnoses every zombie and `D` state process in `processes`, longest
+/// stuck first.
+pub fn diagnose(proc_path: &Path, processes: &[Process], states: &StateHistory, now: u64) -> Vec<Diagnosis> {
+    let mut diagnoses: Vec<Diagnosis> = processes.iter()
+        .filter(|p| p.state == ZOMBIE || p.state == UNINTERRUPTIBLE)
+        .map(|process| {
+            let dir = proc_path.join(process.pid.to_string());
+            let parent_pid = read_parent_pid(&dir);
+            let parent_name = parent_pid.and_then(|ppid| {
+                processes.iter()
+                    .find(|p| p.pid == ppid)
+                    .map(|p| p.process_name.clone())
+                    .or_else(|| read_trimmed(&proc_path.join(ppid.to_string()).join("comm")))
+            });
+            let blocked = process.state == UNINTERRUPTIBLE;
+            Diagnosis {
+                pid: process.pid,
+                name: process.process_name.clone(),
+                state: process.state.clone(),
+                duration: states.since(process.pid).map_or(0, |since| now.saturating_sub(since)),
+                parent_pid,
+                parent_name,
+                wchan: if blocked { read_wchan(&dir) } else { None },
+                stack: if blocked { read_stack(&dir) } else { None },
+            }
+        })
+        .collect();
+    diagnoses.sort_by_key(|d| Reverse(d.duration));
+    diagnoses
+}
+
+/// The parent PID, field 4 of `stat`.
+fn read_parent_pid(dir: &Path) -> Option<i32> {
+    StatFields::read(dir).ok()?.field(4)?.parse().ok()
+}
+
+fn read_wchan(dir: &Path) -> Option<String> {
+    read_trimmed(&dir.join("wchan")).filter(|wchan| wchan != "0")
+}
+
+fn read_stack(dir: &Path) -> Option<Vec<String>> {
+    let stack = fs::read_to_string(dir.join("stack")).ok()?;
+    let frames: Vec<String> = stack.lines()
+        .map(|line| match line.find("] ") {
+            Some(i) => line[i + 2..].to_string(),
+            None => line.to_string(),
+        })
+        .collect();
+    if frames.is_empty() { None } else { Some(frames) }
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|text| text.trim().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: i32, state: &str, name: &str) -> Process {
+        Process::new(pid, String::from(state), String::from(name), 0.0, 0.0, 0.0)
+    }
+
+    #[test]
+    fn remembers_when_the_state_changed() {
+        let mut states = StateHistory::new();
+        states.record(1_000, &[process(1, "S", "init"), process(2, "R", "worker")]);
+        states.record(2_000, &[process(1, "S", "init"), process(2, "D", "worker")]);
+        states.record(3_000, &[process(1, "S", "init"), process(2, "D", "worker")]);
+        assert_eq!(states.since(1), Some(1_000));
+        assert_eq!(states.since(2), Some(2_000));
+
+        states.record(4_000, &[process(1, "S", "init")]);
+        assert_eq!(states.since(2), None);
+        states.record(5_000, &[process(1, "S", "init"), process(2, "D", "worker")]);
+        assert_eq!(states.since(2), Some(5_000));
+    }
+
+    #[test]
+    fn names_the_parent_of_a_zombie() {
+        let proc_path = std::env::temp_dir().join(format!("system_info-diagnostics-{}", std::process::id()));
+        let dir = proc_path.join("20");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("stat"), "20 (child) Z 10 10 10 0 -1").unwrap();
+        let processes = [process(10, "S", "parent"), process(20, "Z", "child"), process(30, "R", "busy")];
+        let mut states = StateHistory::new();
+        states.record(1_000, &processes);
+
+        let diagnoses = diagnose(&proc_path, &processes, &states, 61_000);
+        let _ = fs::remove_dir_all(&proc_path);
+        assert_eq!(diagnoses.len(), 1);
+        let zombie = &diagnoses[0];
+        assert_eq!((zombie.pid, zombie.state.as_str(), zombie.duration), (20, ZOMBIE, 60_000));
+        assert_eq!((zombie.parent_pid, zombie.parent_name.as_deref()), (Some(10), Some("parent")));
+        assert_eq!((zombie.wchan.as_ref(), zombie.stack.as_ref()), (None, None));
+    }
+}
@@ -0,0 +1,175 @@
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use crate::snapshot::Snapshot;
+use crate::Process;
+
+/// Which processes get per-process series. Exporting every PID would give
+/// an unbounded number of label sets, so the exporter is limited to either
+/// the busiest processes or an explicit list of names.
+#[derive(Debug, Clone)]
+pub enum ProcessSelection {
+    TopByCpu(usize),
+    Names(Vec<String>),
+}
+
+impl ProcessSelection {
+
+    pub fn select<'a>(&self, processes: &'a [Process]) -> Vec<&'a Process> {
+        match self {
+            ProcessSelection::TopByCpu(count) => {
+                let mut selected: Vec<&Process> = processes.iter().collect();
+                selected.sort_by(|a, b| crate::columns::Column::CpuPercent.compare(a, b));
+                selected.truncate(*count);
+                selected
+            }
+            ProcessSelection::Names(names) => processes.iter()
+                .filter(|p| names.contains(&p.process_name))
+                .collect(),
+        }
+    }
+}
+
+/// Renders a snapshot in the Prometheus text exposition format.
+pub fn render_metrics(snapshot: &Snapshot, selection: &ProcessSelection) -> String {
+    let mut out = String::new();
+    let cpu = snapshot.cpu_breakdown;
+    let mem = snapshot.mem_breakdown;
+    let load = snapshot.load_average;
+
+    write_header(&mut out, "system_info_cpu_usage_percent", "Total CPU usage since the previous sample.");
+    write_sample(&mut out, "system_info_cpu_usage_percent", "", snapshot.cpu_percent);
+    write_header(&mut out, "system_info_cpu_mode_percent", "Share of CPU time spent in each mode since the previous sample.");
+    for (mode, value) in [
+        ("user", cpu.user),
+        ("nice", cpu.nice),
+        ("system", cpu.system),
+        ("idle", cpu.idle),
+        ("iowait", cpu.iowait),
+        ("irq", cpu.irq),
+        ("softirq", cpu.softirq),
+        ("steal", cpu.steal),
+    ].iter() {
+        write_sample(&mut out, "system_info_cpu_mode_percent", &format!("mode=\"{}\"", mode), *value);
+    }
+
+    write_header(&mut out, "system_info_memory_used_percent", "Memory in use as a percentage of the total.");
+    write_sample(&mut out, "system_info_memory_used_percent", "", snapshot.mem_percent);
+    for (name, help, kb) in [
+        ("system_info_memory_total_bytes", "Total usable memory.", mem.total),
+        ("system_info_memory_free_bytes", "Unused memory.", mem.free),
+        ("system_info_memory_available_bytes", "Memory available for new allocations.", mem.available),
+        ("system_info_memory_buffers_bytes", "Memory used for block device buffers.", mem.buffers),
+        ("system_info_memory_cached_bytes", "Memory used by the page cache.", mem.cached),
+    ].iter() {
+        write_header(&mut out, name, help);
+        write_sample(&mut out, name, "", kb * 1024.0);
+    }
+
+    for (name, help, value) in [
+        ("system_info_load1", "1 minute load average.", load.one),
+        ("system_info_load5", "5 minute load average.", load.five),
+        ("system_info_load15", "15 minute load average.", load.fifteen),
+    ].iter() {
+        write_header(&mut out, name, help);
+        write_sample(&mut out, name, "", *value);
+    }
+
+    let processes = selection.select(&snapshot.processes);
+    write_header(&mut out, "system_info_process_cpu_percent", "CPU usage of the process since the previous sample.");
+    for process in processes.iter() {
+        write_sample(&mut out, "system_info_process_cpu_percent", &process_labels(process), process.cpu_percent);
+    }
+    write_header(&mut out, "system_info_process_memory_percent", "Resident memory of the process as a percentage of the total.");
+    for process in processes.iter() {
+        write_sample(&mut out, "system_info_process_memory_percent", &process_labels(process), process.mem_percent);
+    }
+    write_header(&mut out, "system_info_process_resident_memory_bytes", "Resident set size of the process.");
+    for process in processes.iter() {
+        write_sample(&mut out, "system_info_process_resident_memory_bytes", &process_labels(process), process.rss * 1024.0);
+    }
+    out
+}
+
+/// How long a client may take to send its request or read the answer.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Answers HTTP requests on `listener` with the latest rendered metrics.
+/// `/metrics` gets the metrics text, any other path a 404. Each connection
+/// gets its own thread, so a stalled client does not hold up scrapes.
+pub fn serve(listener: TcpListener, metrics: Arc<Mutex<String>>) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                eprintln!("Exporter connection error: {}", e);
+                continue;
+            }
+        };
+        let metrics = Arc::clone(&metrics);
+        thread::spawn(move || {
+            if let Err(e) = handle_connection(&mut stream, &metrics) {
+                eprintln!("Exporter connection error: {}", e);
+            }
+        });
+    }
+    Ok(())
+}
+
+fn handle_connection(stream: &mut TcpStream, metrics: &Mutex<String>) -> io::Result<()> {
+    stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+    stream.set_write_timeout(Some(CLIENT_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or("");
+    let path = parts.next().unwrap_or("");
+    let (status, body) = if method != "GET" {
+        ("405 Method Not Allowed", String::from("Method Not Allowed\n"))
+    } else if path == "/metrics" {
+        ("200 OK", metrics.lock().unwrap().clone())
+    } else {
+        ("404 Not Found", String::from("Not Found\n"))
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+fn write_header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+}
+
+fn write_sample(out: &mut String, name: &str, labels: &str, value: f64) {
+    let value = if value.is_finite() { value } else { 0.0 };
+    if labels.is_empty() {
+        let _ = writeln!(out, "{} {}", name, value);
+    } else {
+        let _ = writeln!(out, "{}{{{}}} {}", name, labels, value);
+    }
+}
+
+fn process_labels(process: &Process) -> String {
+    format!("pid=\"{}\",name=\"{}\"", process.pid, escape_label(&process.process_name))
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
@@ -0,0 +1,110 @@
+//! Limits the processes shown or printed to those matching a name pattern,
+//! a list of PIDs and an owner. Collection itself is never filtered, so
+//! alerts and the rest still see every process.
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::Process;
+
+/// Which processes to keep. Every part that is set must match; an empty
+/// filter keeps everything.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessFilter {
+    /// Matched against the process name.
+    pub name: Option<Regex>,
+    /// Kept when empty.
+    pub pids: Vec<i32>,
+    /// Owner of the process, as the owner of its `/proc/[pid]` directory.
+    pub uid: Option<u32>,
+}
+
+impl ProcessFilter {
+
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.pids.is_empty() && self.uid.is_none()
+    }
+
+    pub fn matches(&self, proc_path: &Path, process: &Process) -> bool {
+        if self.name.as_ref().is_some_and(|name| !name.is_match(&process.process_name)) {
+            return false;
+        }
+        if !self.pids.is_empty() && !self.pids.contains(&process.pid) {
+            return false;
+        }
+        match self.uid {
+            Some(uid) => fs::metadata(proc_path.join(process.pid.to_string()))
+                .map(|metadata| metadata.uid() == uid)
+                .unwrap_or(false),
+            None => true,
+        }
+    }
+
+    /// Drops the processes in `processes` that do not match.
+    pub fn retain(&self, proc_path: &Path, processes: &mut Vec<Process>) {
+        if !self.is_empty() {
+            processes.retain(|process| self.matches(proc_path, process));
+        }
+    }
+}
+
+/// Parses comma separated PIDs such as `1,42,1337`.
+pub fn parse_pids(list: &str) -> Result<Vec<i32>, String> {
+    list.split(',')
+        .map(|pid| pid.trim().parse().map_err(|_| format!("'{}' is not a PID", pid.trim())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::{AlertEngine, Rule, RuleKind};
+    use crate::snapshot::Snapshot;
+    use crate::{CpuBreakdown, LoadAverage, MemBreakdown};
+
+    fn process(pid: i32, name: &str) -> Process {
+        Process::new(pid, String::from("S"), String::from(name), 0.0, 0.0, 0.0)
+    }
+
+    #[test]
+    fn filtered_rows_leave_alerts_every_process() {
+        let snapshot = Snapshot {
+            timestamp: 1_000,
+            cpu_percent: 0.0,
+            mem_percent: 0.0,
+            total_mem: 0.0,
+            cpu_breakdown: CpuBreakdown::default(),
+            mem_breakdown: MemBreakdown::default(),
+            load_average: LoadAverage::default(),
+            processes: vec![process(1, "sshd"), process(2, "bash"), process(3, "bash")],
+        };
+        let filter = ProcessFilter {
+            name: Some(Regex::new("^bash$").unwrap()),
+            pids: vec![3],
+            uid: None,
+        };
+        let mut rows = snapshot.processes.clone();
+        filter.retain(Path::new("/proc"), &mut rows);
+        assert_eq!(rows.iter().map(|p| p.pid).collect::<Vec<_>>(), [3]);
+
+        let mut engine = AlertEngine::new(vec![Rule {
+            name: String::from("no-sshd"),
+            kind: RuleKind::ProcessMissing,
+            above: None,
+            for_secs: 0,
+            process: Some(String::from("sshd")),
+        }]);
+        assert!(engine.evaluate(&snapshot).is_empty());
+        assert_eq!(snapshot.processes.len(), 3);
+    }
+
+    #[test]
+    fn parses_pid_lists() {
+        assert_eq!(parse_pids("1, 42,1337"), Ok(vec![1, 42, 1337]));
+        assert!(parse_pids("1,,2").is_err());
+        assert!(parse_pids("x").is_err());
+    }
+}
@@ -0,0 +1,219 @@
+//! Following several agents at once.
+//!
+//! Hosts are listed in a TOML file:
+//!
+//! ```toml
+//! [[host]]
+//! name = "build-01"
+//! address = "build-01.example.com:9102"
+//! token = "shared secret"   # optional
+//! ```
+
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::mpsc::Receiver;
+
+use serde::Deserialize;
+
+use crate::daemon::{self, Update};
+use crate::history::TimeSeries;
+use crate::remote;
+use crate::snapshot::Snapshot;
+use crate::Process;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HostConfig {
+    pub name: String,
+    pub address: String,
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FleetConfig {
+    #[serde(rename = "host", default)]
+    pub hosts: Vec<HostConfig>,
+}
+
+impl FleetConfig {
+
+    pub fn load(path: &Path) -> io::Result<FleetConfig> {
+        let text = fs::read_to_string(path)?;
+        let invalid = |message: String| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("{}: {}", path.display(), message))
+        };
+        let config: FleetConfig = toml::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+        if config.hosts.is_empty() {
+            return Err(invalid(String::from("no [[host]] entries")));
+        }
+        let mut names = HashSet::new();
+        for host in &config.hosts {
+            if !names.insert(&host.name) {
+                return Err(invalid(format!("host '{}' is listed twice", host.name)));
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Connection state of a followed host.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Status {
+    Connecting,
+    Connected,
+    Disconnected(String),
+}
+
+/// One followed daemon or agent and everything received from it.
+pub struct Host {
+    pub name: String,
+    pub status: Status,
+    pub cpu_history: TimeSeries,
+    pub mem_history: TimeSeries,
+    pub snapshot: Option<Box<Snapshot>>,
+    updates: Receiver<Update>,
+}
+
+impl Host {
+
+    /// Follows updates from `daemon::watch`.
+    pub fn new(name: String, updates: Receiver<Update>) -> Host {
+        Host {
+            name,
+            status: Status::Connecting,
+            cpu_history: TimeSeries::new(),
+            mem_history: TimeSeries::new(),
+            snapshot: None,
+            updates,
+        }
+    }
+
+    /// Follows the agent described by `config`.
+    pub fn connect(config: &HostConfig) -> Host {
+        let address = config.address.clone();
+        let token = config.token.clone();
+        Host::new(
+            config.name.clone(),
+            daemon::watch(move || remote::connect(&address, token.as_deref())),
+        )
+    }
+
+    /// Applies every update received since the last call.
+    pub fn poll(&mut self) {
+        for update in self.updates.try_iter() {
+            match update {
+                Update::Connected { cpu, mem, snapshot } => {
+                    self.cpu_history = cpu;
+                    self.mem_history = mem;
+                    self.snapshot = Some(snapshot);
+                    self.status = Status::Connected;
+                }
+                Update::Snapshot(snapshot) => {
+                    self.cpu_history.push(snapshot.timestamp, snapshot.cpu_percent);
+                    self.mem_history.push(snapshot.timestamp, snapshot.mem_percent);
+                    self.snapshot = Some(snapshot);
+                }
+                Update::Disconnected { error, retry_in } => {
+                    self.status = Status::Disconnected(
+                        format!("{}, retrying in {}s", error, retry_in.as_secs()),
+                    );
+                }
+            }
+        }
+    }
+
+    pub fn processes(&self) -> &[Process] {
+        self.snapshot.as_ref().map_or(&[], |s| &s.processes[..])
+    }
+
+    /// The process using the most CPU in the latest snapshot.
+    pub fn top_process(&self) -> Option<&Process> {
+        self.processes().iter()
+            .filter(|p| p.cpu_percent.is_finite())
+            .max_by(|a, b| a.cpu_percent.partial_cmp(&b.cpu_percent).unwrap())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::{CpuBreakdown, LoadAverage, MemBreakdown};
+
+    fn load(name: &str, text: &str) -> io::Result<FleetConfig> {
+        let path = std::env::temp_dir().join(format!("system_info-fleet-{}-{}.toml", name, std::process::id()));
+        fs::write(&path, text).unwrap();
+        let config = FleetConfig::load(&path);
+        fs::remove_file(&path).unwrap();
+        config
+    }
+
+    fn snapshot(timestamp: u64, cpu_percent: f64) -> Box<Snapshot> {
+        let mut busy = Process::new(2, String::from("R"), String::from("make"), 0.0, 0.0, 0.0);
+        busy.set_cpu_percent(80.0);
+        Box::new(Snapshot {
+            timestamp,
+            cpu_percent,
+            mem_percent: 40.0,
+            total_mem: 1024.0,
+            cpu_breakdown: CpuBreakdown::default(),
+            mem_breakdown: MemBreakdown::default(),
+            load_average: LoadAverage::default(),
+            processes: vec![Process::new(1, String::from("S"), String::from("init"), 0.0, 0.0, 0.0), busy],
+        })
+    }
+
+    #[test]
+    fn loads_hosts() {
+        let config = load("ok", "\
+            [[host]]\n\
+            name = \"build-01\"\n\
+            address = \"build-01:9102\"\n\
+            token = \"secret\"\n\
+            [[host]]\n\
+            name = \"build-02\"\n\
+            address = \"build-02:9102\"\n").unwrap();
+        let hosts: Vec<(&str, Option<&str>)> = config.hosts.iter()
+            .map(|h| (h.name.as_str(), h.token.as_deref()))
+            .collect();
+        assert_eq!(hosts, [("build-01", Some("secret")), ("build-02", None)]);
+    }
+
+    #[test]
+    fn rejects_bad_host_lists() {
+        let error = |name: &str, text: &str| load(name, text).unwrap_err().to_string();
+        assert!(error("empty", "").ends_with("no [[host]] entries"));
+        let twice = "[[host]]\nname = \"a\"\naddress = \"a:1\"\n[[host]]\nname = \"a\"\naddress = \"b:1\"\n";
+        assert!(error("twice", twice).ends_with("host 'a' is listed twice"));
+        assert!(error("unknown", "[[host]]\nname = \"a\"\naddress = \"a:1\"\nport = 1\n").contains("unknown field"));
+        assert!(error("missing", "[[host]]\nname = \"a\"\n").contains("address"));
+    }
+
+    #[test]
+    fn follows_updates() {
+        let (sender, updates) = mpsc::channel();
+        let mut host = Host::new(String::from("build-01"), updates);
+        assert_eq!(host.status, Status::Connecting);
+        assert!(host.top_process().is_none());
+
+        let mut cpu = TimeSeries::new();
+        cpu.push(1_000, 10.0);
+        sender.send(Update::Connected { cpu, mem: TimeSeries::new(), snapshot: snapshot(1_000, 10.0) }).unwrap();
+        sender.send(Update::Snapshot(snapshot(2_000, 30.0))).unwrap();
+        host.poll();
+        assert_eq!(host.status, Status::Connected);
+        assert_eq!(host.cpu_history.latest().map(|s| s.value), Some(30.0));
+        assert_eq!(host.top_process().map(|p| p.pid), Some(2));
+
+        let error = io::Error::new(io::ErrorKind::ConnectionRefused, "refused");
+        sender.send(Update::Disconnected { error, retry_in: Duration::from_secs(4) }).unwrap();
+        host.poll();
+        assert_eq!(host.status, Status::Disconnected(String::from("refused, retrying in 4s")));
+        assert_eq!(host.processes().len(), 2);
+    }
+}
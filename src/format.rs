@@ -0,0 +1,282 @@
+//! Turns measurements into text for every view.
+//!
+//! Sizes are shown in binary (KiB, MiB) or decimal (kB, MB) units, times as
+//! `HH:MM:SS` and percentages with a configurable number of decimals. In raw
+//! mode every value is printed as the plain number it was measured in (kB,
+//! clock ticks, seconds), which is what scripts reading batch output want.
+//! JSON and CSV output always carry raw numbers.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::details::clock_ticks_per_second;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Units {
+    /// Powers of 1024: KiB, MiB, GiB.
+    Binary,
+    /// Powers of 1000: kB, MB, GB.
+    Decimal,
+}
+
+impl Units {
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Units::Binary => "binary",
+            Units::Decimal => "decimal",
+        }
+    }
+
+    pub fn toggle(self) -> Units {
+        match self {
+            Units::Binary => Units::Decimal,
+            Units::Decimal => Units::Binary,
+        }
+    }
+
+    fn base_and_suffixes(self) -> (f64, [&'static str; 5]) {
+        match self {
+            Units::Binary => (1024.0, ["B", "KiB", "MiB", "GiB", "TiB"]),
+            Units::Decimal => (1000.0, ["B", "kB", "MB", "GB", "TB"]),
+        }
+    }
+}
+
+impl fmt::Display for Units {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Units {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Units, String> {
+        match text {
+            "binary" => Ok(Units::Binary),
+            "decimal" => Ok(Units::Decimal),
+            _ => Err(format!("unknown units '{}', expected binary or decimal", text)),
+        }
+    }
+}
+
+/// How numbers are written.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Format {
+    pub units: Units,
+    /// Decimals shown for percentages.
+    pub precision: usize,
+    /// Print plain numbers in the unit they were measured in.
+    pub raw: bool,
+}
+
+impl Default for Format {
+    fn default() -> Format {
+        Format {
+            units: Units::Binary,
+            precision: 2,
+            raw: false,
+        }
+    }
+}
+
+impl Format {
+
+    /// A format that prints plain numbers.
+    pub fn raw() -> Format {
+        Format {
+            raw: true,
+            ..Format::default()
+        }
+    }
+
+    pub fn percent(&self, value: f64) -> String {
+        if self.raw {
+            value.to_string()
+        } else {
+            format!("{:.*}", self.precision, value)
+        }
+    }
+
+    /// A size in bytes, such as `12.3 MiB`.
+    pub fn bytes(&self, bytes: f64) -> String {
+        if self.raw {
+            return bytes.to_string();
+        }
+        let (size, suffix) = self.unit(bytes);
+        if size > 1.0 {
+            format!("{:.1} {}", bytes / size, suffix)
+        } else {
+            format!("{:.0} {}", bytes, suffix)
+        }
+    }
+
+    /// The unit `bytes` writes a size in, as its size in bytes and suffix.
+    fn unit(&self, bytes: f64) -> (f64, &'static str) {
+        let (base, suffixes) = self.units.base_and_suffixes();
+        let mut size = 1.0;
+        let mut unit = 0;
+        while (bytes / size).abs() >= base && unit + 1 < suffixes.len() {
+            size *= base;
+            unit += 1;
+        }
+        (size, suffixes[unit])
+    }
+
+    /// The unit `kilobytes` writes `kb` in, as its size in kB and suffix.
+    pub fn size_unit(&self, kb: f64) -> (f64, &'static str) {
+        let (size, suffix) = self.unit(kb * 1024.0);
+        (size / 1024.0, suffix)
+    }
+
+    /// A size the kernel reports in kB (1024 bytes); raw mode keeps the kB.
+    pub fn kilobytes(&self, kb: f64) -> String {
+        if self.raw {
+            kb.to_string()
+        } else {
+            self.bytes(kb * 1024.0)
+        }
+    }
+
+    /// A rate in bytes per second, such as `1.5 MB/s`.
+    pub fn rate(&self, bytes_per_second: f64) -> String {
+        if self.raw {
+            bytes_per_second.to_string()
+        } else {
+            format!("{}/s", self.bytes(bytes_per_second))
+        }
+    }
+
+    /// A duration in seconds as `HH:MM:SS`; raw mode keeps the seconds.
+    pub fn duration(&self, seconds: u64) -> String {
+        if self.raw {
+            seconds.to_string()
+        } else {
+            format_hms(seconds)
+        }
+    }
+
+    /// A CPU time in clock ticks as `HH:MM:SS`; raw mode keeps the ticks.
+    pub fn cpu_time(&self, ticks: f64) -> String {
+        if self.raw {
+            ticks.to_string()
+        } else {
+            format_hms((ticks.max(0.0) as u64) / clock_ticks_per_second())
+        }
+    }
+}
+
+/// Formats seconds as `HH:MM:SS`, letting the hours grow past 99.
+pub fn format_hms(seconds: u64) -> String {
+    format!("{:02}:{:02}:{:02}", seconds / 3_600, seconds % 3_600 / 60, seconds % 60)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KIB: f64 = 1024.0;
+
+    fn format(units: Units, precision: usize, raw: bool) -> Format {
+        Format { units, precision, raw }
+    }
+
+    #[test]
+    fn scales_sizes_at_unit_boundaries() {
+        let binary = Format::default();
+        let decimal = format(Units::Decimal, 2, false);
+        let cases = [
+            // kB, binary, decimal
+            (0.0, "0 B", "0 B"),
+            (0.5, "512 B", "512 B"),
+            (1.0, "1.0 KiB", "1.0 kB"),
+            (1023.0, "1023.0 KiB", "1.0 MB"),
+            (1024.0, "1.0 MiB", "1.0 MB"),
+            (1536.0, "1.5 MiB", "1.6 MB"),
+            (KIB * KIB - 1.0, "1024.0 MiB", "1.1 GB"),
+            (KIB * KIB, "1.0 GiB", "1.1 GB"),
+            (3.7 * KIB * KIB, "3.7 GiB", "4.0 GB"),
+            (KIB * KIB * KIB, "1.0 TiB", "1.1 TB"),
+            (KIB * KIB * KIB * KIB * 2.0, "2048.0 TiB", "2251.8 TB"),
+        ];
+        for &(kb, expected_binary, expected_decimal) in &cases {
+            assert_eq!(binary.kilobytes(kb), expected_binary, "{} kB", kb);
+            assert_eq!(decimal.kilobytes(kb), expected_decimal, "{} kB", kb);
+        }
+        assert_eq!(decimal.bytes(999.0), "999 B");
+        assert_eq!(decimal.bytes(1000.0), "1.0 kB");
+        assert_eq!(binary.rate(1536.0), "1.5 KiB/s");
+    }
+
+    #[test]
+    fn size_units_are_given_in_kilobytes() {
+        let cases = [
+            (Units::Binary, 512.0, (1.0, "KiB")),
+            (Units::Binary, 2048.0, (1024.0, "MiB")),
+            (Units::Binary, 5.0 * KIB * KIB, (KIB * KIB, "GiB")),
+            (Units::Decimal, 2048.0, (1e6 / KIB, "MB")),
+        ];
+        for &(units, kb, expected) in &cases {
+            assert_eq!(format(units, 2, false).size_unit(kb), expected, "{} kB", kb);
+        }
+    }
+
+    #[test]
+    fn percentages_use_the_precision() {
+        let cases = [(0, "13"), (1, "12.6"), (2, "12.57"), (4, "12.5678")];
+        for &(precision, expected) in &cases {
+            assert_eq!(format(Units::Binary, precision, false).percent(12.5678), expected);
+        }
+    }
+
+    #[test]
+    fn raw_mode_prints_plain_numbers() {
+        let raw = Format::raw();
+        assert_eq!(raw.percent(12.5678), "12.5678");
+        assert_eq!(raw.kilobytes(1536.0), "1536");
+        assert_eq!(raw.bytes(1536.5), "1536.5");
+        assert_eq!(raw.rate(2048.0), "2048");
+        assert_eq!(raw.duration(90_061), "90061");
+        assert_eq!(raw.cpu_time(250.0), "250");
+        assert_eq!(raw.size_unit(3.0 * KIB * KIB), (KIB * KIB, "GiB"));
+    }
+
+    #[test]
+    fn formats_hours_minutes_and_seconds() {
+        let cases = [
+            (0, "00:00:00"),
+            (59, "00:00:59"),
+            (60, "00:01:00"),
+            (3_599, "00:59:59"),
+            (3_600, "01:00:00"),
+            (90_061, "25:01:01"),
+            (360_000, "100:00:00"),
+        ];
+        for &(seconds, expected) in &cases {
+            assert_eq!(format_hms(seconds), expected);
+            assert_eq!(Format::default().duration(seconds), expected);
+        }
+    }
+
+    #[test]
+    fn cpu_time_counts_whole_seconds_of_ticks() {
+        let tps = clock_ticks_per_second() as f64;
+        let format = Format::default();
+        assert_eq!(format.cpu_time(0.0), "00:00:00");
+        assert_eq!(format.cpu_time(tps - 1.0), "00:00:00");
+        assert_eq!(format.cpu_time(61.5 * tps), "00:01:01");
+        assert_eq!(format.cpu_time(-tps), "00:00:00");
+    }
+
+    #[test]
+    fn parses_units() {
+        assert_eq!("binary".parse(), Ok(Units::Binary));
+        assert_eq!("decimal".parse(), Ok(Units::Decimal));
+        assert!("metric".parse::<Units>().is_err());
+        assert_eq!(Units::Binary.toggle(), Units::Decimal);
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::VecDeque;
+
+use serde::{Deserialize, Serialize};
+
+/// One point of a time series. `timestamp` is in milliseconds since the Unix
+/// epoch and marks the start of the bucket the value was averaged over.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub timestamp: u64,
+    pub value: f64,
+}
+
+/// A fixed size ring of samples averaged into buckets of `resolution` ms.
+/// Once full, each new bucket evicts the oldest one.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Tier {
+    resolution: u64,
+    capacity: usize,
+    samples: VecDeque<Sample>,
+    last_count: u32,
+}
+
+impl Tier {
+
+    pub fn new(resolution: u64, capacity: usize) -> Tier {
+        Tier {
+            resolution,
+            capacity,
+            samples: VecDeque::with_capacity(capacity),
+            last_count: 0,
+        }
+    }
+
+    pub fn resolution(&self) -> u64 {
+        self.resolution
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// How far back this tier reaches once full, in ms.
+    pub fn span(&self) -> u64 {
+        self.resolution * self.capacity as u64
+    }
+
+    pub fn samples(&self) -> &VecDeque<Sample> {
+        &self.samples
+    }
+
+    /// Adds a value to the bucket containing `timestamp`. The newest bucket
+    /// is kept as a running average, so it is visible before it closes.
+    pub fn push(&mut self, timestamp: u64, value: f64) {
+        let bucket = timestamp - timestamp % self.resolution;
+        match self.samples.back_mut() {
+            Some(last) if last.timestamp == bucket => {
+                let count = f64::from(self.last_count);
+                last.value = (last.value * count + value) / (count + 1.0);
+                self.last_count += 1;
+                return;
+            }
+            Some(last) if last.timestamp > bucket => return,
+            _ => {}
+        }
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample {
+            timestamp: bucket,
+            value,
+        });
+        self.last_count = 1;
+    }
+}
+
+/// A bounded time series kept at several resolutions. The default tiers hold
+/// 1 s samples for 5 minutes, 10 s samples for an hour and 1 minute samples
+/// for 24 hours, so memory use stays constant however long the monitor runs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeSeries {
+    tiers: Vec<Tier>,
+}
+
+impl TimeSeries {
+
+    pub fn new() -> TimeSeries {
+        TimeSeries::with_tiers(&[(1_000, 300), (10_000, 360), (60_000, 1_440)])
+    }
+
+    /// Builds a series from `(resolution in ms, capacity)` pairs, finest first.
+    pub fn with_tiers(tiers: &[(u64, usize)]) -> TimeSeries {
+        TimeSeries {
+            tiers: tiers.iter()
+                .map(|&(resolution, capacity)| Tier::new(resolution, capacity))
+                .collect(),
+        }
+    }
+
+    pub fn push(&mut self, timestamp: u64, value: f64) {
+        for tier in self.tiers.iter_mut() {
+            tier.push(timestamp, value);
+        }
+    }
+
+    pub fn tiers(&self) -> &[Tier] {
+        &self.tiers
+    }
+
+    /// Timestamp of the oldest sample still held: the start of the finest
+    /// tier that has not evicted anything yet, or else of the coarsest.
+    pub fn oldest(&self) -> Option<u64> {
+        let tier = self.tiers.iter()
+            .find(|tier| tier.samples().len() < tier.capacity())
+            .or_else(|| self.tiers.last())?;
+        tier.samples().front().map(|sample| sample.timestamp)
+    }
+
+    pub fn latest(&self) -> Option<Sample> {
+        self.tiers.first().and_then(|tier| tier.samples().back().cloned())
+    }
+
+    /// The finest tier whose span covers `window` ms, or the coarsest tier if
+    /// none does.
+    pub fn tier_for(&self, window: u64) -> &Tier {
+        self.tiers.iter()
+            .find(|tier| tier.span() >= window)
+            .unwrap_or_else(|| self.tiers.last().expect("time series without tiers"))
+    }
+
+    /// Samples from the tier best suited to the window between `start` and
+    /// `end` ms, including buckets that overlap either edge.
+    pub fn range(&self, start: u64, end: u64) -> Vec<Sample> {
+        let tier = self.tier_for(end.saturating_sub(start));
+        tier.samples()
+            .iter()
+            .filter(|s| s.timestamp + tier.resolution() > start && s.timestamp <= end)
+            .cloned()
+            .collect()
+    }
+}
+
+impl Default for TimeSeries {
+    fn default() -> TimeSeries {
+        TimeSeries::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(tier: &Tier) -> Vec<(u64, f64)> {
+        tier.samples().iter().map(|s| (s.timestamp, s.value)).collect()
+    }
+
+    #[test]
+    fn tier_evicts_the_oldest_bucket_when_full() {
+        let mut tier = Tier::new(1_000, 3);
+        for second in 0..5 {
+            tier.push(second * 1_000, second as f64);
+        }
+        assert_eq!(tier.samples().len(), 3);
+        assert_eq!(tier.samples().front().unwrap().timestamp, 2_000);
+        assert_eq!(tier.span(), 3_000);
+
+        // Late samples for an evicted or closed bucket are dropped.
+        tier.push(500, 99.0);
+        assert_eq!(tier.samples().front().unwrap().timestamp, 2_000);
+        assert_eq!(tier.samples().back().unwrap().value, 4.0);
+    }
+
+    #[test]
+    fn buckets_average_their_samples() {
+        let mut series = TimeSeries::new();
+        for second in 0..25 {
+            series.push(120_000 + second * 1_000, second as f64);
+        }
+        let tiers = series.tiers();
+        assert_eq!(tiers[0].samples().len(), 25);
+        // 0-9, 10-19 and the open bucket 20-24.
+        assert_eq!(values(&tiers[1]), [(120_000, 4.5), (130_000, 14.5), (140_000, 22.0)]);
+        assert_eq!(values(&tiers[2]), [(120_000, 12.0)]);
+        assert_eq!(series.latest(), Some(Sample { timestamp: 144_000, value: 24.0 }));
+    }
+
+    #[test]
+    fn picks_the_finest_tier_covering_the_window() {
+        let series = TimeSeries::new();
+        assert_eq!(series.tier_for(60_000).resolution(), 1_000);
+        assert_eq!(series.tier_for(300_000).resolution(), 1_000);
+        assert_eq!(series.tier_for(300_001).resolution(), 10_000);
+        assert_eq!(series.tier_for(3_600_000).resolution(), 10_000);
+        assert_eq!(series.tier_for(86_400_000).resolution(), 60_000);
+        assert_eq!(series.tier_for(u64::MAX).resolution(), 60_000);
+    }
+
+    #[test]
+    fn range_reads_the_tier_for_its_width() {
+        let mut series = TimeSeries::with_tiers(&[(1_000, 10), (10_000, 10)]);
+        for second in 0..40 {
+            series.push(second * 1_000, 1.0);
+        }
+        let now = 39_000;
+        // Ten seconds fit the fine tier, with the bucket overlapping the start.
+        let fine = series.range(now - 10_000, now);
+        assert_eq!(fine.len(), 10);
+        assert_eq!(fine[0].timestamp, 30_000);
+        // Eleven do not, so the 10 s buckets from 20 s on answer instead.
+        let coarse = series.range(now - 11_000, now);
+        assert_eq!(coarse.iter().map(|s| s.timestamp).collect::<Vec<_>>(), [20_000, 30_000]);
+        assert!(series.range(50_000, 60_000).is_empty());
+    }
+
+    #[test]
+    fn oldest_is_kept_by_the_finest_tier_not_yet_full() {
+        let mut series = TimeSeries::with_tiers(&[(1_000, 5), (10_000, 5)]);
+        assert_eq!(series.oldest(), None);
+        series.push(3_500, 1.0);
+        series.push(4_500, 1.0);
+        assert_eq!(series.oldest(), Some(3_000));
+        for second in 5..30 {
+            series.push(second * 1_000, 1.0);
+        }
+        // The fine tier has evicted 3 s, the coarse one still starts at 0.
+        assert_eq!(series.tiers()[0].samples().front().unwrap().timestamp, 25_000);
+        assert_eq!(series.oldest(), Some(0));
+        for second in 30..80 {
+            series.push(second * 1_000, 1.0);
+        }
+        // Both tiers are full; the coarsest holds the oldest sample.
+        assert_eq!(series.oldest(), Some(30_000));
+    }
+}
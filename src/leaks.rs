@@ -0,0 +1,271 @@
+//! Memory leak detection from per-process RSS trends.
+//!
+//! When asked to, `ProcessInfo` records every process's RSS each update into an
+//! `RssHistory`. `find_suspects` fits a least squares line to each history
+//! over the configured window and flags processes whose RSS grows faster
+//! than the configured slope, either steadily (never shrinking) or along a
+//! good linear fit.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use crate::history::Sample;
+use crate::Process;
+
+/// The longest RSS history kept per process, in ms.
+pub const MAX_HISTORY: u64 = 30 * 60_000;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct ProcessHistory {
+    name: String,
+    /// RSS in kB.
+    samples: VecDeque<Sample>,
+}
+
+/// Recent RSS samples for every running process.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RssHistory {
+    processes: HashMap<i32, ProcessHistory>,
+}
+
+impl RssHistory {
+
+    pub fn new() -> RssHistory {
+        RssHistory::default()
+    }
+
+    /// Adds the current RSS of `processes` and forgets processes that exited.
+    /// A PID reused by a process with another name starts a new history.
+    pub fn record(&mut self, timestamp: u64, processes: &[Process]) {
+        let running: HashSet<(i32, &str)> = processes.iter()
+            .map(|p| (p.pid, p.process_name.as_str()))
+            .collect();
+        self.processes.retain(|&pid, history| running.contains(&(pid, history.name.as_str())));
+        let cutoff = timestamp.saturating_sub(MAX_HISTORY);
+        for process in processes {
+            let history = self.processes.entry(process.pid).or_insert_with(|| ProcessHistory {
+                name: process.process_name.clone(),
+                samples: VecDeque::new(),
+            });
+            while history.samples.front().is_some_and(|s| s.timestamp < cutoff) {
+                history.samples.pop_front();
+            }
+            history.samples.push_back(Sample {
+                timestamp,
+                value: process.rss,
+            });
+        }
+    }
+
+    /// The RSS samples of `pid`, oldest first.
+    pub fn samples(&self, pid: i32) -> Option<&VecDeque<Sample>> {
+        self.processes.get(&pid).map(|history| &history.samples)
+    }
+}
+
+/// When a growing RSS counts as a suspected leak.
+#[derive(Debug, Clone, Copy)]
+pub struct LeakConfig {
+    /// How far back the trend is fitted, in ms.
+    pub window: u64,
+    /// Growth rate above which a process is flagged, in kB per second.
+    pub min_slope: f64,
+    /// Fit quality (r²) accepted as linear growth when RSS sometimes shrinks.
+    pub min_r_squared: f64,
+}
+
+impl Default for LeakConfig {
+    fn default() -> LeakConfig {
+        LeakConfig {
+            window: 5 * 60_000,
+            min_slope: 1024.0 / 60.0,
+            min_r_squared: 0.9,
+        }
+    }
+}
+
+/// Fewest samples a trend is fitted to.
+const MIN_SAMPLES: usize = 10;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LeakSuspect {
+    pub pid: i32,
+    pub name: String,
+    /// Latest RSS in kB.
+    pub rss: f64,
+    /// Fitted growth in kB per second.
+    pub slope: f64,
+    pub r_squared: f64,
+    /// True when RSS never shrank over the window.
+    pub monotonic: bool,
+    /// Time covered by the fitted samples, in ms.
+    pub span: u64,
+}
+
+/// Processes whose RSS grew faster than `config.min_slope` over the window
+/// ending at `now`, fastest first. A history must cover at least half the
+/// window to be judged.
+pub fn find_suspects(history: &RssHistory, config: &LeakConfig, now: u64) -> Vec<LeakSuspect> {
+    let start = now.saturating_sub(config.window);
+    let mut suspects: Vec<LeakSuspect> = history.processes.iter()
+        .filter_map(|(&pid, process)| {
+            let samples: Vec<&Sample> = process.samples.iter().filter(|s| s.timestamp >= start).collect();
+            let (first, last) = (samples.first()?, samples.last()?);
+            let span = last.timestamp - first.timestamp;
+            if samples.len() < MIN_SAMPLES || span < config.window / 2 {
+                return None;
+            }
+            let (slope, r_squared) = fit(&samples);
+            let monotonic = samples.windows(2).all(|pair| pair[1].value >= pair[0].value);
+            let growing = slope > config.min_slope && last.value > first.value;
+            if growing && (monotonic || r_squared >= config.min_r_squared) {
+                Some(LeakSuspect {
+                    pid,
+                    name: process.name.clone(),
+                    rss: last.value,
+                    slope,
+                    r_squared,
+                    monotonic,
+                    span,
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+    suspects.sort_by(|a, b| b.slope.partial_cmp(&a.slope).unwrap());
+    suspects
+}
+
+/// Least squares slope in value per second, and the fit's r².
+fn fit(samples: &[&Sample]) -> (f64, f64) {
+    let n = samples.len() as f64;
+    let origin = samples[0].timestamp;
+    let xs: Vec<f64> = samples.iter().map(|s| (s.timestamp - origin) as f64 / 1000.0).collect();
+    let mean_x = xs.iter().sum::<f64>() / n;
+    let mean_y = samples.iter().map(|s| s.value).sum::<f64>() / n;
+    let (mut sxy, mut sxx, mut syy) = (0.0, 0.0, 0.0);
+    for (x, sample) in xs.iter().zip(samples) {
+        let (dx, dy) = (x - mean_x, sample.value - mean_y);
+        sxy += dx * dy;
+        sxx += dx * dx;
+        syy += dy * dy;
+    }
+    if sxx == 0.0 {
+        return (0.0, 0.0);
+    }
+    let slope = sxy / sxx;
+    let r_squared = if syy == 0.0 { 0.0 } else { sxy * sxy / (sxx * syy) };
+    (slope, r_squared)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: i32, name: &str, rss: f64) -> Process {
+        Process::new(pid, String::from("S"), String::from(name), 0.0, 0.0, rss)
+    }
+
+    fn samples(values: &[f64]) -> Vec<Sample> {
+        values.iter()
+            .enumerate()
+            .map(|(i, &value)| Sample { timestamp: i as u64 * 1000, value })
+            .collect()
+    }
+
+    /// A history of one process sampled every second with `rss(second)`.
+    fn history(seconds: u64, rss: impl Fn(u64) -> f64) -> RssHistory {
+        let mut history = RssHistory::new();
+        for second in 0..=seconds {
+            history.record(second * 1000, &[process(7, "leaky", rss(second))]);
+        }
+        history
+    }
+
+    fn config(window_secs: u64) -> LeakConfig {
+        LeakConfig {
+            window: window_secs * 1000,
+            min_slope: 10.0,
+            min_r_squared: 0.9,
+        }
+    }
+
+    #[test]
+    fn fits_a_known_line() {
+        let line = samples(&[100.0, 150.0, 200.0, 250.0, 300.0]);
+        let refs: Vec<&Sample> = line.iter().collect();
+        let (slope, r_squared) = fit(&refs);
+        assert!((slope - 50.0).abs() < 1e-9);
+        assert!((r_squared - 1.0).abs() < 1e-9);
+
+        let flat = samples(&[100.0; 5]);
+        let refs: Vec<&Sample> = flat.iter().collect();
+        assert_eq!(fit(&refs), (0.0, 0.0));
+
+        let zigzag = samples(&[0.0, 100.0, 0.0, 100.0]);
+        let refs: Vec<&Sample> = zigzag.iter().collect();
+        let (slope, r_squared) = fit(&refs);
+        assert!((slope - 20.0).abs() < 1e-9);
+        assert!((r_squared - 0.2).abs() < 1e-9);
+    }
+
+    #[test]
+    fn flags_steady_growth() {
+        let suspects = find_suspects(&history(60, |s| 1000.0 + 50.0 * s as f64), &config(60), 60_000);
+        assert_eq!(suspects.len(), 1);
+        let suspect = &suspects[0];
+        assert_eq!((suspect.pid, suspect.name.as_str(), suspect.rss), (7, "leaky", 4000.0));
+        assert!((suspect.slope - 50.0).abs() < 1e-9);
+        assert!(suspect.monotonic);
+        assert_eq!(suspect.span, 60_000);
+    }
+
+    #[test]
+    fn accepts_a_noisy_line_only_if_it_fits() {
+        // Dips every fifth second but still climbs along a line.
+        let sawtooth = |s: u64| 50.0 * s as f64 - if s % 5 == 4 { 60.0 } else { 0.0 };
+        let suspects = find_suspects(&history(60, sawtooth), &config(60), 60_000);
+        assert_eq!(suspects.len(), 1);
+        assert!(!suspects[0].monotonic);
+        assert!(suspects[0].r_squared >= 0.9);
+
+        // Swings far wider than the trend.
+        let noisy = |s: u64| 20.0 * s as f64 + if s.is_multiple_of(2) { 2000.0 } else { 0.0 };
+        assert!(find_suspects(&history(60, noisy), &config(60), 60_000).is_empty());
+    }
+
+    #[test]
+    fn ignores_slow_or_shrinking_processes() {
+        assert!(find_suspects(&history(60, |s| 1000.0 + 5.0 * s as f64), &config(60), 60_000).is_empty());
+        assert!(find_suspects(&history(60, |s| 10_000.0 - 50.0 * s as f64), &config(60), 60_000).is_empty());
+    }
+
+    #[test]
+    fn needs_half_the_window() {
+        let growing = |s: u64| 50.0 * s as f64;
+        assert!(find_suspects(&history(29, growing), &config(60), 29_000).is_empty());
+        assert_eq!(find_suspects(&history(30, growing), &config(60), 30_000).len(), 1);
+        // Too few samples even when they span the window.
+        let mut sparse = RssHistory::new();
+        for second in (0..=60).step_by(10) {
+            sparse.record(second * 1000, &[process(7, "leaky", 50.0 * second as f64)]);
+        }
+        assert!(find_suspects(&sparse, &config(60), 60_000).is_empty());
+    }
+
+    #[test]
+    fn a_reused_pid_starts_a_new_history() {
+        let mut history = history(60, |s| 50.0 * s as f64);
+        assert_eq!(history.samples(7).unwrap().len(), 61);
+        history.record(61_000, &[process(7, "other", 10.0)]);
+        let samples = history.samples(7).unwrap();
+        assert_eq!(samples.len(), 1);
+        assert_eq!(samples[0].value, 10.0);
+        assert!(find_suspects(&history, &config(60), 61_000).is_empty());
+
+        history.record(62_000, &[process(8, "other", 10.0)]);
+        assert!(history.samples(7).is_none());
+    }
+}
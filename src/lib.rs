@@ -1,30 +1,83 @@
-use std::{ fs };
-use std::path::Path;
+use std::{ env, fs };
+use std::path::{Path, PathBuf};
 use std::str;
 use std::io;
 use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+pub mod alerts;
+pub mod batch;
+pub mod columns;
+pub mod csv_export;
+pub mod daemon;
+pub mod details;
+pub mod diagnostics;
+pub mod exporter;
+pub mod filter;
+pub mod fleet;
+pub mod format;
+pub mod history;
+pub mod leaks;
+pub mod pins;
+pub mod priority;
+pub mod protocol;
+pub mod record;
+pub mod remote;
+pub mod scheduling;
+pub mod signals;
+pub mod snapshot;
+pub mod store;
+pub mod users;
+pub mod wrapper;
+
+use columns::Column;
+use details::StatFields;
+use diagnostics::StateHistory;
+use leaks::RssHistory;
+use scheduling::SchedPolicy;
+use history::TimeSeries;
+
+/// Share of CPU time spent in each `/proc/stat` category since the previous
+/// sample, as percentages.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CpuBreakdown {
+    pub user: f64,
+    pub nice: f64,
+    pub system: f64,
+    pub idle: f64,
+    pub iowait: f64,
+    pub irq: f64,
+    pub softirq: f64,
+    pub steal: f64,
+}
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct CPUUsage {
-    usage: Vec<(f64, f64)>,
+    usage: TimeSeries,
     prev_time: f64,
     prev_usage: f64,
     current_cpu: f64,
+    prev_values: Vec<f64>,
+    breakdown: CpuBreakdown,
 }
 
 
 impl CPUUsage {
 
     pub fn new() -> CPUUsage {
-        let usage = vec![];
+        let usage = TimeSeries::new();
         let prev_time = 0.0;
         let prev_usage = 0.0;
         let current_cpu = 0.0;
+        let prev_values = vec![];
+        let breakdown = CpuBreakdown::default();
         CPUUsage {
             usage,
             prev_time,
             prev_usage,
             current_cpu,
+            prev_values,
+            breakdown,
         }
     }
 
@@ -32,34 +85,29 @@ impl CPUUsage {
         self.current_cpu
     }
 
-    pub fn clear_usage(&mut self) {
-        if self.usage.len() >= 300 {
-            self.usage.clear();
-        } 
+    pub fn get_breakdown(&self) -> CpuBreakdown {
+        self.breakdown
     }
 
     pub fn add_cpu_data(&mut self) -> io::Result<()>{
-        self.clear_usage();
         let new_cpu_data = self.get_cpu_info()?;
-        let time_to_add = match self.usage.last() {
-             Some(x) =>  x.0 + 1.0,
-             None => 1.0
-        };
-        self.usage.push((time_to_add, new_cpu_data));
+        self.usage.push(snapshot::now_millis(), new_cpu_data);
         Ok(())
     }
 
-    pub fn get_usage(&self) -> &Vec<(f64, f64)> {
-        self.usage.as_ref()
+    /// The most recent samples as chart points, numbered from 1.
+    pub fn get_usage(&self) -> Vec<(f64, f64)> {
+        chart_points(&self.usage)
+    }
+
+    pub fn get_history(&self) -> &TimeSeries {
+        &self.usage
     }
 
     pub fn get_cpu_info(&mut self) -> Result<f64, io::Error> {
         let cpu_file_path = Path::new("/proc/stat");
         let cpu_file = fs::read_to_string(cpu_file_path)?;
-        let cpu_info = match cpu_file.lines().next() {
-            Some(i) => i,
-            None => "File Error",
-        };
+        let cpu_info = cpu_file.lines().next().unwrap_or("File Error");
         let mut cpu_vec: Vec<&str> = cpu_info
             .split(' ')
             .collect();
@@ -70,10 +118,33 @@ impl CPUUsage {
             .collect();
 
         let cpu_usage = self.calculate_current_cpu_usage(&cpu_values);
+        self.calculate_breakdown(&cpu_values);
         Ok(cpu_usage)
     }
 
-    fn calculate_current_cpu_usage(&mut self, cpu_vec: &Vec<f64>) -> f64 {
+    fn calculate_breakdown(&mut self, cpu_vec: &[f64]) {
+        if self.prev_values.is_empty() {
+            self.prev_values = cpu_vec.to_vec();
+        }
+        let diffs: Vec<f64> = (0..8)
+            .map(|i| cpu_vec.get(i).unwrap_or(&0.0) - self.prev_values.get(i).unwrap_or(&0.0))
+            .collect();
+        let total: f64 = diffs.iter().sum();
+        let percent = |diff: f64| if total > 0.0 { diff * 100.0 / total } else { 0.0 };
+        self.breakdown = CpuBreakdown {
+            user: percent(diffs[0]),
+            nice: percent(diffs[1]),
+            system: percent(diffs[2]),
+            idle: percent(diffs[3]),
+            iowait: percent(diffs[4]),
+            irq: percent(diffs[5]),
+            softirq: percent(diffs[6]),
+            steal: percent(diffs[7]),
+        };
+        self.prev_values = cpu_vec.to_vec();
+    }
+
+    fn calculate_current_cpu_usage(&mut self, cpu_vec: &[f64]) -> f64 {
         let current_usage = cpu_vec[0] + cpu_vec[2];
         let current_time = cpu_vec[0] + cpu_vec[2] + cpu_vec[3];
         if self.prev_time == 0.0 && self.prev_usage == 0.0 {
@@ -89,37 +160,59 @@ impl CPUUsage {
     }
 }
 
-#[derive(Debug)]
+impl Default for CPUUsage {
+    fn default() -> CPUUsage {
+        CPUUsage::new()
+    }
+}
+
+/// Memory figures from `/proc/meminfo`, in kB.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct MemBreakdown {
+    pub total: f64,
+    pub free: f64,
+    pub available: f64,
+    pub buffers: f64,
+    pub cached: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 pub struct MemInfo {
-    usage: Vec<(f64, f64)>,
+    usage: TimeSeries,
     current_mem: f64,
+    breakdown: MemBreakdown,
 }
 
 impl MemInfo {
 
     pub fn new() -> MemInfo {
-        let usage = vec![];
+        let usage = TimeSeries::new();
         let current_mem = 0.0;
+        let breakdown = MemBreakdown::default();
         MemInfo {
             usage,
             current_mem,
+            breakdown,
         }
     }
 
-    pub fn clear_usage(&mut self) {
-        if self.usage.len() >= 300 {
-            self.usage.clear();
-        } 
+    /// The most recent samples as chart points, numbered from 1.
+    pub fn get_usage(&self) -> Vec<(f64, f64)> {
+        chart_points(&self.usage)
     }
 
-    pub fn get_usage(&self) -> &Vec<(f64, f64)> {
-        self.usage.as_ref()
+    pub fn get_history(&self) -> &TimeSeries {
+        &self.usage
     }
 
     pub fn get_current_mem(&self) -> f64 {
         self.current_mem
     }
 
+    pub fn get_breakdown(&self) -> MemBreakdown {
+        self.breakdown
+    }
+
     
     pub fn get_mem_info(&mut self) -> Result<f64, io::Error> {
         let mem_file_path = Path::new("/proc/meminfo");
@@ -134,18 +227,20 @@ impl MemInfo {
         let mem_free = extract_kb_info(mem_free_line);
         let percentage_used = 100.0 - (mem_free / mem_total * 100.0);
         self.current_mem = percentage_used;
+        self.breakdown = MemBreakdown {
+            total: mem_total,
+            free: mem_free,
+            available: get_meminfo_value(&mem_file, "MemAvailable"),
+            buffers: get_meminfo_value(&mem_file, "Buffers"),
+            cached: get_meminfo_value(&mem_file, "Cached"),
+        };
         Ok(percentage_used)
 
     }
 
     pub fn add_mem_data(&mut self) -> io::Result<()>{
-        self.clear_usage();
         let new_mem_data = self.get_mem_info()?;
-        let time_to_add = match self.usage.last() {
-             Some(x) =>  x.0 + 1.0,
-             None => 1.0
-        };
-        self.usage.push((time_to_add, new_mem_data));
+        self.usage.push(snapshot::now_millis(), new_mem_data);
         Ok(())
     }
 
@@ -153,7 +248,48 @@ impl MemInfo {
 
 }
 
-#[derive(Debug, Clone)]
+impl Default for MemInfo {
+    fn default() -> MemInfo {
+        MemInfo::new()
+    }
+}
+
+/// System load averages over 1, 5 and 15 minutes from `/proc/loadavg`.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct LoadAverage {
+    pub one: f64,
+    pub five: f64,
+    pub fifteen: f64,
+}
+
+/// Where settings are kept: `system_info` under `$XDG_CONFIG_HOME`, or under
+/// `~/.config` when that is not set.
+pub fn config_dir() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+    Some(dir.join("system_info"))
+}
+
+pub fn get_load_average() -> Result<LoadAverage, io::Error> {
+    let load_file = fs::read_to_string(Path::new("/proc/loadavg"))?;
+    let values: Vec<f64> = load_file
+        .split_whitespace()
+        .take(3)
+        .map(|x| x.parse().unwrap_or(0.0))
+        .collect();
+    if values.len() < 3 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed /proc/loadavg"));
+    }
+    Ok(LoadAverage {
+        one: values[0],
+        five: values[1],
+        fifteen: values[2],
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Process {
     pub pid: i32,
     pub process_name: String,
@@ -164,6 +300,26 @@ pub struct Process {
     pub mem_percent: f64,
     pub cpu_percent: f64,
     pub rss: f64,
+    /// Kernel scheduling priority, field 18 of `stat`.
+    #[serde(default)]
+    pub priority: i64,
+    /// Nice value, field 19 of `stat`.
+    #[serde(default)]
+    pub nice: i64,
+    /// CPUs the process may run on, `Cpus_allowed_list` of `status`.
+    #[serde(default)]
+    pub cpus_allowed: String,
+    /// CPU the process last ran on, field 39 of `stat`.
+    #[serde(default)]
+    pub last_cpu: i32,
+    /// Scheduling policy and real time priority, fields 41 and 40 of `stat`.
+    #[serde(default)]
+    pub policy: SchedPolicy,
+    #[serde(default)]
+    pub rt_priority: u32,
+    /// Start time in ms since the Unix epoch, from field 22 of `stat`.
+    #[serde(default)]
+    pub start_time: u64,
 }
 
 impl Process {
@@ -182,6 +338,13 @@ impl Process {
             mem_percent,
             cpu_percent,
             rss,
+            priority: 0,
+            nice: 0,
+            cpus_allowed: String::new(),
+            last_cpu: 0,
+            policy: SchedPolicy::Other,
+            rt_priority: 0,
+            start_time: 0,
         }
     }
 
@@ -209,14 +372,46 @@ impl Process {
         self.rss = rss;
     }
 
+    pub fn set_priority(&mut self, priority: i64, nice: i64) {
+        self.priority = priority;
+        self.nice = nice;
+    }
+
+    pub fn set_scheduling(&mut self, last_cpu: i32, policy: SchedPolicy, rt_priority: u32) {
+        self.last_cpu = last_cpu;
+        self.policy = policy;
+        self.rt_priority = rt_priority;
+    }
+
+    pub fn set_cpus_allowed(&mut self, cpus_allowed: String) {
+        self.cpus_allowed = cpus_allowed;
+    }
+
+    pub fn set_start_time(&mut self, start_time: u64) {
+        self.start_time = start_time;
+    }
+
+    /// Milliseconds since the process started, as of `now`.
+    pub fn age(&self, now: u64) -> u64 {
+        now.saturating_sub(self.start_time)
+    }
+
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct ProcessInfo {
     processes: Vec<Process>, 
     cpu_time_diff: f64,
     current_cpu_time: f64,
     total_mem: f64,
+    sort_key: Column,
+    rss_history: RssHistory,
+    /// Whether `update` records `rss_history`, which only the live view uses.
+    track_rss: bool,
+    state_history: StateHistory,
+    /// Boot time in ms since the Unix epoch, to convert start times.
+    boot_time: u64,
+    ticks_per_second: u64,
 }
 
 
@@ -227,11 +422,22 @@ impl ProcessInfo {
         let cpu_time_diff = 0.0;
         let current_cpu_time = 0.0;
         let total_mem = get_total_mem_info()?;
+        let sort_key = Column::MemPercent;
+        let rss_history = RssHistory::new();
+        let state_history = StateHistory::new();
+        let boot_time = details::boot_time().unwrap_or(0) * 1000;
+        let ticks_per_second = details::clock_ticks_per_second();
        Ok(ProcessInfo {
             processes,
             cpu_time_diff,
             current_cpu_time,
             total_mem,
+            sort_key,
+            rss_history,
+            track_rss: false,
+            state_history,
+            boot_time,
+            ticks_per_second,
         })
     }
 
@@ -239,19 +445,49 @@ impl ProcessInfo {
         self.processes.as_ref()
     }
 
-    pub fn sort_by_cpu(&mut self) {
+    pub fn get_sort_key(&self) -> Column {
+        self.sort_key
+    }
+
+    pub fn set_sort_key(&mut self, sort_key: Column) {
+        self.sort_key = sort_key;
+        self.sort();
+    }
+
+    pub fn sort(&mut self) {
+        let sort_key = self.sort_key;
         self.processes
-            .sort_by(|a, b| b.mem_percent.partial_cmp(&a.mem_percent).unwrap());
+            .sort_by(|a, b| sort_key.compare(a, b));
     }
 
     pub fn update(&mut self, proc_path: &Path) -> Result<(), io::Error>{
         self.update_cpu_diff()?;
-        self.remove_terminated_processes(&proc_path)?;
-        self.read_dirs(&proc_path)?;
-        self.sort_by_cpu();
+        self.remove_terminated_processes(proc_path)?;
+        self.read_dirs(proc_path)?;
+        self.sort();
+        let now = snapshot::now_millis();
+        if self.track_rss {
+            self.rss_history.record(now, &self.processes);
+        }
+        self.state_history.record(now, &self.processes);
         Ok(())
     }
 
+    /// Makes `update` record the RSS of every process for leak detection.
+    pub fn set_rss_tracking(&mut self, track: bool) {
+        self.track_rss = track;
+    }
+
+    /// Recent RSS of every process, as recorded by `update` when tracking.
+    pub fn get_rss_history(&self) -> &RssHistory {
+        &self.rss_history
+    }
+
+    /// Since when each process has been in its current state.
+    pub fn get_state_history(&self) -> &StateHistory {
+        &self.state_history
+    }
+
     pub fn remove_terminated_processes(&mut self, proc_path: &Path) -> Result<(), io::Error> {
         let mut pids = vec![];
         let mut path;
@@ -266,8 +502,8 @@ impl ProcessInfo {
             }
         }
         let filtered_processes: Vec<Process> = self.processes.iter()
-            .cloned()
             .filter(|p| pids.contains(&p.pid.to_string()))
+            .cloned()
             .collect();
         self.processes = filtered_processes;
         Ok(())
@@ -307,7 +543,7 @@ impl ProcessInfo {
 
             }
             for dir in dirs.iter() {
-                self.read_dirs(&dir)?;
+                self.read_dirs(dir)?;
             }
         }
         Ok(())
@@ -325,9 +561,9 @@ impl ProcessInfo {
             rss = mem_total_vec[1].parse().unwrap();
         }
                 
-        let pid_vec = get_line_from_file(&contents, "Pid");
-        let state_vec = get_line_from_file(&contents, "State");
-        let name_vec = get_line_from_file(&contents, "Name");
+        let pid_vec = get_line_from_file(contents, "Pid");
+        let state_vec = get_line_from_file(contents, "State");
+        let name_vec = get_line_from_file(contents, "Name");
         let pid: i32 = pid_vec[1].parse().unwrap();
         let process_name = String::from(name_vec[0]);
         let state = String::from(state_vec[0]);
@@ -343,6 +579,11 @@ impl ProcessInfo {
             rss,
         );
         new_process.set_mem_percent(mem_percent);
+        let cpus_allowed = contents.lines()
+            .find(|line| line.starts_with("Cpus_allowed_list"))
+            .and_then(|line| line.split_whitespace().nth(1))
+            .unwrap_or("");
+        new_process.set_cpus_allowed(String::from(cpus_allowed));
         self.add_mem_info_to_processes(new_process)?;
         Ok(())
     }
@@ -356,8 +597,12 @@ impl ProcessInfo {
                 process.set_utime(p.utime);
                 process.set_total_time(p.total_time);
                 process.set_cpu_percent(p.cpu_percent);
-                let filtered_processes: Vec<Process> = self.processes.iter().cloned()
+                process.set_priority(p.priority, p.nice);
+                process.set_scheduling(p.last_cpu, p.policy, p.rt_priority);
+                process.set_start_time(p.start_time);
+                let filtered_processes: Vec<Process> = self.processes.iter()
                     .filter(| x| x.pid != process.pid)
+                    .cloned()
                     .collect();
                 self.processes = filtered_processes;
                 self.processes.push(process);
@@ -368,23 +613,33 @@ impl ProcessInfo {
     }
 
     pub fn get_cpu_data(&mut self, contents: &str) -> Result<(), io::Error> {
-        let contents_array: Vec<&str> = contents
-            .split_whitespace()
-            .collect();
-        let pid: i32 = contents_array[0].parse().unwrap();
-        let process_name: String = contents_array[2].replace("(", "").replace(")", "");
-        let state: String = contents_array[1].replace("(", "").replace(")", "");
-        let utime: f64 = contents_array[13].parse().unwrap();
-        let stime: f64 = contents_array[14].parse().unwrap();
+        // A process that exited while being read leaves no stat to parse.
+        let stat = match StatFields::parse(contents) {
+            Ok(stat) => stat,
+            Err(_) => return Ok(()),
+        };
+        let pid = stat.pid;
+        let state: String = stat.value(3)?;
+        let utime: f64 = stat.value(14)?;
+        let stime: f64 = stat.value(15)?;
+        let priority: i64 = stat.value(18)?;
+        let nice: i64 = stat.value(19)?;
+        let start_ticks: u64 = stat.value(22)?;
+        let last_cpu: i32 = stat.value(39)?;
+        let rt_priority: u32 = stat.value(40)?;
+        let policy = SchedPolicy::from_raw(stat.value(41)?);
         let rss = 0.0;
-        let mem_data = Process::new(
+        let mut mem_data = Process::new(
             pid,
-            process_name,
             state,
+            stat.name,
             utime,
             stime,
             rss,
         );
+        mem_data.set_priority(priority, nice);
+        mem_data.set_scheduling(last_cpu, policy, rt_priority);
+        mem_data.set_start_time(self.boot_time + start_ticks * 1000 / self.ticks_per_second);
         self.add_cpu_info_to_processes(mem_data)?;
         Ok(())
     }
@@ -406,8 +661,10 @@ impl ProcessInfo {
                 process.set_cpu_percent(percent);
                 process.set_rss(p.rss);
                 process.set_mem_percent(p.mem_percent);
-                let filtered_processes: Vec<Process> = self.processes.iter().cloned()
+                process.set_cpus_allowed(p.cpus_allowed.clone());
+                let filtered_processes: Vec<Process> = self.processes.iter()
                     .filter(| x| x.pid != process.pid)
+                    .cloned()
                     .collect();
                 self.processes = filtered_processes;
                 self.processes.push(process);
@@ -417,6 +674,10 @@ impl ProcessInfo {
         Ok(())
     }
 
+    pub fn get_total_mem(&self) -> f64 {
+        self.total_mem
+    }
+
     pub fn get_cpu_info(&mut self) -> Result<f64, io::Error>{
         let mut cpu_time = 0.0;
         let cpu_vec = create_cpu_vector()?;
@@ -427,13 +688,19 @@ impl ProcessInfo {
     }
 }
 
+fn chart_points(series: &TimeSeries) -> Vec<(f64, f64)> {
+    series.tiers()[0]
+        .samples()
+        .iter()
+        .enumerate()
+        .map(|(i, sample)| ((i + 1) as f64, sample.value))
+        .collect()
+}
+
 pub fn create_cpu_vector() -> Result<Vec<f64>, io::Error> {
     let cpu_file_path = Path::new("/proc/stat");
     let cpu_file = fs::read_to_string(cpu_file_path)?;
-    let cpu_info = match cpu_file.lines().next() {
-        Some(i) => i,
-        None => "File Error",
-     };
+    let cpu_info = cpu_file.lines().next().unwrap_or("File Error");
     let mut cpu_vec: Vec<&str> = cpu_info
         .split(' ')
         .collect();
@@ -455,6 +722,16 @@ pub fn extract_kb_info(line: Vec<&str>) -> f64 {
         kb    
 }
 
+/// Returns the kB value of the `/proc/meminfo` entry named `key`, or 0 when
+/// the kernel does not report it.
+pub fn get_meminfo_value(mem_file: &str, key: &str) -> f64 {
+    mem_file.lines()
+        .find(|line| line.split(':').next() == Some(key))
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(0.0)
+}
+
 pub fn get_total_mem_info() -> Result<f64, io::Error> {
         let mem_file_path = Path::new("/proc/meminfo");
         let mem_file = fs::read_to_string(mem_file_path)?;
@@ -1,126 +1,613 @@
+mod chart;
+mod cores;
+mod detail_pane;
+mod query;
+mod renice;
+mod settings;
+mod setup;
+mod signal_dialog;
+mod ui;
 mod util;
 
-use std::io;
-use std::path::Path;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::sync::{Arc, Mutex};
 use std::{thread, time};
-use system_info::{CPUUsage, MemInfo, ProcessInfo};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
+use regex::Regex;
+use system_info::alerts::{Actions as AlertActions, AlertConfig, AlertEngine};
+use system_info::batch::{self, BatchOptions};
+use system_info::columns::{self, Column};
+use system_info::csv_export::CsvExporter;
+use system_info::daemon::{self, Client, SharedState};
+use system_info::diagnostics::{self, Diagnosis};
+use system_info::filter::{self, ProcessFilter};
+use system_info::fleet::{FleetConfig, Host, HostConfig, Status};
+use system_info::format::Format;
+use system_info::exporter::{self, ProcessSelection};
+use system_info::history::Sample;
+use system_info::leaks::{self, LeakConfig, LeakSuspect};
+use system_info::pins::{self, PinTarget, Pins};
+use system_info::record::{Recorder, Recording, Replay};
+use system_info::remote;
+use system_info::snapshot::{self, Snapshot};
+use system_info::store::{Retention, Store};
+use system_info::users;
+use system_info::wrapper::{RunSummary, Wrapped};
+use system_info::{CPUUsage, MemInfo, Process, ProcessInfo};
 use termion::event::Key;
-use termion::input::MouseTerminal;
-use termion::raw::IntoRawMode;
-use termion::screen::AlternateScreen;
-use tui::backend::TermionBackend;
-use tui::layout::{Constraint, Layout};
-use tui::style::{Color, Modifier, Style};
-use tui::widgets::{Axis, Block, Borders, Chart, Dataset, Marker, Row, Table, Widget};
-use tui::Terminal;
 
+use chart::{ChartWindow, Measure};
+use cores::CorePicker;
+use detail_pane::DetailPane;
+use renice::ReniceDialog;
+use settings::{Action, Settings};
+use setup::ColumnSetup;
+use signal_dialog::SignalDialog;
+use tui::style::Color;
+use ui::{Dialog, FleetRow, Panel, Series, Theme, View};
 use util::event::{Event, Events};
 
 fn main() -> Result<(), failure::Error> {
+    let matches = App::new("system_info")
+        .about("Displays CPU, memory and process usage")
+        .after_help("Options go before the subcommand, as in: system_info --output json snapshot")
+        .arg(Arg::with_name("batch")
+            .short("b")
+            .long("batch")
+            .help("Prints snapshots to stdout instead of starting the interactive view"))
+        .arg(Arg::with_name("json")
+            .short("j")
+            .long("json")
+            .conflicts_with("batch")
+            .help("Prints one JSON snapshot per line (NDJSON) instead of starting the interactive view"))
+        .arg(Arg::with_name("csv")
+            .long("csv")
+            .takes_value(true)
+            .value_name("FILE")
+            .conflicts_with_all(&["batch", "json"])
+            .help("Writes system metrics as CSV rows to FILE ('-' for stdout)"))
+        .arg(Arg::with_name("csv-processes")
+            .long("csv-processes")
+            .takes_value(true)
+            .value_name("FILE")
+            .conflicts_with_all(&["batch", "json"])
+            .help("Writes per-process metrics as CSV rows to FILE ('-' for stdout)"))
+        .arg(Arg::with_name("record")
+            .long("record")
+            .takes_value(true)
+            .value_name("FILE")
+            .conflicts_with_all(&["batch", "json", "csv", "csv-processes"])
+            .help("Appends a snapshot per tick to the recording FILE instead of starting the interactive view"))
+        .arg(Arg::with_name("store")
+            .long("store")
+            .takes_value(true)
+            .value_name("DB")
+            .conflicts_with_all(&["batch", "json", "csv", "csv-processes", "record"])
+            .help("Stores system and top process metrics in the SQLite database DB instead of starting the interactive view"))
+        .arg(Arg::with_name("store-top")
+            .long("store-top")
+            .takes_value(true)
+            .value_name("N")
+            .default_value("10")
+            .validator(validate_number::<usize>)
+            .help("Number of busiest and largest processes stored per snapshot"))
+        .arg(Arg::with_name("retention-raw")
+            .long("retention-raw")
+            .takes_value(true)
+            .value_name("HOURS")
+            .default_value("24")
+            .validator(validate_non_negative)
+            .help("Hours full resolution metrics are kept before being averaged per minute"))
+        .arg(Arg::with_name("retention")
+            .long("retention")
+            .takes_value(true)
+            .value_name("DAYS")
+            .default_value("30")
+            .validator(validate_non_negative)
+            .help("Days stored metrics are kept"))
+        .arg(Arg::with_name("replay")
+            .long("replay")
+            .takes_value(true)
+            .value_name("FILE")
+            .conflicts_with_all(&["batch", "json", "csv", "csv-processes", "record", "store"])
+            .help("Plays back a recording in the interactive view"))
+        .arg(Arg::with_name("exporter")
+            .long("exporter")
+            .conflicts_with_all(&["batch", "json", "csv", "csv-processes", "record", "store", "replay"])
+            .help("Serves Prometheus metrics over HTTP at /metrics on localhost"))
+        .arg(Arg::with_name("daemon")
+            .long("daemon")
+            .conflicts_with_all(&["batch", "json", "csv", "csv-processes", "record", "store", "replay", "exporter"])
+            .help("Collects in the background and serves interactive views attached with --attach"))
+        .arg(Arg::with_name("attach")
+            .long("attach")
+            .conflicts_with_all(&["batch", "json", "csv", "csv-processes", "record", "store", "replay", "exporter", "daemon"])
+            .help("Shows the interactive view for a running --daemon instead of collecting locally"))
+        .arg(Arg::with_name("agent")
+            .long("agent")
+            .conflicts_with_all(&["batch", "json", "csv", "csv-processes", "record", "store", "replay", "exporter", "daemon", "attach"])
+            .help("Collects in the background and streams snapshots to --remote clients over TCP"))
+        .arg(Arg::with_name("listen")
+            .long("listen")
+            .takes_value(true)
+            .value_name("ADDRESS")
+            .default_value("127.0.0.1:9102")
+            .help("Address the agent listens on; addresses other than loopback need --token or --token-file"))
+        .arg(Arg::with_name("remote")
+            .long("remote")
+            .takes_value(true)
+            .value_name("HOST[:PORT]")
+            .conflicts_with_all(&["batch", "json", "csv", "csv-processes", "record", "store", "replay", "exporter", "daemon", "attach", "agent"])
+            .help("Shows the interactive view for the agent at HOST (default port 9102)"))
+        .arg(Arg::with_name("fleet")
+            .long("fleet")
+            .takes_value(true)
+            .value_name("FILE")
+            .conflicts_with_all(&["batch", "json", "csv", "csv-processes", "record", "store", "replay", "exporter", "daemon", "attach", "agent", "remote"])
+            .help("Shows an overview of the agents listed in the TOML file FILE"))
+        .arg(Arg::with_name("token")
+            .long("token")
+            .takes_value(true)
+            .value_name("TOKEN")
+            .help("Shared token the agent requires and --remote presents"))
+        .arg(Arg::with_name("token-file")
+            .long("token-file")
+            .takes_value(true)
+            .value_name("FILE")
+            .conflicts_with("token")
+            .help("Reads the shared token from FILE, keeping it out of the process list"))
+        .arg(Arg::with_name("alerts")
+            .long("alerts")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Evaluates the alert rules in the TOML file FILE every tick"))
+        .arg(Arg::with_name("leak-window")
+            .long("leak-window")
+            .takes_value(true)
+            .value_name("SECONDS")
+            .default_value("300")
+            .validator(|seconds| match seconds.parse::<u64>() {
+                Ok(seconds) if seconds <= leaks::MAX_HISTORY / 1000 => Ok(()),
+                Ok(_) => Err(format!("the RSS history only goes back {} seconds", leaks::MAX_HISTORY / 1000)),
+                Err(_) => Err(format!("'{}' is not a valid number", seconds)),
+            })
+            .help("Window over which RSS growth is fitted for the suspected leaks panel"))
+        .arg(Arg::with_name("leak-slope")
+            .long("leak-slope")
+            .takes_value(true)
+            .value_name("KIB_PER_MIN")
+            .default_value("1024")
+            .validator(validate_non_negative)
+            .help("RSS growth rate above which a process is listed as a suspected leak"))
+        .arg(Arg::with_name("pin")
+            .long("pin")
+            .takes_value(true)
+            .value_name("LIST")
+            .validator(|list| pins::parse_pins(&list).map(|_| ()))
+            .help("Comma separated PIDs or process names whose CPU and memory are charted in the interactive view"))
+        .arg(Arg::with_name("socket")
+            .long("socket")
+            .takes_value(true)
+            .value_name("PATH")
+            .help("Unix socket used by --daemon and --attach (default: $XDG_RUNTIME_DIR/system_info.sock)"))
+        .arg(Arg::with_name("port")
+            .long("port")
+            .takes_value(true)
+            .value_name("PORT")
+            .default_value("9101")
+            .validator(validate_number::<u16>)
+            .help("Local port used by the exporter"))
+        .arg(Arg::with_name("top")
+            .long("top")
+            .takes_value(true)
+            .value_name("N")
+            .default_value("10")
+            .validator(validate_number::<usize>)
+            .help("Number of busiest processes the exporter reports"))
+        .arg(Arg::with_name("process-names")
+            .long("process-names")
+            .takes_value(true)
+            .value_name("LIST")
+            .help("Comma separated process names the exporter reports instead of the busiest ones"))
+        .arg(Arg::with_name("iterations")
+            .short("n")
+            .long("iterations")
+            .takes_value(true)
+            .value_name("N")
+            .validator(validate_number::<usize>)
+            .help("Number of snapshots to write in batch, JSON, CSV or record mode (default: unlimited)"))
+        .arg(Arg::with_name("interval")
+            .short("d")
+            .long("interval")
+            .alias("delay")
+            .takes_value(true)
+            .value_name("SECONDS")
+            .validator(|seconds| {
+                let seconds = seconds.parse().map_err(|_| format!("'{}' is not a valid number", seconds))?;
+                settings::parse_interval(seconds).map(|_| ())
+            })
+            .help("Seconds between refreshes and snapshots (default: 1)"))
+        .arg(Arg::with_name("filter")
+            .short("f")
+            .long("filter")
+            .takes_value(true)
+            .value_name("REGEX")
+            .validator(|pattern| Regex::new(&pattern).map(|_| ()).map_err(|e| e.to_string()))
+            .help("Only shows processes whose name matches REGEX"))
+        .arg(Arg::with_name("pids")
+            .short("p")
+            .long("pid")
+            .takes_value(true)
+            .value_name("LIST")
+            .validator(|list| filter::parse_pids(&list).map(|_| ()))
+            .help("Only shows the processes with these comma separated PIDs"))
+        .arg(Arg::with_name("user")
+            .short("u")
+            .long("user")
+            .takes_value(true)
+            .value_name("USER")
+            .validator(|user| users::resolve_user(&user).map(|_| ()))
+            .help("Only shows processes owned by USER, a name or uid"))
+        .arg(Arg::with_name("output")
+            .long("output")
+            .takes_value(true)
+            .value_name("MODE")
+            .possible_values(&["tui", "batch", "json", "csv"])
+            .conflicts_with_all(&["batch", "json", "csv", "csv-processes", "record", "store", "replay", "exporter", "daemon",
+                                  "attach", "agent", "remote", "fleet"])
+            .help("Shows the interactive view or prints text, JSON or process CSV snapshots to stdout (default: tui)"))
+        .arg(Arg::with_name("config")
+            .long("config")
+            .takes_value(true)
+            .value_name("FILE")
+            .help("Reads settings from FILE instead of system_info/config.toml in $XDG_CONFIG_HOME or ~/.config"))
+        .arg(Arg::with_name("columns")
+            .short("o")
+            .long("columns")
+            .takes_value(true)
+            .value_name("LIST")
+            .validator(|list| columns::parse_columns(&list).map(|_| ()))
+            .help("Comma separated columns to show, each optionally with a width such as name:24: \
+                   pid,name,state,pri,ni,policy,rtprio,affinity,psr,start,age,utime,stime,time,rss,mem,cpu"))
+        .arg(Arg::with_name("sort")
+            .short("s")
+            .long("sort")
+            .takes_value(true)
+            .value_name("COLUMN")
+            .default_value("mem")
+            .validator(|name| name.parse::<Column>().map(|_| ()))
+            .help("Column used to sort the process table"))
+        .arg(Arg::with_name("limit")
+            .short("l")
+            .long("limit")
+            .takes_value(true)
+            .value_name("N")
+            .validator(validate_number::<usize>)
+            .help("Maximum number of processes to print in batch mode"))
+        .arg(Arg::with_name("units")
+            .long("units")
+            .takes_value(true)
+            .value_name("UNITS")
+            .possible_values(&["binary", "decimal"])
+            .default_value("binary")
+            .help("Shows sizes in binary (KiB, MiB) or decimal (kB, MB) units"))
+        .arg(Arg::with_name("precision")
+            .long("precision")
+            .takes_value(true)
+            .value_name("DECIMALS")
+            .default_value("2")
+            .validator(validate_number::<usize>)
+            .help("Decimals shown for percentages"))
+        .arg(Arg::with_name("raw")
+            .long("raw")
+            .help("Shows plain numbers in the unit they are measured in: kB, clock ticks, seconds (JSON and CSV always do)"))
+        .arg(Arg::with_name("summary-json")
+            .long("summary-json")
+            .takes_value(true)
+            .value_name("FILE")
+            .requires("command")
+            .help("Writes the summary of a wrapped command as JSON to FILE ('-' for stdout)"))
+        .arg(Arg::with_name("no-tui")
+            .long("no-tui")
+            .requires("command")
+            .help("Runs the wrapped command on this terminal and only prints its summary (the default when stdout is not a terminal)"))
+        .arg(Arg::with_name("command-log")
+            .long("command-log")
+            .takes_value(true)
+            .value_name("FILE")
+            .requires("command")
+            .help("Where the output of a command wrapped in the interactive view goes (default: system_info-PID.log in the temporary directory)"))
+        .arg(Arg::with_name("command")
+            .multiple(true)
+            .last(true)
+            .value_name("COMMAND")
+            .help("Runs COMMAND, shows only its process tree and prints a summary of its resource usage when it exits"))
+        .subcommand(SubCommand::with_name("snapshot")
+            .about("Prints one snapshot in the --output mode, text by default, and exits"))
+        .subcommand(SubCommand::with_name("watch")
+            .about("Opens the interactive view on the details of one process")
+            .arg(Arg::with_name("pid")
+                .required(true)
+                .validator(validate_number::<i32>)))
+        .subcommand(SubCommand::with_name("record")
+            .about("Appends a snapshot per interval to the recording FILE, like --record")
+            .arg(Arg::with_name("file")
+                .required(true)
+                .value_name("FILE")))
+        .subcommand(SubCommand::with_name("replay")
+            .about("Plays back a recording in the interactive view, like --replay")
+            .arg(Arg::with_name("file")
+                .required(true)
+                .value_name("FILE")))
+        .subcommand(SubCommand::with_name("query")
+            .about("Queries metrics stored with --store")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("system")
+                .about("System CPU, memory and load over time")
+                .args(&query::range_args()))
+            .subcommand(SubCommand::with_name("process")
+                .about("CPU and memory of one process over time")
+                .args(&query::range_args())
+                .arg(Arg::with_name("pid")
+                    .long("pid")
+                    .takes_value(true)
+                    .validator(validate_number::<i32>)
+                    .required_unless("name"))
+                .arg(Arg::with_name("name")
+                    .long("name")
+                    .takes_value(true)
+                    .conflicts_with("pid")))
+            .subcommand(SubCommand::with_name("top-mem")
+                .about("Processes with the highest peak memory")
+                .args(&query::range_args())
+                .arg(Arg::with_name("limit")
+                    .long("limit")
+                    .takes_value(true)
+                    .default_value("10")
+                    .validator(validate_number::<usize>))))
+        .get_matches();
+
+    if let Some(query_matches) = matches.subcommand_matches("query") {
+        return query::run(query_matches);
+    }
+
+    let settings = load_settings(&matches)?;
+    let mut mode = output_mode(&matches);
+    // The output flags conflict with --record and --replay themselves.
+    let refused = match matches.subcommand_name() {
+        Some(name @ "watch") | Some(name @ "replay") => Some((name, "shows the interactive view")),
+        Some(name @ "record") => Some((name, "writes to its FILE")),
+        _ => None,
+    };
+    if let (Some((name, does)), false) = (refused, mode == OutputMode::Tui) {
+        return Err(failure::format_err!("{} {}, it cannot be used with --output", name, does));
+    }
+    if let Some(path) = file_argument(&matches, "replay") {
+        return run_replay(Path::new(path), &settings);
+    }
+    let watched: Option<i32> = matches.subcommand_matches("watch")
+        .map(|watch| watch.value_of("pid").unwrap().parse().unwrap());
+    if matches.subcommand_matches("snapshot").is_some() && mode == OutputMode::Tui {
+        mode = OutputMode::Batch;
+    }
+    let socket_path = match matches.value_of("socket") {
+        Some(path) => PathBuf::from(path),
+        None => daemon::default_socket_path(),
+    };
+    let token = match matches.value_of("token-file") {
+        Some(path) => Some(fs::read_to_string(path)?.trim().to_string()),
+        None => matches.value_of("token").map(String::from),
+    };
+    let mut alerts = match matches.value_of("alerts") {
+        Some(path) => Some(Alerts::load(Path::new(path))?),
+        None => None,
+    };
+    if matches.is_present("attach") {
+        let name = socket_path.display().to_string();
+        let host = Host::new(name, daemon::watch(move || Client::connect(&socket_path)));
+        return run_attach(host, &settings);
+    }
+    if let Some(address) = matches.value_of("remote") {
+        return run_attach(Host::connect(&HostConfig {
+            name: address.to_string(),
+            address: address.to_string(),
+            token,
+        }), &settings);
+    }
+    if let Some(path) = matches.value_of("fleet") {
+        return run_fleet(&FleetConfig::load(Path::new(path))?, &settings);
+    }
+    if let Some(command) = matches.values_of("command") {
+        return run_wrapper(&matches, &settings, command.map(String::from).collect());
+    }
     let mut process_info = ProcessInfo::new()?;
+    process_info.set_sort_key(settings.sort);
     let proc_path = Path::new("/proc/");
-    process_info.update(&proc_path)?;
-    let second = time::Duration::from_millis(1000);
-    thread::sleep(second);
-    process_info.update(&proc_path)?;
-    let events = Events::new();
     let mut cpu_usage = CPUUsage::new();
     let mut mem_info = MemInfo::new();
-    let stdout = io::stdout().into_raw_mode()?;
-    let stdout = MouseTerminal::from(stdout);
-    let stdout = AlternateScreen::from(stdout);
-    let backend = TermionBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
-    terminal.hide_cursor()?;
+    cpu_usage.get_cpu_info()?;
+    process_info.update(proc_path)?;
+    let second = time::Duration::from_millis(1000);
+    thread::sleep(second);
+    process_info.update(proc_path)?;
+    if matches.is_present("exporter") {
+        return run_exporter(&matches, &settings, cpu_usage, mem_info, process_info);
+    }
+    if matches.is_present("daemon") || matches.is_present("agent") {
+        return run_daemon(&matches, &settings, &socket_path, token, alerts, cpu_usage, mem_info, process_info);
+    }
+    let headless = mode != OutputMode::Tui
+        || file_argument(&matches, "record").is_some()
+        || matches.is_present("store");
+    if headless {
+        return run_batch(&matches, &settings, mode, alerts.as_mut(), &mut cpu_usage, &mut mem_info, &mut process_info);
+    }
+    process_info.set_rss_tracking(true);
+    let leak_config = LeakConfig {
+        window: matches.value_of("leak-window").unwrap().parse::<u64>().unwrap() * 1000,
+        min_slope: matches.value_of("leak-slope").unwrap().parse::<f64>().unwrap() / 60.0,
+        ..LeakConfig::default()
+    };
+    let mut panel = settings.panel;
+    let mut selected = 0;
+    let mut detail: Option<DetailPane> = match watched {
+        Some(pid) => match process_info.get_processes().iter().find(|process| process.pid == pid) {
+            Some(process) => Some(DetailPane::open(proc_path, process, process_info.get_rss_history())),
+            None => return Err(failure::format_err!("no process with PID {}", pid)),
+        },
+        None => None,
+    };
+    let mut popup: Option<Popup> = None;
+    let keys = &settings.keys;
+    let mut format = settings.format;
+    let mut table_columns = settings.columns.clone();
+    let mut pins = Pins::new(match matches.value_of("pin") {
+        Some(list) => pins::parse_pins(list).unwrap(),
+        None => vec![],
+    });
+    pins.record(snapshot::now_millis(), process_info.get_processes());
+    // The table lists only what the filter asks for; everything else sees
+    // every process.
+    let filter = process_filter(&matches);
+    let filtered = |process_info: &ProcessInfo| {
+        let mut rows = process_info.get_processes().clone();
+        filter.retain(proc_path, &mut rows);
+        rows
+    };
+    let mut rows = filtered(&process_info);
+    let events = Events::with_config(settings.event_config());
+    let mut terminal = ui::open_terminal()?;
+    let mut window = ChartWindow::new();
+    let title = format!(
+        "CPU/Memory [1-4] zoom [[/]] pan [f] freeze [{}/{}] pin PID/name [{}] renice [{}] affinity [{}] signal \
+         [{}] leaks [{}] diagnostics [{}] units [{}] raw [{}] columns [{}] details",
+        keys.label(Action::PinPid),
+        keys.label(Action::PinName),
+        keys.label(Action::Renice),
+        keys.label(Action::Affinity),
+        keys.label(Action::Signal),
+        keys.label(Action::Leaks),
+        keys.label(Action::Diagnostics),
+        keys.label(Action::Units),
+        keys.label(Action::Raw),
+        keys.label(Action::Columns),
+        keys.label(Action::Details),
+    );
     loop {
-        terminal.draw(|mut f| {
-            let chunks = Layout::default()
-                .constraints([Constraint::Length(20), Constraint::Min(0)].as_ref())
-                .split(f.size());
-            Chart::default()
-                .block(
-                    Block::default()
-                        .title("CPU/Memory")
-                        .title_style(Style::default().fg(Color::Cyan).modifier(Modifier::BOLD))
-                        .borders(Borders::ALL),
-                )
-                .x_axis(
-                    Axis::default()
-                        .title("Seconds")
-                        .style(Style::default().fg(Color::Gray))
-                        .labels_style(Style::default().modifier(Modifier::ITALIC))
-                        .bounds([0.0, 300.0])
-                        .labels(&["0", "50", "100", "150", "200", "250", "300"]),
-                )
-                .y_axis(
-                    Axis::default()
-                        .title("%")
-                        .style(Style::default().fg(Color::Gray))
-                        .labels_style(Style::default().modifier(Modifier::ITALIC))
-                        .bounds([0.0, 100.0])
-                        .labels(&["0", "20", "40", "60", "80", "100"]),
-                )
-                .datasets(&[
-                    Dataset::default()
-                        .name(&format!("CPU {:.2}%", cpu_usage.get_current_cpu()))
-                        .marker(Marker::Dot)
-                        .style(Style::default().fg(Color::Cyan))
-                        .data(&cpu_usage.get_usage()[..]),
-                    Dataset::default()
-                        .name(&format!("Memory {:.2}%", mem_info.get_current_mem()))
-                        .marker(Marker::Dot)
-                        .style(Style::default().fg(Color::Magenta))
-                        .data(&mem_info.get_usage()[..]),
-                ])
-                .render(&mut f, chunks[0]);
-
-            // let selected_style = Style::default().fg(Color::Yellow).modifier(Modifier::BOLD);
-            let normal_style = Style::default().fg(Color::White);
-            let header = [
-                "PID",
-                "Process Name",
-                "State",
-                "UTime",
-                "STime",
-                "Total Time",
-                "RSS",
-                "Memory %",
-                "CPU %",
-            ];
-            let rows = process_info
-                .get_processes()
-                .iter()
-                .enumerate()
-                .map(|(_i, process)| {
-                    let process_vec: Vec<String> = vec![
-                        process.pid.to_string(),
-                        process.process_name.clone(),
-                        process.state.clone(),
-                        process.utime.to_string(),
-                        process.stime.to_string(),
-                        process.total_time.to_string(),
-                        process.rss.to_string(),
-                        format!("{:.2}", process.mem_percent),
-                        format!("{:.2}", process.cpu_percent),
-                    ];
-                    Row::StyledData(process_vec.into_iter(), normal_style)
-                    // if i == app.selected {
-                    //
-                    // } else {
-                    //      Row::StyledData(item.into_iter(), normal_style)
-                    // }
-                });
-            Table::new(header.into_iter(), rows)
-                .block(Block::default().borders(Borders::ALL).title("Processes"))
-                .widths(&[20, 20, 20, 20, 20, 20, 20, 20, 20])
-                .render(&mut f, chunks[1]);
-        })?;
+        let now = snapshot::now_millis();
+        if let Some(ref pane) = detail {
+            ui::draw_details(&mut terminal, &pane.view(&format), &settings.theme)?;
+        } else {
+            let (start, end) = (window.start(now), window.end(now));
+            let mut series = system_series(
+                &window,
+                now,
+                (&cpu_usage.get_history().range(start, end), cpu_usage.get_current_cpu()),
+                (&mem_info.get_history().range(start, end), mem_info.get_current_mem()),
+                &format,
+                &settings.theme,
+            );
+            series.extend(pin_series(&window, now, &pins, &format));
+            let mut view = View::new(title.clone(), &window, now, series, &rows);
+            settings.apply_to(&mut view);
+            if let Some(ref alerts) = alerts {
+                view.alerts = alerts.banner();
+            }
+            view.format = format;
+            view.columns = table_columns.clone();
+            view.panel = match panel {
+                PanelMode::Processes => None,
+                PanelMode::Leaks => {
+                    let suspects = leaks::find_suspects(process_info.get_rss_history(), &leak_config, now);
+                    Some(leak_panel(&suspects, &leak_config, &format))
+                }
+                PanelMode::Diagnostics => {
+                    let processes = process_info.get_processes();
+                    let states = process_info.get_state_history();
+                    Some(diagnostics_panel(&diagnostics::diagnose(proc_path, processes, states, now)))
+                }
+            };
+            view.selected = Some(selected);
+            view.dialog = popup.as_ref().map(Popup::view);
+            ui::draw(&mut terminal, &view)?;
+        }
         match events.next()? {
+            // The quit key quits even from dialogs.
+            Event::Input(input) if keys.action(input) == Some(Action::Quit) => {
+                println!("quit");
+                break;
+            }
+            Event::Input(input) if popup.is_some() => {
+                if !popup.as_mut().unwrap().handle_key(input, proc_path) {
+                    if let Some(Popup::Columns(setup)) = popup.take() {
+                        table_columns = setup.applied().unwrap_or(table_columns);
+                    }
+                }
+            }
+            Event::Input(input) if detail.is_some() => {
+                if !detail.as_mut().unwrap().handle_key(input, proc_path) {
+                    detail = None;
+                }
+            }
             Event::Input(input) => {
-                if input == Key::Char('q') {
-                    println!("quit");
-                    break;
+                let processes = &rows;
+                let last = processes.len().saturating_sub(1);
+                // Row actions only apply while the process table is shown.
+                let shown = match panel {
+                    PanelMode::Processes => processes.get(selected),
+                    _ => None,
+                };
+                match keys.action(input) {
+                    Some(Action::Leaks) => panel = panel.toggle(PanelMode::Leaks),
+                    Some(Action::Units) => format.units = format.units.toggle(),
+                    Some(Action::Raw) => format.raw = !format.raw,
+                    Some(Action::Columns) => {
+                        popup = Some(Popup::Columns(ColumnSetup::open(&table_columns, settings.path.clone())));
+                    }
+                    Some(Action::Diagnostics) => panel = panel.toggle(PanelMode::Diagnostics),
+                    Some(Action::Up) => selected = selected.saturating_sub(1),
+                    Some(Action::Down) => selected = (selected + 1).min(last),
+                    Some(Action::PageUp) => selected = selected.saturating_sub(PAGE_ROWS),
+                    Some(Action::PageDown) => selected = (selected + PAGE_ROWS).min(last),
+                    Some(action @ Action::PinPid) | Some(action @ Action::PinName) => {
+                        if let Some(process) = shown {
+                            let target = if action == Action::PinPid {
+                                PinTarget::Pid(process.pid)
+                            } else {
+                                PinTarget::Name(process.process_name.clone())
+                            };
+                            if pins.toggle(target) {
+                                pins.record(now, process_info.get_processes());
+                            }
+                        }
+                    }
+                    Some(Action::Renice) => {
+                        if let Some(process) = shown {
+                            popup = Some(Popup::Renice(ReniceDialog::open(proc_path, process)));
+                        }
+                    }
+                    Some(Action::Affinity) => {
+                        if let Some(process) = shown {
+                            popup = Some(Popup::Cores(CorePicker::open(proc_path, process)));
+                        }
+                    }
+                    Some(Action::Signal) => {
+                        if let Some(process) = shown {
+                            popup = Some(Popup::Signal(SignalDialog::open(proc_path, process)));
+                        }
+                    }
+                    Some(Action::Details) => {
+                        if let Some(process) = shown {
+                            detail = Some(DetailPane::open(proc_path, process, process_info.get_rss_history()));
+                        }
+                    }
+                    Some(Action::Quit) | None => {
+                        window.handle_key(input, now, cpu_usage.get_history().oldest());
+                    }
                 }
             }
             Event::Tick => {
@@ -132,12 +619,836 @@ fn main() -> Result<(), failure::Error> {
                     eprintln!("Application error: {}", e);
                     process::exit(1);
                 }
-                if let Err(e) = process_info.update(&proc_path) {
+                if let Err(e) = process_info.update(proc_path) {
                     eprintln!("Application error: {}", e);
                     process::exit(1);
                 }
+                rows = filtered(&process_info);
+                if let Some(ref mut alerts) = alerts {
+                    match Snapshot::capture(&cpu_usage, &mem_info, &process_info) {
+                        Ok(snapshot) => alerts.check(&snapshot),
+                        Err(e) => alerts.action_error = Some(e.to_string()),
+                    }
+                }
+                if let Some(ref mut pane) = detail {
+                    pane.refresh(proc_path, process_info.get_processes());
+                }
+                pins.record(snapshot::now_millis(), process_info.get_processes());
             }
         }
     }
     Ok(())
 }
+
+/// The system CPU and memory lines, each given as its samples and current
+/// value.
+fn system_series(
+    window: &ChartWindow,
+    now: u64,
+    cpu: (&[Sample], f64),
+    mem: (&[Sample], f64),
+    format: &Format,
+    theme: &Theme,
+) -> Vec<Series> {
+    vec![
+        Series {
+            name: format!("CPU {}%", format.percent(cpu.1)),
+            color: theme.cpu,
+            points: window.points(cpu.0, now),
+            measure: Measure::Percent,
+        },
+        Series {
+            name: format!("Memory {}%", format.percent(mem.1)),
+            color: theme.memory,
+            points: window.points(mem.0, now),
+            measure: Measure::Percent,
+        },
+    ]
+}
+
+/// A dialog open over the live view, acting on the selected process.
+enum Popup {
+    Renice(ReniceDialog),
+    Cores(CorePicker),
+    Signal(SignalDialog),
+    Columns(ColumnSetup),
+}
+
+impl Popup {
+
+    /// Returns false when the dialog should close.
+    fn handle_key(&mut self, key: Key, proc_path: &Path) -> bool {
+        match self {
+            Popup::Renice(dialog) => dialog.handle_key(key, proc_path),
+            Popup::Cores(picker) => picker.handle_key(key, proc_path),
+            Popup::Signal(dialog) => dialog.handle_key(key, proc_path),
+            Popup::Columns(setup) => setup.handle_key(key),
+        }
+    }
+
+    fn view(&self) -> Dialog {
+        match self {
+            Popup::Renice(dialog) => dialog.view(),
+            Popup::Cores(picker) => picker.view(),
+            Popup::Signal(dialog) => dialog.view(),
+            Popup::Columns(setup) => setup.view(),
+        }
+    }
+}
+
+/// Rows the selection moves on page up and page down.
+const PAGE_ROWS: usize = 10;
+
+/// What the live view shows below the chart.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum PanelMode {
+    Processes,
+    Leaks,
+    Diagnostics,
+}
+
+impl PanelMode {
+
+    /// Switches to `mode`, or back to the process table if already there.
+    fn toggle(self, mode: PanelMode) -> PanelMode {
+        if self == mode { PanelMode::Processes } else { mode }
+    }
+}
+
+fn leak_panel(suspects: &[LeakSuspect], config: &LeakConfig, format: &Format) -> Panel {
+    Panel {
+        title: format!(
+            "Suspected leaks (over {}s, above {:.0} KiB/min) [L] processes",
+            config.window / 1000,
+            config.min_slope * 60.0,
+        ),
+        header: vec!["PID", "Process Name", "RSS", "Growth", "Trend", "Fit (r²)", "Observed"],
+        widths: vec![8, 20, 12, 14, 10, 10, 10],
+        rows: suspects.iter()
+            .map(|suspect| vec![
+                suspect.pid.to_string(),
+                suspect.name.clone(),
+                format.kilobytes(suspect.rss),
+                format.rate(suspect.slope * 1024.0),
+                String::from(if suspect.monotonic { "steady" } else { "linear" }),
+                format!("{:.2}", suspect.r_squared),
+                format.duration(suspect.span / 1000),
+            ])
+            .collect(),
+    }
+}
+
+fn diagnostics_panel(diagnoses: &[Diagnosis]) -> Panel {
+    Panel {
+        title: String::from("Zombies and uninterruptible sleep [D] processes"),
+        header: vec!["PID", "Process Name", "State", "For", "Parent", "Wait Channel", "Kernel Stack"],
+        widths: vec![8, 20, 6, 10, 28, 24, 60],
+        rows: diagnoses.iter()
+            .map(|diagnosis| vec![
+                diagnosis.pid.to_string(),
+                diagnosis.name.clone(),
+                diagnosis.state.clone(),
+                format_elapsed(diagnosis.duration),
+                match (diagnosis.parent_pid, &diagnosis.parent_name) {
+                    (Some(pid), Some(name)) => format!("{} ({})", pid, name),
+                    (Some(pid), None) => pid.to_string(),
+                    _ => String::from("-"),
+                },
+                diagnosis.wchan.clone().unwrap_or_else(|| String::from("-")),
+                match diagnosis.stack {
+                    Some(ref frames) => frames.join(" < "),
+                    None if diagnosis.state == diagnostics::UNINTERRUPTIBLE => String::from("(unreadable)"),
+                    None => String::from("-"),
+                },
+            ])
+            .collect(),
+    }
+}
+
+/// Formats a duration in ms as `1h02m03s`, `2m03s` or `3s`.
+fn format_elapsed(ms: u64) -> String {
+    let seconds = ms / 1000;
+    if seconds >= 3_600 {
+        format!("{}h{:02}m{:02}s", seconds / 3_600, seconds % 3_600 / 60, seconds % 60)
+    } else if seconds >= 60 {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{}s", seconds)
+    }
+}
+
+/// Alert rules and the actions run when they fire or resolve.
+struct Alerts {
+    engine: AlertEngine,
+    actions: AlertActions,
+    /// The last action that failed, until it is reported.
+    action_error: Option<String>,
+}
+
+impl Alerts {
+
+    fn load(path: &Path) -> io::Result<Alerts> {
+        let config = AlertConfig::load(path)?;
+        Ok(Alerts {
+            engine: AlertEngine::new(config.rules),
+            actions: config.actions,
+            action_error: None,
+        })
+    }
+
+    fn check(&mut self, snapshot: &Snapshot) {
+        for event in self.engine.evaluate(snapshot) {
+            if let Err(e) = self.actions.dispatch(&event) {
+                self.action_error = Some(format!("{}: {}", event.rule, e));
+            }
+        }
+    }
+
+    /// Banner lines for the interactive view.
+    fn banner(&self) -> Vec<String> {
+        let mut lines: Vec<String> = self.engine.active().iter()
+            .map(|(rule, message)| format!("ALERT {}: {}", rule, message))
+            .collect();
+        if let Some(ref e) = self.action_error {
+            lines.push(format!("Alert action failed: {}", e));
+        }
+        lines
+    }
+}
+
+/// Line colors for pinned processes, as (CPU, RSS) pairs.
+const PIN_COLORS: [(Color, Color); 4] = [
+    (Color::Yellow, Color::LightYellow),
+    (Color::Green, Color::LightGreen),
+    (Color::Red, Color::LightRed),
+    (Color::Blue, Color::LightBlue),
+];
+
+/// CPU and RSS lines for every pinned process. RSS goes on the chart of
+/// sizes below the percentages.
+fn pin_series(window: &ChartWindow, now: u64, pins: &Pins, format: &Format) -> Vec<Series> {
+    let (start, end) = (window.start(now), window.end(now));
+    let mut series = vec![];
+    for (pin, &(cpu_color, mem_color)) in pins.pins().iter().zip(PIN_COLORS.iter().cycle()) {
+        let cpu = pin.current_cpu.map_or(String::from("not running"), |cpu| format!("{}%", format.percent(cpu)));
+        let rss = pin.current_rss.map_or(String::from("not running"), |rss| format.kilobytes(rss));
+        series.push(Series {
+            name: format!("{} CPU {}", pin.target, cpu),
+            color: cpu_color,
+            points: window.points(&pin.cpu.range(start, end), now),
+            measure: Measure::Percent,
+        });
+        series.push(Series {
+            name: format!("{} RSS {}", pin.target, rss),
+            color: mem_color,
+            points: window.points(&pin.rss.range(start, end), now),
+            measure: Measure::Kilobytes,
+        });
+    }
+    series
+}
+
+/// Snapshots stored between two retention passes over the database.
+const STORE_MAINTENANCE_INTERVAL: usize = 600;
+
+enum Output {
+    Text(BatchOptions),
+    Json,
+    Csv(Box<CsvExporter>),
+    Record(Recorder),
+    Store(Store, Retention),
+}
+
+fn run_batch(
+    matches: &ArgMatches,
+    settings: &Settings,
+    mode: OutputMode,
+    mut alerts: Option<&mut Alerts>,
+    cpu_usage: &mut CPUUsage,
+    mem_info: &mut MemInfo,
+    process_info: &mut ProcessInfo,
+) -> Result<(), failure::Error> {
+    let iterations: Option<usize> = if matches.subcommand_matches("snapshot").is_some() {
+        Some(1)
+    } else {
+        matches.value_of("iterations").map(|n| n.parse().unwrap())
+    };
+    let mut output = if let Some(path) = file_argument(matches, "record") {
+        Output::Record(Recorder::open(Path::new(path))?)
+    } else if let Some(path) = matches.value_of("store") {
+        let top = matches.value_of("store-top").unwrap().parse().unwrap();
+        let raw_hours: f64 = matches.value_of("retention-raw").unwrap().parse().unwrap();
+        let days: f64 = matches.value_of("retention").unwrap().parse().unwrap();
+        let retention = Retention {
+            raw: (raw_hours * 3_600_000.0) as u64,
+            downsampled: (days * 86_400_000.0) as u64,
+        };
+        Output::Store(Store::open(Path::new(path), top)?, retention)
+    } else if mode == OutputMode::Json {
+        Output::Json
+    } else if mode == OutputMode::Csv {
+        let system = matches.value_of("csv").map(open_output).transpose()?;
+        let processes = match matches.value_of("csv-processes") {
+            Some(path) => Some(open_output(path)?),
+            None if system.is_none() => Some(open_output("-")?),
+            None => None,
+        };
+        Output::Csv(Box::new(CsvExporter::new(system, processes)))
+    } else {
+        Output::Text(BatchOptions {
+            columns: settings.columns.clone(),
+            limit: matches.value_of("limit").map(|n| n.parse().unwrap()),
+            format: settings.format,
+        })
+    };
+    let proc_path = Path::new("/proc/");
+    // Only what is printed is filtered; alerts and recordings see every
+    // process.
+    let filter = process_filter(matches);
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut iteration = 0;
+    while iterations.is_none_or(|n| iteration < n) {
+        if iteration > 0 {
+            thread::sleep(settings.interval);
+        }
+        cpu_usage.add_cpu_data()?;
+        mem_info.add_mem_data()?;
+        process_info.update(proc_path)?;
+        if let Some(ref mut alerts) = alerts {
+            alerts.check(&Snapshot::capture(cpu_usage, mem_info, process_info)?);
+            if let Some(e) = alerts.action_error.take() {
+                eprintln!("Alert action error: {}", e);
+            }
+        }
+        let written = match output {
+            Output::Text(ref options) => {
+                let mut rows = process_info.get_processes().clone();
+                filter.retain(proc_path, &mut rows);
+                batch::write_snapshot(&mut out, cpu_usage, mem_info, &rows, options)
+            }
+            Output::Json => {
+                Snapshot::capture(cpu_usage, mem_info, process_info)
+                    .and_then(|mut snapshot| {
+                        filter.retain(proc_path, &mut snapshot.processes);
+                        snapshot::write_ndjson(&mut out, &snapshot)
+                    })
+            }
+            Output::Csv(ref mut exporter) => {
+                Snapshot::capture(cpu_usage, mem_info, process_info)
+                    .and_then(|mut snapshot| {
+                        filter.retain(proc_path, &mut snapshot.processes);
+                        exporter.write_snapshot(&snapshot)
+                    })
+            }
+            Output::Record(ref mut recorder) => {
+                Snapshot::capture(cpu_usage, mem_info, process_info)
+                    .and_then(|snapshot| recorder.write_snapshot(&snapshot))
+            }
+            Output::Store(ref mut store, retention) => {
+                let maintained = if iteration % STORE_MAINTENANCE_INTERVAL == 0 {
+                    store.maintain(retention, snapshot::now_millis())
+                } else {
+                    Ok(())
+                };
+                maintained
+                    .and_then(|_| Snapshot::capture(cpu_usage, mem_info, process_info))
+                    .and_then(|snapshot| store.insert_snapshot(&snapshot))
+            }
+        };
+        match written {
+            Err(ref e) if e.kind() == io::ErrorKind::BrokenPipe => break,
+            result => result?,
+        }
+        iteration += 1;
+    }
+    Ok(())
+}
+
+fn run_exporter(
+    matches: &ArgMatches,
+    settings: &Settings,
+    mut cpu_usage: CPUUsage,
+    mut mem_info: MemInfo,
+    mut process_info: ProcessInfo,
+) -> Result<(), failure::Error> {
+    let port: u16 = matches.value_of("port").unwrap().parse().unwrap();
+    let interval = settings.interval;
+    let selection = match matches.value_of("process-names") {
+        Some(names) => ProcessSelection::Names(names.split(',').map(String::from).collect()),
+        None => ProcessSelection::TopByCpu(matches.value_of("top").unwrap().parse().unwrap()),
+    };
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    let metrics = Arc::new(Mutex::new(String::new()));
+    let collector_metrics = Arc::clone(&metrics);
+    thread::spawn(move || {
+        let proc_path = Path::new("/proc/");
+        loop {
+            match snapshot::collect(&mut cpu_usage, &mut mem_info, &mut process_info, proc_path) {
+                Ok(snapshot) => {
+                    *collector_metrics.lock().unwrap() = exporter::render_metrics(&snapshot, &selection);
+                }
+                Err(e) => eprintln!("Application error: {}", e),
+            }
+            thread::sleep(interval);
+        }
+    });
+    exporter::serve(listener, metrics)?;
+    Ok(())
+}
+
+/// Collects in the background for the local daemon socket or, in agent
+/// mode, for remote clients over TCP.
+#[allow(clippy::too_many_arguments)]
+fn run_daemon(
+    matches: &ArgMatches,
+    settings: &Settings,
+    socket_path: &Path,
+    token: Option<String>,
+    mut alerts: Option<Alerts>,
+    mut cpu_usage: CPUUsage,
+    mut mem_info: MemInfo,
+    mut process_info: ProcessInfo,
+) -> Result<(), failure::Error> {
+    let interval = settings.interval;
+    let state = Arc::new(SharedState::new());
+    let collector_state = Arc::clone(&state);
+    thread::spawn(move || {
+        let proc_path = Path::new("/proc/");
+        loop {
+            match snapshot::collect(&mut cpu_usage, &mut mem_info, &mut process_info, proc_path) {
+                Ok(snapshot) => {
+                    if let Some(ref mut alerts) = alerts {
+                        alerts.check(&snapshot);
+                        if let Some(e) = alerts.action_error.take() {
+                            eprintln!("Alert action error: {}", e);
+                        }
+                    }
+                    collector_state.publish(snapshot);
+                }
+                Err(e) => eprintln!("Application error: {}", e),
+            }
+            thread::sleep(interval);
+        }
+    });
+    if matches.is_present("agent") {
+        let listener = remote::bind(matches.value_of("listen").unwrap(), token.as_deref())?;
+        remote::serve(listener, state, token)?;
+    } else {
+        daemon::serve(daemon::bind(socket_path)?, state)?;
+    }
+    Ok(())
+}
+
+/// How often the attached view checks for snapshots pushed by the daemon.
+const ATTACH_POLL_INTERVAL: time::Duration = time::Duration::from_millis(100);
+
+/// Shows the interactive view for a daemon or agent, keeping what was shown
+/// while it reconnects.
+fn run_attach(mut host: Host, settings: &Settings) -> Result<(), failure::Error> {
+    let events = Events::input_only(settings.event_config());
+    let mut terminal = ui::open_terminal()?;
+    let mut window = ChartWindow::new();
+    loop {
+        host.poll();
+        let now = snapshot::now_millis();
+        draw_host(&mut terminal, &window, now, &host, settings)?;
+        if let Some(Event::Input(input)) = events.next_timeout(ATTACH_POLL_INTERVAL)? {
+            if settings.keys.action(input) == Some(Action::Quit) {
+                break;
+            }
+            window.handle_key(input, now, host.cpu_history.oldest());
+        }
+    }
+    Ok(())
+}
+
+/// Shows every host from a fleet file, one row each, with the per-host view
+/// of the selected one on enter.
+fn run_fleet(config: &FleetConfig, settings: &Settings) -> Result<(), failure::Error> {
+    let mut hosts: Vec<Host> = config.hosts.iter().map(Host::connect).collect();
+    let keys = &settings.keys;
+    let title = format!(
+        "Fleet [{}/{}] select [{}] open [{}] quit",
+        keys.label(Action::Up),
+        keys.label(Action::Down),
+        keys.label(Action::Details),
+        keys.label(Action::Quit),
+    );
+    let events = Events::input_only(settings.event_config());
+    let mut terminal = ui::open_terminal()?;
+    let mut window = ChartWindow::new();
+    let mut selected = 0;
+    let mut opened = false;
+    loop {
+        for host in hosts.iter_mut() {
+            host.poll();
+        }
+        let now = snapshot::now_millis();
+        if opened {
+            draw_host(&mut terminal, &window, now, &hosts[selected], settings)?;
+        } else {
+            let rows: Vec<FleetRow> = hosts.iter().map(|host| fleet_row(host, &settings.format)).collect();
+            let message = match hosts[selected].status {
+                Status::Disconnected(ref error) => format!("{}: {}", hosts[selected].name, error),
+                _ => String::new(),
+            };
+            ui::draw_fleet(&mut terminal, &title, &rows, selected, &message, &settings.format, &settings.theme)?;
+        }
+        let input = match events.next_timeout(ATTACH_POLL_INTERVAL)? {
+            Some(Event::Input(input)) => input,
+            _ => continue,
+        };
+        match (keys.action(input), input) {
+            (Some(Action::Quit), _) => break,
+            (_, Key::Esc) | (_, Key::Backspace) | (_, Key::Left) if opened => opened = false,
+            _ if opened => {
+                window.handle_key(input, now, hosts[selected].cpu_history.oldest());
+            }
+            (Some(Action::Up), _) => selected = selected.saturating_sub(1),
+            (Some(Action::Down), _) => selected = (selected + 1).min(hosts.len() - 1),
+            (Some(Action::Details), _) | (_, Key::Right) => {
+                opened = true;
+                window = ChartWindow::new();
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn fleet_row(host: &Host, format: &Format) -> FleetRow {
+    let snapshot = host.snapshot.as_ref();
+    let status = match host.status {
+        Status::Connecting => "connecting",
+        Status::Connected => "up",
+        Status::Disconnected(_) => "down",
+    };
+    let sparkline = host.cpu_history.tiers()[0].samples().iter()
+        .map(|sample| sample.value.max(0.0).round() as u64)
+        .collect();
+    FleetRow {
+        name: host.name.clone(),
+        status: String::from(status),
+        up: host.status == Status::Connected,
+        cpu: snapshot.map(|s| s.cpu_percent),
+        mem: snapshot.map(|s| s.mem_percent),
+        load: snapshot.map(|s| [s.load_average.one, s.load_average.five, s.load_average.fifteen]),
+        top_process: host.top_process()
+            .map_or(String::new(), |p| format!("{} ({}%)", p.process_name, format.percent(p.cpu_percent))),
+        sparkline,
+    }
+}
+
+/// The chart and process table for one followed host.
+fn draw_host(terminal: &mut ui::Screen, window: &ChartWindow, now: u64, host: &Host, settings: &Settings) -> io::Result<()> {
+    let (start, end) = (window.start(now), window.end(now));
+    let (cpu, mem) = host.snapshot.as_ref().map_or((0.0, 0.0), |s| (s.cpu_percent, s.mem_percent));
+    let series = system_series(
+        window,
+        now,
+        (&host.cpu_history.range(start, end), cpu),
+        (&host.mem_history.range(start, end), mem),
+        &settings.format,
+        &settings.theme,
+    );
+    let status = match host.status {
+        Status::Connecting => String::from("connecting"),
+        Status::Connected => String::from("connected"),
+        Status::Disconnected(ref error) => format!("disconnected: {}", error),
+    };
+    let title = format!("CPU/Memory - {} ({}) [1-4] zoom [[/]] pan [f] freeze", host.name, status);
+    let mut view = View::new(title, window, now, series, host.processes());
+    // Ages count to the host's clock, not ours.
+    if let Some(ref snapshot) = host.snapshot {
+        view.sampled = snapshot.timestamp;
+    }
+    settings.apply_to(&mut view);
+    ui::draw(terminal, &view)
+}
+
+/// Runs a wrapped command, shows its process tree until it exits, prints
+/// what it used and exits with its status.
+fn run_wrapper(matches: &ArgMatches, settings: &Settings, args: Vec<String>) -> Result<(), failure::Error> {
+    let proc_path = Path::new("/proc/");
+    let interactive = !matches.is_present("no-tui") && termion::is_tty(&io::stdout());
+    let mut command = process::Command::new(&args[0]);
+    command.args(&args[1..]);
+    // The command would scribble over the interactive view, so its output
+    // goes to a file instead.
+    let log_path = if interactive {
+        let path = match matches.value_of("command-log") {
+            Some(path) => PathBuf::from(path),
+            None => std::env::temp_dir().join(format!("system_info-{}.log", process::id())),
+        };
+        let log = File::create(&path)?;
+        command.stdin(process::Stdio::null()).stdout(log.try_clone()?).stderr(log);
+        Some(path)
+    } else {
+        None
+    };
+    let wrapped = Wrapped::spawn(command, args.clone(), proc_path)
+        .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", args[0], e)))?;
+    ignore_interrupts();
+    let summary = if interactive {
+        watch_wrapped(proc_path, &args, &wrapped, settings)?
+    } else {
+        wrapped.finish()?
+    };
+    if let Some(path) = log_path {
+        eprintln!("Command output:   {}", path.display());
+    }
+    summary.write_text(&mut io::stderr(), &settings.format)?;
+    if let Some(path) = matches.value_of("summary-json") {
+        let mut out = open_output(path)?;
+        serde_json::to_writer_pretty(&mut out, &summary)?;
+        writeln!(out)?;
+    }
+    process::exit(summary.exit_code());
+}
+
+/// Leaves SIGINT and SIGQUIT to the wrapped command, as `time` does, so the
+/// summary is still printed when it is interrupted from the terminal.
+fn ignore_interrupts() {
+    // SAFETY: SIG_IGN installs no handler code.
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_IGN);
+        libc::signal(libc::SIGQUIT, libc::SIG_IGN);
+    }
+}
+
+/// The interactive view of a wrapped command: system totals and only the
+/// processes of its tree. `q` asks the tree to stop.
+fn watch_wrapped(
+    proc_path: &Path,
+    args: &[String],
+    wrapped: &Wrapped,
+    settings: &Settings,
+) -> Result<RunSummary, failure::Error> {
+    let mut process_info = ProcessInfo::new()?;
+    let mut cpu_usage = CPUUsage::new();
+    let mut mem_info = MemInfo::new();
+    cpu_usage.get_cpu_info()?;
+    process_info.update(proc_path)?;
+    thread::sleep(time::Duration::from_millis(1000));
+    process_info.update(proc_path)?;
+    // Input goes on after quit, which stops the command rather than the view.
+    let events = Events::with_config(settings.event_config());
+    let mut terminal = ui::open_terminal()?;
+    let mut window = ChartWindow::new();
+    let (mut stopping, mut killed) = (false, false);
+    loop {
+        if let Some(summary) = wrapped.try_finish() {
+            return Ok(summary?);
+        }
+        let now = snapshot::now_millis();
+        let (start, end) = (window.start(now), window.end(now));
+        let series = system_series(
+            &window,
+            now,
+            (&cpu_usage.get_history().range(start, end), cpu_usage.get_current_cpu()),
+            (&mem_info.get_history().range(start, end), mem_info.get_current_mem()),
+            &settings.format,
+            &settings.theme,
+        );
+        let tree = wrapped.current();
+        let processes: Vec<Process> = process_info.get_processes().iter()
+            .filter(|p| tree.contains(&p.pid))
+            .cloned()
+            .collect();
+        let title = format!(
+            "{} (PID {}, {} processes) {}",
+            args.join(" "),
+            wrapped.pid(),
+            tree.len(),
+            match (stopping, killed) {
+                (false, _) => format!("[{}] stop", settings.keys.label(Action::Quit)),
+                (true, false) => format!("stopping, [{}] kill", settings.keys.label(Action::Quit)),
+                (true, true) => String::from("killing"),
+            },
+        );
+        let mut view = View::new(title, &window, now, series, &processes);
+        settings.apply_to(&mut view);
+        ui::draw(&mut terminal, &view)?;
+        match events.next()? {
+            Event::Input(input) if settings.keys.action(input) == Some(Action::Quit) => {
+                if !stopping {
+                    wrapped.terminate()?;
+                    stopping = true;
+                } else if !killed {
+                    wrapped.kill()?;
+                    killed = true;
+                }
+            }
+            Event::Input(input) => {
+                window.handle_key(input, now, cpu_usage.get_history().oldest());
+            }
+            Event::Tick => {
+                cpu_usage.add_cpu_data()?;
+                mem_info.add_mem_data()?;
+                process_info.update(proc_path)?;
+            }
+        }
+    }
+}
+
+fn run_replay(path: &Path, settings: &Settings) -> Result<(), failure::Error> {
+    let recording = Recording::open(path)?;
+    if recording.is_empty() {
+        return Err(failure::format_err!("{} contains no snapshots", path.display()));
+    }
+    let mut replay = Replay::new(recording);
+    let events = Events::input_only(settings.event_config());
+    let mut terminal = ui::open_terminal()?;
+    let mut snapshot = replay.current()?;
+    let mut shown = replay.position();
+    let mut window = ChartWindow::new();
+    loop {
+        if replay.position() != shown {
+            snapshot = replay.current()?;
+            shown = replay.position();
+        }
+        let now = replay.current_timestamp();
+        let (start, end) = (window.start(now), window.end(now));
+        let state = if replay.is_paused() {
+            String::from("paused")
+        } else {
+            format!("{}x", replay.speed())
+        };
+        let title = format!(
+            "CPU/Memory - replay {}/{} {} [space] pause [left/right] step [pgup/pgdn] seek [+/-] speed",
+            replay.position() + 1,
+            replay.len(),
+            state,
+        );
+        let series = system_series(
+            &window,
+            now,
+            (&replay.cpu_history(start, end), snapshot.cpu_percent),
+            (&replay.mem_history(start, end), snapshot.mem_percent),
+            &settings.format,
+            &settings.theme,
+        );
+        let mut view = View::new(title, &window, now, series, &snapshot.processes);
+        settings.apply_to(&mut view);
+        ui::draw(&mut terminal, &view)?;
+        let event = match replay.delay_to_next() {
+            Some(delay) => events.next_timeout(delay)?,
+            None => Some(events.next()?),
+        };
+        match event {
+            None => replay.step(1),
+            Some(Event::Input(key)) if settings.keys.action(key) == Some(Action::Quit) => break,
+            Some(Event::Input(key)) => match key {
+                Key::Char(' ') | Key::Char('p') => replay.toggle_pause(),
+                Key::Right | Key::Char('l') => replay.step(1),
+                Key::Left | Key::Char('h') => replay.step(-1),
+                Key::PageDown => replay.step(60),
+                Key::PageUp => replay.step(-60),
+                Key::Home => replay.seek(0),
+                Key::End => replay.seek(replay.len()),
+                Key::Char('+') | Key::Char('>') => replay.faster(),
+                Key::Char('-') | Key::Char('<') => replay.slower(),
+                key => {
+                    window.handle_key(key, now, Some(replay.start_timestamp()));
+                }
+            },
+            Some(Event::Tick) => {}
+        }
+    }
+    Ok(())
+}
+
+/// The settings file, from `--config` or the config directory, with what
+/// the command line sets on top.
+fn load_settings(matches: &ArgMatches) -> Result<Settings, failure::Error> {
+    let path = matches.value_of("config").map(PathBuf::from).or_else(Settings::default_path);
+    let mut settings = match path {
+        Some(ref path) if matches.is_present("config") || path.exists() => Settings::load(path)?,
+        _ => Settings::default(),
+    };
+    settings.path = path;
+    let given = |name: &str| matches.occurrences_of(name) > 0;
+    if given("sort") {
+        settings.sort = matches.value_of("sort").unwrap().parse().unwrap();
+    }
+    if given("units") {
+        settings.format.units = matches.value_of("units").unwrap().parse().unwrap();
+    }
+    if given("precision") {
+        settings.format.precision = matches.value_of("precision").unwrap().parse().unwrap();
+    }
+    if let Some(seconds) = matches.value_of("interval") {
+        settings.interval = settings::parse_interval(seconds.parse().unwrap()).unwrap();
+    }
+    settings.format.raw = matches.is_present("raw");
+    if let Some(list) = matches.value_of("columns") {
+        settings.columns = columns::parse_columns(list).unwrap();
+    }
+    Ok(settings)
+}
+
+/// How snapshots are shown.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputMode {
+    Tui,
+    Batch,
+    Json,
+    Csv,
+}
+
+/// The mode chosen with `--output` or one of the flags it stands for.
+fn output_mode(matches: &ArgMatches) -> OutputMode {
+    match matches.value_of("output") {
+        Some("batch") => OutputMode::Batch,
+        Some("json") => OutputMode::Json,
+        Some("csv") => OutputMode::Csv,
+        Some(_) => OutputMode::Tui,
+        None if matches.is_present("batch") => OutputMode::Batch,
+        None if matches.is_present("json") => OutputMode::Json,
+        None if matches.is_present("csv") || matches.is_present("csv-processes") => OutputMode::Csv,
+        None => OutputMode::Tui,
+    }
+}
+
+/// The FILE given to the `name` subcommand, or else to the `--name` option.
+fn file_argument<'a>(matches: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    matches.subcommand_matches(name)
+        .and_then(|subcommand| subcommand.value_of("file"))
+        .or_else(|| matches.value_of(name))
+}
+
+/// The processes `--filter`, `--pid` and `--user` ask for.
+fn process_filter(matches: &ArgMatches) -> ProcessFilter {
+    ProcessFilter {
+        name: matches.value_of("filter").map(|pattern| Regex::new(pattern).unwrap()),
+        pids: match matches.value_of("pids") {
+            Some(list) => filter::parse_pids(list).unwrap(),
+            None => vec![],
+        },
+        uid: matches.value_of("user").map(|user| users::resolve_user(user).unwrap()),
+    }
+}
+
+fn open_output(path: &str) -> io::Result<Box<dyn Write>> {
+    if path == "-" {
+        Ok(Box::new(io::stdout()))
+    } else {
+        Ok(Box::new(File::create(path)?))
+    }
+}
+
+/// A finite number of zero or more.
+fn validate_non_negative(value: String) -> Result<(), String> {
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() && number >= 0.0 => Ok(()),
+        Ok(_) => Err(format!("'{}' must be zero or more", value)),
+        Err(_) => Err(format!("'{}' is not a valid number", value)),
+    }
+}
+
+fn validate_number<T: std::str::FromStr>(value: String) -> Result<(), String> {
+    value.parse::<T>()
+        .map(|_| ())
+        .map_err(|_| format!("'{}' is not a valid number", value))
+}
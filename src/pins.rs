@@ -0,0 +1,175 @@
+//! CPU and RSS history of pinned processes.
+//!
+//! A pin follows either one PID or every process with a given name. Name
+//! pins sum all matching processes, so their history carries on when a
+//! service restarts under a new PID.
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::history::TimeSeries;
+use crate::Process;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PinTarget {
+    Pid(i32),
+    Name(String),
+}
+
+impl PinTarget {
+
+    pub fn matches(&self, process: &Process) -> bool {
+        match self {
+            PinTarget::Pid(pid) => process.pid == *pid,
+            PinTarget::Name(name) => &process.process_name == name,
+        }
+    }
+}
+
+impl FromStr for PinTarget {
+    type Err = String;
+
+    /// Numbers pin a PID, anything else a process name.
+    fn from_str(text: &str) -> Result<PinTarget, String> {
+        let text = text.trim();
+        if text.is_empty() {
+            return Err(String::from("empty pin"));
+        }
+        Ok(match text.parse() {
+            Ok(pid) => PinTarget::Pid(pid),
+            Err(_) => PinTarget::Name(text.to_string()),
+        })
+    }
+}
+
+impl fmt::Display for PinTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PinTarget::Pid(pid) => write!(f, "{}", pid),
+            PinTarget::Name(name) => write!(f, "{}", name),
+        }
+    }
+}
+
+/// One pinned process and its history.
+#[derive(Debug, Clone)]
+pub struct Pin {
+    pub target: PinTarget,
+    /// CPU percent, summed over matching processes.
+    pub cpu: TimeSeries,
+    /// RSS in kB, summed over matching processes.
+    pub rss: TimeSeries,
+    /// The values at the last update, or `None` if nothing matched.
+    pub current_cpu: Option<f64>,
+    pub current_rss: Option<f64>,
+}
+
+impl Pin {
+
+    pub fn new(target: PinTarget) -> Pin {
+        Pin {
+            target,
+            cpu: TimeSeries::new(),
+            rss: TimeSeries::new(),
+            current_cpu: None,
+            current_rss: None,
+        }
+    }
+
+    fn record(&mut self, timestamp: u64, processes: &[Process]) {
+        let matching: Vec<&Process> = processes.iter().filter(|p| self.target.matches(p)).collect();
+        if matching.is_empty() {
+            self.current_cpu = None;
+            self.current_rss = None;
+            return;
+        }
+        let finite = |value: f64| if value.is_finite() { value } else { 0.0 };
+        let cpu = matching.iter().map(|p| finite(p.cpu_percent)).sum();
+        let rss = matching.iter().map(|p| finite(p.rss)).sum();
+        self.cpu.push(timestamp, cpu);
+        self.rss.push(timestamp, rss);
+        self.current_cpu = Some(cpu);
+        self.current_rss = Some(rss);
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Pins {
+    pins: Vec<Pin>,
+}
+
+impl Pins {
+
+    pub fn new(targets: Vec<PinTarget>) -> Pins {
+        Pins {
+            pins: targets.into_iter().map(Pin::new).collect(),
+        }
+    }
+
+    pub fn pins(&self) -> &[Pin] {
+        &self.pins
+    }
+
+    /// Pins `target`, or unpins it if it already is. Returns whether it is
+    /// pinned now.
+    pub fn toggle(&mut self, target: PinTarget) -> bool {
+        match self.pins.iter().position(|pin| pin.target == target) {
+            Some(i) => {
+                self.pins.remove(i);
+                false
+            }
+            None => {
+                self.pins.push(Pin::new(target));
+                true
+            }
+        }
+    }
+
+    pub fn record(&mut self, timestamp: u64, processes: &[Process]) {
+        for pin in self.pins.iter_mut() {
+            pin.record(timestamp, processes);
+        }
+    }
+}
+
+/// Parses a comma separated list of PIDs and names.
+pub fn parse_pins(list: &str) -> Result<Vec<PinTarget>, String> {
+    list.split(',').map(str::parse).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(pid: i32, name: &str, cpu: f64, rss: f64) -> Process {
+        let mut process = Process::new(pid, String::from("S"), String::from(name), 0.0, 0.0, rss);
+        process.cpu_percent = cpu;
+        process
+    }
+
+    #[test]
+    fn name_pins_sum_matching_processes() {
+        let mut pins = Pins::new(parse_pins("nginx,7").unwrap());
+        pins.record(1_000, &[process(5, "nginx", 1.5, 100.0), process(6, "nginx", 2.0, 300.0), process(7, "sh", 0.5, 50.0)]);
+        let (name, pid) = (&pins.pins()[0], &pins.pins()[1]);
+        assert_eq!((name.current_cpu, name.current_rss), (Some(3.5), Some(400.0)));
+        assert_eq!((pid.current_cpu, pid.current_rss), (Some(0.5), Some(50.0)));
+
+        // A restart under a new PID carries on the name pin only.
+        pins.record(2_000, &[process(8, "nginx", 1.0, 200.0)]);
+        let (name, pid) = (&pins.pins()[0], &pins.pins()[1]);
+        assert_eq!(name.current_rss, Some(200.0));
+        assert_eq!(name.rss.range(0, 3_000).len(), 2);
+        assert_eq!((pid.current_cpu, pid.current_rss), (None, None));
+        assert_eq!(pid.rss.range(0, 3_000).len(), 1);
+    }
+
+    #[test]
+    fn toggles_pins() {
+        let mut pins = Pins::default();
+        assert!(pins.toggle(PinTarget::Name(String::from("sshd"))));
+        assert!(!pins.toggle(PinTarget::Name(String::from("sshd"))));
+        assert!(pins.pins().is_empty());
+        assert!(parse_pins("1,,2").is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! CPU and I/O scheduling priority of processes.
+//!
+//! The nice value is read and set with `getpriority`/`setpriority`; the I/O
+//! class and level with the `ioprio_get`/`ioprio_set` system calls, which
+//! libc does not wrap. Raising a priority (lowering nice, or picking the
+//! real time I/O class) needs `CAP_SYS_NICE`.
+
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// The lowest and highest nice values.
+pub const NICE_RANGE: (i32, i32) = (-20, 19);
+
+/// I/O priority levels run from 0 (highest) to 7 within a class.
+pub const IO_LEVELS: u8 = 8;
+
+const IOPRIO_CLASS_SHIFT: i32 = 13;
+const IOPRIO_WHO_PROCESS: i32 = 1;
+
+/// The nice value of `pid`.
+pub fn get_nice(pid: i32) -> io::Result<i32> {
+    // getpriority may legitimately return -1, so errno tells failures apart.
+    // SAFETY: errno is thread local and getpriority has no preconditions.
+    unsafe {
+        *libc::__errno_location() = 0;
+        let nice = libc::getpriority(libc::PRIO_PROCESS, pid as libc::id_t);
+        if nice == -1 && *libc::__errno_location() != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(nice)
+    }
+}
+
+/// Sets the nice value of `pid`, clamped to `NICE_RANGE` by the kernel.
+pub fn set_nice(pid: i32, nice: i32) -> io::Result<()> {
+    // SAFETY: setpriority has no memory safety preconditions.
+    if unsafe { libc::setpriority(libc::PRIO_PROCESS, pid as libc::id_t, nice) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IoClass {
+    /// No class set: the level follows the nice value.
+    None,
+    RealTime,
+    BestEffort,
+    /// Only gets disk time when no one else wants it.
+    Idle,
+}
+
+impl IoClass {
+
+    fn from_raw(class: i32) -> IoClass {
+        match class {
+            1 => IoClass::RealTime,
+            2 => IoClass::BestEffort,
+            3 => IoClass::Idle,
+            _ => IoClass::None,
+        }
+    }
+
+    fn raw(self) -> i32 {
+        match self {
+            IoClass::None => 0,
+            IoClass::RealTime => 1,
+            IoClass::BestEffort => 2,
+            IoClass::Idle => 3,
+        }
+    }
+
+    /// The name `ionice` uses.
+    pub fn name(self) -> &'static str {
+        match self {
+            IoClass::None => "none",
+            IoClass::RealTime => "rt",
+            IoClass::BestEffort => "be",
+            IoClass::Idle => "idle",
+        }
+    }
+}
+
+/// An I/O scheduling class and level, written like `be/4` or `idle`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IoPriority {
+    pub class: IoClass,
+    /// 0 (highest) to 7; meaningless for `Idle`.
+    pub level: u8,
+}
+
+impl fmt::Display for IoPriority {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.class {
+            IoClass::Idle | IoClass::None => write!(f, "{}", self.class.name()),
+            _ => write!(f, "{}/{}", self.class.name(), self.level),
+        }
+    }
+}
+
+impl FromStr for IoPriority {
+    type Err = String;
+
+    /// Accepts `idle`, `none`, or `rt` and `be` with an optional `/LEVEL`
+    /// defaulting to 4. `idle` and `none` take no level.
+    fn from_str(text: &str) -> Result<IoPriority, String> {
+        let text = text.trim();
+        let (class, level) = match text.split_once('/') {
+            Some((class, level)) => (class, Some(level)),
+            None => (text, None),
+        };
+        let class = match class {
+            "none" => IoClass::None,
+            "rt" | "realtime" => IoClass::RealTime,
+            "be" | "best-effort" => IoClass::BestEffort,
+            "idle" => IoClass::Idle,
+            _ => return Err(format!("unknown I/O class '{}', expected rt, be, idle or none", class)),
+        };
+        let level = match level {
+            Some(_) if class == IoClass::Idle || class == IoClass::None => {
+                return Err(format!("I/O class '{}' takes no level", class.name()));
+            }
+            Some(level) => match level.parse::<u8>() {
+                Ok(level) if level < IO_LEVELS => level,
+                _ => return Err(format!("I/O level '{}' is not between 0 and {}", level, IO_LEVELS - 1)),
+            },
+            None => 4,
+        };
+        Ok(IoPriority { class, level })
+    }
+}
+
+/// The I/O priority of `pid`.
+pub fn get_io_priority(pid: i32) -> io::Result<IoPriority> {
+    // SAFETY: ioprio_get takes two integers and touches no memory of ours.
+    let raw = unsafe { libc::syscall(libc::SYS_ioprio_get, IOPRIO_WHO_PROCESS, pid) };
+    if raw < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let raw = raw as i32;
+    Ok(IoPriority {
+        class: IoClass::from_raw(raw >> IOPRIO_CLASS_SHIFT),
+        level: (raw & ((1 << IOPRIO_CLASS_SHIFT) - 1)) as u8,
+    })
+}
+
+/// Sets the I/O priority of `pid`.
+pub fn set_io_priority(pid: i32, priority: IoPriority) -> io::Result<()> {
+    let level = if priority.class == IoClass::Idle { 0 } else { priority.level as i32 };
+    let raw = (priority.class.raw() << IOPRIO_CLASS_SHIFT) | level;
+    // SAFETY: ioprio_set takes three integers and touches no memory of ours.
+    if unsafe { libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, pid, raw) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<IoPriority, String> {
+        text.parse()
+    }
+
+    #[test]
+    fn parses_io_priorities() {
+        assert_eq!(parse("be/0"), Ok(IoPriority { class: IoClass::BestEffort, level: 0 }));
+        assert_eq!(parse(" rt "), Ok(IoPriority { class: IoClass::RealTime, level: 4 }));
+        assert_eq!(parse("idle").map(|p| p.class), Ok(IoClass::Idle));
+        assert_eq!(parse("best-effort/7").map(|p| p.to_string()), Ok(String::from("be/7")));
+        assert!(parse("be/8").is_err());
+        assert!(parse("be/").is_err());
+        assert!(parse("be/-1").is_err());
+        assert!(parse("idle/3").is_err());
+        assert!(parse("none/0").is_err());
+        assert!(parse("fast").is_err());
+        assert!(parse("").is_err());
+    }
+}
@@ -0,0 +1,71 @@
+//! Wire protocol spoken by the collector daemon, the remote agent and their
+//! clients.
+//!
+//! Messages are JSON objects, one per line, tagged by a `type` field. On
+//! connect the server sends `{"type":"hello","version":2,"auth_required":false}`;
+//! a client must check the version before sending anything. When
+//! `auth_required` is true the first request must be
+//! `{"type":"auth","token":"..."}`, answered with `{"type":"authenticated"}`;
+//! the server closes the connection after any other first request or a wrong
+//! token. Clients then send requests:
+//!
+//! * `{"type":"snapshot"}` is answered with
+//!   `{"type":"snapshot","snapshot":{...}}` holding the latest `Snapshot`,
+//!   or an error if nothing has been collected yet.
+//! * `{"type":"history"}` is answered with
+//!   `{"type":"history","cpu":{...},"mem":{...}}`, the daemon's CPU and
+//!   memory `TimeSeries` at every resolution it keeps.
+//! * `{"type":"subscribe"}` makes the server send a `snapshot` message after
+//!   every collection until the client disconnects. No further requests are
+//!   read on that connection.
+//!
+//! Malformed or unexpected requests get `{"type":"error","message":"..."}`.
+
+use std::io::{self, BufRead, Write};
+
+use serde::{Deserialize, Serialize};
+
+use crate::history::TimeSeries;
+use crate::snapshot::Snapshot;
+
+pub const PROTOCOL_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    Auth { token: String },
+    Snapshot,
+    History,
+    Subscribe,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Hello {
+        version: u32,
+        #[serde(default)]
+        auth_required: bool,
+    },
+    Authenticated,
+    Snapshot { snapshot: Box<Snapshot> },
+    History { cpu: TimeSeries, mem: TimeSeries },
+    Error { message: String },
+}
+
+pub fn write_message<W: Write, T: Serialize>(out: &mut W, message: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, message)?;
+    out.write_all(b"\n")?;
+    out.flush()
+}
+
+/// Reads one message. Returns `Ok(None)` when the peer closed the connection.
+pub fn read_message<R: BufRead, T: for<'de> Deserialize<'de>>(input: &mut R) -> io::Result<Option<T>> {
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    serde_json::from_str(&line)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
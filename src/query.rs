@@ -0,0 +1,141 @@
+use std::path::Path;
+
+use chrono::{DateTime, Duration, Local, NaiveDate, NaiveDateTime, TimeZone};
+use clap::{Arg, ArgMatches};
+use system_info::store::{ProcessFilter, Store};
+
+/// The database and time range arguments shared by every query.
+pub fn range_args<'a, 'b>() -> Vec<Arg<'a, 'b>> {
+    vec![
+        Arg::with_name("db")
+            .long("db")
+            .takes_value(true)
+            .value_name("DB")
+            .required(true)
+            .help("Metrics database written by --store"),
+        Arg::with_name("from")
+            .long("from")
+            .takes_value(true)
+            .value_name("TIME")
+            .default_value("-1h")
+            .allow_hyphen_values(true)
+            .validator(validate_time)
+            .help("Start of the range: now, today, yesterday, -30m, -6h, -7d, 2019-11-02 14:00 or RFC 3339"),
+        Arg::with_name("to")
+            .long("to")
+            .takes_value(true)
+            .value_name("TIME")
+            .default_value("now")
+            .allow_hyphen_values(true)
+            .validator(validate_time)
+            .help("End of the range, in the same formats as --from"),
+    ]
+}
+
+/// Runs the `query` subcommand against a metrics database.
+pub fn run(matches: &ArgMatches) -> Result<(), failure::Error> {
+    let (name, args) = matches.subcommand();
+    let args = args.expect("clap requires a query subcommand");
+    let store = Store::open_read_only(Path::new(args.value_of("db").unwrap()))?;
+    let (start, end) = time_range(args)?;
+    match name {
+        "system" => {
+            println!("{:<20} {:>8} {:>9} {:>7}", "TIME", "CPU %", "Memory %", "Load1");
+            for record in store.system_range(start, end)? {
+                println!(
+                    "{:<20} {:>8.2} {:>9.2} {:>7.2}",
+                    format_time(record.timestamp),
+                    record.cpu_percent,
+                    record.mem_percent,
+                    record.load1,
+                );
+            }
+        }
+        "process" => {
+            let filter = match args.value_of("pid") {
+                Some(pid) => ProcessFilter::Pid(pid.parse()?),
+                None => ProcessFilter::Name(args.value_of("name").unwrap().to_string()),
+            };
+            println!("{:<20} {:>7} {:<20} {:>8} {:>9} {:>10}", "TIME", "PID", "Process Name", "CPU %", "Memory %", "RSS");
+            for record in store.process_range(&filter, start, end)? {
+                println!(
+                    "{:<20} {:>7} {:<20} {:>8.2} {:>9.2} {:>10}",
+                    format_time(record.timestamp),
+                    record.pid,
+                    record.name,
+                    record.cpu_percent,
+                    record.mem_percent,
+                    record.rss,
+                );
+            }
+        }
+        "top-mem" => {
+            let limit = args.value_of("limit").unwrap().parse()?;
+            println!("{:<20} {:>12} {:>10}", "Process Name", "Peak RSS", "Avg Mem %");
+            for consumer in store.top_memory(start, end, limit)? {
+                println!(
+                    "{:<20} {:>12} {:>10.2}",
+                    consumer.name,
+                    consumer.peak_rss,
+                    consumer.average_mem_percent,
+                );
+            }
+        }
+        _ => unreachable!("clap requires a query subcommand"),
+    }
+    Ok(())
+}
+
+fn time_range(args: &ArgMatches) -> Result<(u64, u64), failure::Error> {
+    let start = parse_time(args.value_of("from").unwrap())?;
+    let end = parse_time(args.value_of("to").unwrap())?;
+    Ok((start, end))
+}
+
+/// Parses a point in time as ms since the Unix epoch. Accepts `now`,
+/// `today`, `yesterday`, offsets such as `-30m`, `-6h` or `-7d`, RFC 3339
+/// timestamps, and local `YYYY-MM-DD[ HH:MM[:SS]]` times.
+pub fn parse_time(value: &str) -> Result<u64, failure::Error> {
+    let now = Local::now();
+    let midnight = |date: NaiveDate| Local.from_local_datetime(&date.and_hms(0, 0, 0)).earliest();
+    let time = match value {
+        "now" => Some(now),
+        "today" => midnight(now.date().naive_local()),
+        "yesterday" => midnight(now.date().naive_local() - Duration::days(1)),
+        _ if value.starts_with('-') => {
+            let offset = &value[1..];
+            let unit_at = offset.char_indices().last().map_or(0, |(i, _)| i);
+            let (amount, unit) = offset.split_at(unit_at);
+            let amount: i64 = amount.parse()
+                .map_err(|_| failure::format_err!("invalid time offset '{}'", value))?;
+            let offset = match unit {
+                "s" => Duration::seconds(amount),
+                "m" => Duration::minutes(amount),
+                "h" => Duration::hours(amount),
+                "d" => Duration::days(amount),
+                _ => return Err(failure::format_err!("invalid time offset '{}'", value)),
+            };
+            Some(now - offset)
+        }
+        _ => DateTime::parse_from_rfc3339(value)
+            .map(|time| time.with_timezone(&Local))
+            .ok()
+            .or_else(|| {
+                ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"].iter()
+                    .filter_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+                    .next()
+                    .and_then(|time| Local.from_local_datetime(&time).earliest())
+            })
+            .or_else(|| NaiveDate::parse_from_str(value, "%Y-%m-%d").ok().and_then(midnight)),
+    };
+    time.map(|time| time.timestamp_millis() as u64)
+        .ok_or_else(|| failure::format_err!("invalid time '{}'", value))
+}
+
+fn validate_time(value: String) -> Result<(), String> {
+    parse_time(&value).map(|_| ()).map_err(|e| e.to_string())
+}
+
+fn format_time(timestamp: u64) -> String {
+    Local.timestamp_millis(timestamp as i64).format("%Y-%m-%d %H:%M:%S").to_string()
+}
@@ -0,0 +1,276 @@
+//! Session recordings.
+//!
+//! A recording starts with the magic bytes `SIREC`, followed by a little
+//! endian `u16` format version. Each tick is then appended as one frame:
+//!
+//! ```text
+//! u32  payload length
+//! u64  timestamp (ms since the Unix epoch)
+//! f64  system CPU %
+//! f64  system memory %
+//! ...  payload: the bincode encoded `Snapshot`
+//! ```
+//!
+//! The fixed frame header lets a reader index a recording and draw the
+//! CPU/memory chart without decoding every process table.
+
+use std::convert::TryInto;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::history::Sample;
+use crate::snapshot::Snapshot;
+
+const MAGIC: &[u8; 5] = b"SIREC";
+pub const FORMAT_VERSION: u16 = 4;
+const FILE_HEADER_LEN: u64 = 7;
+const FRAME_HEADER_LEN: u64 = 28;
+const MAX_HISTORY_POINTS: usize = 600;
+
+/// Appends snapshots to a recording, creating it if needed.
+pub struct Recorder {
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+
+    pub fn open(path: &Path) -> io::Result<Recorder> {
+        let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+        if file.metadata()?.len() == 0 {
+            file.write_all(MAGIC)?;
+            file.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        } else {
+            read_file_header(&mut file)?;
+            // Drop a frame cut short by an interrupted recorder, or readers
+            // would stop at it and miss everything appended after.
+            let (_, end) = index_frames(&file)?;
+            file.set_len(end)?;
+        }
+        Ok(Recorder {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    pub fn write_snapshot(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        let payload = bincode::serialize(snapshot)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        self.writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+        self.writer.write_all(&snapshot.timestamp.to_le_bytes())?;
+        self.writer.write_all(&snapshot.cpu_percent.to_le_bytes())?;
+        self.writer.write_all(&snapshot.mem_percent.to_le_bytes())?;
+        self.writer.write_all(&payload)?;
+        self.writer.flush()
+    }
+}
+
+/// The fixed header of one recorded frame.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameInfo {
+    pub timestamp: u64,
+    pub cpu_percent: f64,
+    pub mem_percent: f64,
+    offset: u64,
+    len: u32,
+}
+
+/// A recording opened for random access.
+pub struct Recording {
+    file: File,
+    frames: Vec<FrameInfo>,
+}
+
+impl Recording {
+
+    /// Opens a recording and indexes its frames. A partially written frame at
+    /// the end, as left by an interrupted recorder, is ignored.
+    pub fn open(path: &Path) -> io::Result<Recording> {
+        let mut file = File::open(path)?;
+        read_file_header(&mut file)?;
+        let (frames, _) = index_frames(&file)?;
+        Ok(Recording {
+            file,
+            frames,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    pub fn frames(&self) -> &[FrameInfo] {
+        &self.frames
+    }
+
+    pub fn read_snapshot(&mut self, index: usize) -> io::Result<Snapshot> {
+        let frame = self.frames.get(index)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "frame out of range"))?;
+        let mut payload = vec![0u8; frame.len as usize];
+        self.file.seek(SeekFrom::Start(frame.offset))?;
+        self.file.read_exact(&mut payload)?;
+        bincode::deserialize(&payload)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
+
+/// Playback position and speed over a `Recording`.
+pub struct Replay {
+    recording: Recording,
+    position: usize,
+    paused: bool,
+    speed: f64,
+}
+
+impl Replay {
+
+    pub fn new(recording: Recording) -> Replay {
+        Replay {
+            recording,
+            position: 0,
+            paused: false,
+            speed: 1.0,
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn len(&self) -> usize {
+        self.recording.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.recording.is_empty()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn speed(&self) -> f64 {
+        self.speed
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn faster(&mut self) {
+        self.speed = (self.speed * 2.0).min(64.0);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed = (self.speed / 2.0).max(0.25);
+    }
+
+    /// Moves by `frames`, clamped to the recording.
+    pub fn step(&mut self, frames: i64) {
+        let target = self.position as i64 + frames;
+        self.seek(target.max(0) as usize);
+    }
+
+    pub fn seek(&mut self, position: usize) {
+        self.position = position.min(self.len().saturating_sub(1));
+    }
+
+    /// How long to wait before the next frame is due at the current speed,
+    /// or `None` when paused or at the end of the recording.
+    pub fn delay_to_next(&self) -> Option<Duration> {
+        if self.paused || self.position + 1 >= self.len() {
+            return None;
+        }
+        let frames = self.recording.frames();
+        let gap = frames[self.position + 1].timestamp
+            .saturating_sub(frames[self.position].timestamp);
+        Some(Duration::from_secs_f64(gap as f64 / 1000.0 / self.speed))
+    }
+
+    pub fn current(&mut self) -> io::Result<Snapshot> {
+        self.recording.read_snapshot(self.position)
+    }
+
+    /// Timestamp of the first recorded frame.
+    pub fn start_timestamp(&self) -> u64 {
+        self.recording.frames()[0].timestamp
+    }
+
+    pub fn current_timestamp(&self) -> u64 {
+        self.recording.frames()[self.position].timestamp
+    }
+
+    /// Recorded CPU samples between `start` and `end` ms, thinned out to at
+    /// most `MAX_HISTORY_POINTS`.
+    pub fn cpu_history(&self, start: u64, end: u64) -> Vec<Sample> {
+        self.history(start, end, |f| f.cpu_percent)
+    }
+
+    /// Recorded memory samples between `start` and `end` ms.
+    pub fn mem_history(&self, start: u64, end: u64) -> Vec<Sample> {
+        self.history(start, end, |f| f.mem_percent)
+    }
+
+    fn history(&self, start: u64, end: u64, value: fn(&FrameInfo) -> f64) -> Vec<Sample> {
+        let frames: Vec<&FrameInfo> = self.recording.frames().iter()
+            .filter(|f| f.timestamp >= start && f.timestamp <= end)
+            .collect();
+        let stride = frames.len() / MAX_HISTORY_POINTS + 1;
+        frames.iter()
+            .step_by(stride)
+            .map(|f| Sample {
+                timestamp: f.timestamp,
+                value: value(f),
+            })
+            .collect()
+    }
+}
+
+/// The complete frames of a recording whose file header has been read, and
+/// the offset just past the last of them.
+fn index_frames(file: &File) -> io::Result<(Vec<FrameInfo>, u64)> {
+    let file_len = file.metadata()?.len();
+    let mut frames = vec![];
+    let mut offset = FILE_HEADER_LEN;
+    let mut reader = BufReader::new(file);
+    reader.seek(SeekFrom::Start(offset))?;
+    let mut header = [0u8; FRAME_HEADER_LEN as usize];
+    while offset + FRAME_HEADER_LEN <= file_len {
+        reader.read_exact(&mut header)?;
+        let len = u32::from_le_bytes(header[0..4].try_into().unwrap());
+        if offset + FRAME_HEADER_LEN + u64::from(len) > file_len {
+            break;
+        }
+        frames.push(FrameInfo {
+            timestamp: u64::from_le_bytes(header[4..12].try_into().unwrap()),
+            cpu_percent: f64::from_le_bytes(header[12..20].try_into().unwrap()),
+            mem_percent: f64::from_le_bytes(header[20..28].try_into().unwrap()),
+            offset: offset + FRAME_HEADER_LEN,
+            len,
+        });
+        reader.seek(SeekFrom::Current(i64::from(len)))?;
+        offset += FRAME_HEADER_LEN + u64::from(len);
+    }
+    Ok((frames, offset))
+}
+
+fn read_file_header(file: &mut File) -> io::Result<()> {
+    let mut header = [0u8; FILE_HEADER_LEN as usize];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+    if &header[0..5] != MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a system_info recording"));
+    }
+    let version = u16::from_le_bytes([header[5], header[6]]);
+    if version != FORMAT_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unsupported recording version {}", version),
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,88 @@
+//! Remote monitoring over TCP.
+//!
+//! An agent serves its `SharedState` with the same protocol as the local
+//! daemon, optionally requiring a shared token. `watch` in `daemon` keeps a
+//! client following an agent across network failures and agent restarts.
+
+use std::io;
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::daemon::{self, Client, SharedState};
+
+pub const DEFAULT_PORT: u16 = 9102;
+
+/// How long a client waits for the agent before treating the connection as
+/// dead. Agents send a snapshot every collection, so a silent minute means
+/// the network or the agent is gone.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(60);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How long the agent waits for a client to authenticate.
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Binds the agent's listener. Without a token only loopback addresses are
+/// allowed, as anyone who can connect sees the full process list.
+pub fn bind(address: &str, token: Option<&str>) -> io::Result<TcpListener> {
+    if token.is_none() {
+        if let Some(exposed) = address.to_socket_addrs()?.find(|address| !address.ip().is_loopback()) {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("refusing to listen on {} without a token", exposed),
+            ));
+        }
+    }
+    TcpListener::bind(address)
+}
+
+/// Accepts agent clients on `listener`, each on its own thread.
+pub fn serve(listener: TcpListener, state: Arc<SharedState>, token: Option<String>) -> io::Result<()> {
+    for stream in listener.incoming() {
+        let accepted = stream.and_then(|stream| {
+            if let Err(e) = stream.set_nodelay(true) {
+                eprintln!("Agent connection error: {}", e);
+            }
+            // Clients that never authenticate are dropped instead of holding
+            // a thread forever.
+            stream.set_read_timeout(Some(AUTH_TIMEOUT))?;
+            let timeout = stream.try_clone()?;
+            let authenticated = move || timeout.set_read_timeout(None);
+            daemon::spawn_client(stream.try_clone()?, stream, Arc::clone(&state), token.clone(), authenticated);
+            Ok(())
+        });
+        if let Err(e) = accepted {
+            eprintln!("Agent accept error: {}", e);
+        }
+    }
+    Ok(())
+}
+
+/// Connects to the agent at `address` (`host:port`, the port defaulting to
+/// `DEFAULT_PORT`).
+pub fn connect(address: &str, token: Option<&str>) -> io::Result<Client<TcpStream, TcpStream>> {
+    let address = with_default_port(address);
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, format!("cannot resolve {}", address));
+    for socket_address in address.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&socket_address, CONNECT_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_nodelay(true)?;
+                stream.set_read_timeout(Some(READ_TIMEOUT))?;
+                return Client::new(stream.try_clone()?, stream, token);
+            }
+            Err(e) => last_error = e,
+        }
+    }
+    Err(last_error)
+}
+
+fn with_default_port(address: &str) -> String {
+    let has_port = match address.rfind(':') {
+        Some(i) => !address.ends_with(']') && address[i + 1..].parse::<u16>().is_ok(),
+        None => false,
+    };
+    if has_port {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, DEFAULT_PORT)
+    }
+}
@@ -0,0 +1,140 @@
+use std::path::Path;
+
+use system_info::details;
+use system_info::priority::{self, IoPriority, NICE_RANGE};
+use system_info::Process;
+use termion::event::Key;
+
+use crate::ui::Dialog;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Nice,
+    Io,
+}
+
+/// Edits the nice value and I/O priority of one process.
+pub struct ReniceDialog {
+    pid: i32,
+    /// Start time of the process when the dialog opened, telling it apart
+    /// from a later one reusing its PID.
+    start: Option<u64>,
+    name: String,
+    nice: String,
+    io: String,
+    /// The values when the dialog opened; only changed ones are applied.
+    original: (String, String),
+    field: Field,
+    error: Option<String>,
+}
+
+impl ReniceDialog {
+
+    pub fn open(proc_path: &Path, process: &Process) -> ReniceDialog {
+        let nice = process.nice.to_string();
+        let io = priority::get_io_priority(process.pid)
+            .map(|io| io.to_string())
+            .unwrap_or_default();
+        ReniceDialog {
+            pid: process.pid,
+            start: details::start_time(proc_path, process.pid),
+            name: process.process_name.clone(),
+            original: (nice.clone(), io.clone()),
+            nice,
+            io,
+            field: Field::Nice,
+            error: None,
+        }
+    }
+
+    /// Handles a key meant for the dialog. Returns false when it should
+    /// close, either cancelled or after the change was applied.
+    pub fn handle_key(&mut self, key: Key, proc_path: &Path) -> bool {
+        let input = match self.field {
+            Field::Nice => &mut self.nice,
+            Field::Io => &mut self.io,
+        };
+        match key {
+            Key::Esc => return false,
+            Key::Char('\t') | Key::Up | Key::Down => {
+                self.field = if self.field == Field::Nice { Field::Io } else { Field::Nice };
+            }
+            Key::Backspace => {
+                input.pop();
+            }
+            Key::Char('\n') => match self.apply(proc_path) {
+                Ok(()) => return false,
+                Err(e) => self.error = Some(e),
+            },
+            Key::Char(c) if c.is_ascii_alphanumeric() || c == '-' || c == '/' => input.push(c),
+            _ => {}
+        }
+        true
+    }
+
+    fn apply(&self, proc_path: &Path) -> Result<(), String> {
+        if self.start.is_none() || details::start_time(proc_path, self.pid) != self.start {
+            return Err(format!("process {} has exited", self.pid));
+        }
+        let nice: i32 = self.nice.trim().parse()
+            .ok()
+            .filter(|nice| (NICE_RANGE.0..=NICE_RANGE.1).contains(nice))
+            .ok_or_else(|| format!("nice must be between {} and {}", NICE_RANGE.0, NICE_RANGE.1))?;
+        let io: Option<IoPriority> = if self.io == self.original.1 {
+            None
+        } else {
+            Some(self.io.parse()?)
+        };
+        if self.nice != self.original.0 {
+            priority::set_nice(self.pid, nice).map_err(|e| format!("renice: {}", e))?;
+        }
+        if let Some(io) = io {
+            priority::set_io_priority(self.pid, io).map_err(|e| format!("ionice: {}", e))?;
+        }
+        Ok(())
+    }
+
+    pub fn view(&self) -> Dialog {
+        let marker = |field: Field| if self.field == field { ">" } else { " " };
+        let mut lines = vec![
+            format!("{} Nice ({} to {}):  {}", marker(Field::Nice), NICE_RANGE.0, NICE_RANGE.1, self.nice),
+            format!("{} I/O priority (rt/N, be/N, idle, none):  {}", marker(Field::Io), self.io),
+            String::new(),
+            String::from("[tab] switch field [enter] apply [esc] cancel"),
+        ];
+        if let Some(ref error) = self.error {
+            lines.push(error.clone());
+        }
+        Dialog {
+            title: format!("Renice {} ({})", self.pid, self.name),
+            lines,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn refuses_a_reused_pid() {
+        let proc_path = std::env::temp_dir().join(format!("system_info-renice-{}", std::process::id()));
+        let dir = proc_path.join("4242");
+        fs::create_dir_all(&dir).unwrap();
+        let stat = |start: u64| format!("4242 (app) S 1 4242 4242 0 0 0 0 0 0 0 0 0 0 0 20 0 1 0 {} 0 10", start);
+        fs::write(dir.join("stat"), stat(100)).unwrap();
+        let process = Process::new(4242, String::from("S"), String::from("app"), 0.0, 0.0, 0.0);
+        let mut dialog = ReniceDialog::open(&proc_path, &process);
+        assert_eq!(dialog.start, Some(100));
+
+        fs::write(dir.join("stat"), stat(900)).unwrap();
+        dialog.nice = String::from("5");
+        assert!(dialog.handle_key(Key::Char('\n'), &proc_path));
+        assert_eq!(dialog.error.as_deref(), Some("process 4242 has exited"));
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(dialog.apply(&proc_path).is_err());
+        let _ = fs::remove_dir_all(&proc_path);
+    }
+}
@@ -0,0 +1,221 @@
+//! CPU affinity and scheduling policy of processes.
+//!
+//! Affinity is read and set with `sched_getaffinity`/`sched_setaffinity`,
+//! the policy with `sched_setscheduler`. Real time policies and widening an
+//! affinity beyond the cpuset need `CAP_SYS_NICE`.
+
+use std::fmt;
+use std::io;
+use std::mem;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// The highest real time priority `SCHED_FIFO` and `SCHED_RR` accept.
+pub const MAX_RT_PRIORITY: u32 = 99;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SchedPolicy {
+    #[default]
+    Other,
+    Fifo,
+    RoundRobin,
+    Batch,
+    Idle,
+    Deadline,
+}
+
+impl SchedPolicy {
+
+    /// Policies in the order the core picker cycles through them.
+    pub const ALL: [SchedPolicy; 6] = [
+        SchedPolicy::Other,
+        SchedPolicy::Batch,
+        SchedPolicy::Idle,
+        SchedPolicy::Fifo,
+        SchedPolicy::RoundRobin,
+        SchedPolicy::Deadline,
+    ];
+
+    /// The policy for field 41 of `stat`.
+    pub fn from_raw(policy: u32) -> SchedPolicy {
+        match policy as i32 {
+            libc::SCHED_FIFO => SchedPolicy::Fifo,
+            libc::SCHED_RR => SchedPolicy::RoundRobin,
+            libc::SCHED_BATCH => SchedPolicy::Batch,
+            libc::SCHED_IDLE => SchedPolicy::Idle,
+            SCHED_DEADLINE => SchedPolicy::Deadline,
+            _ => SchedPolicy::Other,
+        }
+    }
+
+    fn raw(self) -> i32 {
+        match self {
+            SchedPolicy::Other => libc::SCHED_OTHER,
+            SchedPolicy::Fifo => libc::SCHED_FIFO,
+            SchedPolicy::RoundRobin => libc::SCHED_RR,
+            SchedPolicy::Batch => libc::SCHED_BATCH,
+            SchedPolicy::Idle => libc::SCHED_IDLE,
+            SchedPolicy::Deadline => SCHED_DEADLINE,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            SchedPolicy::Other => "other",
+            SchedPolicy::Fifo => "fifo",
+            SchedPolicy::RoundRobin => "rr",
+            SchedPolicy::Batch => "batch",
+            SchedPolicy::Idle => "idle",
+            SchedPolicy::Deadline => "deadline",
+        }
+    }
+
+    /// Whether the policy takes a real time priority.
+    pub fn is_realtime(self) -> bool {
+        matches!(self, SchedPolicy::Fifo | SchedPolicy::RoundRobin)
+    }
+}
+
+/// `SCHED_DEADLINE`, which libc does not define.
+const SCHED_DEADLINE: i32 = 6;
+
+impl fmt::Display for SchedPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for SchedPolicy {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<SchedPolicy, String> {
+        SchedPolicy::ALL.iter()
+            .cloned()
+            .find(|policy| policy.name() == text)
+            .ok_or_else(|| format!("unknown scheduling policy '{}'", text))
+    }
+}
+
+/// Sets the policy of `pid`. `rt_priority` must be 1 to `MAX_RT_PRIORITY`
+/// for real time policies and is ignored otherwise. `SCHED_DEADLINE` needs
+/// runtime, deadline and period parameters and cannot be set this way.
+pub fn set_policy(pid: i32, policy: SchedPolicy, rt_priority: u32) -> io::Result<()> {
+    if policy == SchedPolicy::Deadline {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "deadline scheduling needs sched_setattr"));
+    }
+    let param = libc::sched_param {
+        sched_priority: if policy.is_realtime() { rt_priority as i32 } else { 0 },
+    };
+    // SAFETY: param lives across the call and is only read.
+    if unsafe { libc::sched_setscheduler(pid, policy.raw(), &param) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// The CPUs `pid` may run on.
+pub fn get_affinity(pid: i32) -> io::Result<Vec<usize>> {
+    // SAFETY: cpu_set_t is plain data, and the kernel writes at most its size.
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        if libc::sched_getaffinity(pid, mem::size_of::<libc::cpu_set_t>(), &mut set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok((0..libc::CPU_SETSIZE as usize).filter(|&cpu| libc::CPU_ISSET(cpu, &set)).collect())
+    }
+}
+
+/// Restricts `pid` to `cpus`.
+pub fn set_affinity(pid: i32, cpus: &[usize]) -> io::Result<()> {
+    if cpus.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "no CPUs selected"));
+    }
+    // SAFETY: cpu_set_t is plain data, CPU_SET is bounds checked against
+    // CPU_SETSIZE, and the kernel only reads the set.
+    unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        for &cpu in cpus {
+            if cpu >= libc::CPU_SETSIZE as usize {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("no CPU {}", cpu)));
+            }
+            libc::CPU_SET(cpu, &mut set);
+        }
+        if libc::sched_setaffinity(pid, mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Number of CPUs configured in the system.
+pub fn cpu_count() -> usize {
+    // SAFETY: sysconf has no preconditions.
+    let count = unsafe { libc::sysconf(libc::_SC_NPROCESSORS_CONF) };
+    if count > 0 { count as usize } else { 1 }
+}
+
+/// Parses a CPU list such as `0-3,8` as used by `Cpus_allowed_list`.
+pub fn parse_cpu_list(list: &str) -> Result<Vec<usize>, String> {
+    let mut cpus = vec![];
+    for part in list.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        let invalid = || format!("invalid CPU list entry '{}'", part);
+        match part.split_once('-') {
+            Some((first, last)) => {
+                let first: usize = first.parse().map_err(|_| invalid())?;
+                let last: usize = last.parse().map_err(|_| invalid())?;
+                if first > last {
+                    return Err(invalid());
+                }
+                cpus.extend(first..=last);
+            }
+            None => cpus.push(part.parse().map_err(|_| invalid())?),
+        }
+    }
+    cpus.sort_unstable();
+    cpus.dedup();
+    Ok(cpus)
+}
+
+/// Writes `cpus`, sorted, in the compact `0-3,8` form.
+pub fn format_cpu_list(cpus: &[usize]) -> String {
+    let mut ranges: Vec<String> = vec![];
+    let mut i = 0;
+    while i < cpus.len() {
+        let mut j = i;
+        while j + 1 < cpus.len() && cpus[j + 1] == cpus[j] + 1 {
+            j += 1;
+        }
+        ranges.push(if i == j { cpus[i].to_string() } else { format!("{}-{}", cpus[i], cpus[j]) });
+        i = j + 1;
+    }
+    ranges.join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cpu_lists() {
+        assert_eq!(parse_cpu_list("0-3,8"), Ok(vec![0, 1, 2, 3, 8]));
+        assert_eq!(parse_cpu_list("5, 1-2,,2"), Ok(vec![1, 2, 5]));
+        assert_eq!(parse_cpu_list("4-4"), Ok(vec![4]));
+        assert_eq!(parse_cpu_list(""), Ok(vec![]));
+        assert!(parse_cpu_list("3-1").is_err());
+        assert!(parse_cpu_list("1-").is_err());
+        assert!(parse_cpu_list("-1").is_err());
+        assert!(parse_cpu_list("a").is_err());
+    }
+
+    #[test]
+    fn formats_cpu_lists() {
+        assert_eq!(format_cpu_list(&[0, 1, 2, 3, 8]), "0-3,8");
+        assert_eq!(format_cpu_list(&[1, 3, 4]), "1,3-4");
+        assert_eq!(format_cpu_list(&[7]), "7");
+        assert_eq!(format_cpu_list(&[]), "");
+        let cpus = parse_cpu_list("0,2-5,9-10").unwrap();
+        assert_eq!(format_cpu_list(&cpus), "0,2-5,9-10");
+    }
+}
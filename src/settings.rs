@@ -0,0 +1,620 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::Deserialize;
+use system_info::columns::{self, Column, ColumnSpec};
+use system_info::format::Format;
+use termion::event::Key;
+use toml::Spanned;
+use tui::style::Color;
+
+use crate::ui::{Theme, View};
+use crate::util::event::Config;
+use crate::PanelMode;
+
+/// Keys the chart handles itself, which cannot be bound to anything else.
+const CHART_KEYS: &str = "1234[]f";
+
+/// Shortest refresh interval accepted.
+const MIN_INTERVAL: f64 = 0.1;
+
+/// Something a key does in the live view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Quit,
+    Up,
+    Down,
+    PageUp,
+    PageDown,
+    Details,
+    PinPid,
+    PinName,
+    Renice,
+    Affinity,
+    Signal,
+    Leaks,
+    Diagnostics,
+    Units,
+    Raw,
+    Columns,
+}
+
+impl Action {
+
+    pub const ALL: [Action; 16] = [
+        Action::Quit,
+        Action::Up,
+        Action::Down,
+        Action::PageUp,
+        Action::PageDown,
+        Action::Details,
+        Action::PinPid,
+        Action::PinName,
+        Action::Renice,
+        Action::Affinity,
+        Action::Signal,
+        Action::Leaks,
+        Action::Diagnostics,
+        Action::Units,
+        Action::Raw,
+        Action::Columns,
+    ];
+
+    /// The name used under `[keys]`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::PageUp => "page_up",
+            Action::PageDown => "page_down",
+            Action::Details => "details",
+            Action::PinPid => "pin_pid",
+            Action::PinName => "pin_name",
+            Action::Renice => "renice",
+            Action::Affinity => "affinity",
+            Action::Signal => "signal",
+            Action::Leaks => "leaks",
+            Action::Diagnostics => "diagnostics",
+            Action::Units => "units",
+            Action::Raw => "raw",
+            Action::Columns => "columns",
+        }
+    }
+
+    fn default_keys(self) -> &'static [Key] {
+        match self {
+            Action::Quit => &[Key::Char('q')],
+            Action::Up => &[Key::Up, Key::Char('k')],
+            Action::Down => &[Key::Down, Key::Char('j')],
+            Action::PageUp => &[Key::PageUp],
+            Action::PageDown => &[Key::PageDown],
+            Action::Details => &[Key::Char('\n')],
+            Action::PinPid => &[Key::Char('p')],
+            Action::PinName => &[Key::Char('P')],
+            Action::Renice => &[Key::Char('r')],
+            Action::Affinity => &[Key::Char('a')],
+            Action::Signal => &[Key::Char('K')],
+            Action::Leaks => &[Key::Char('L')],
+            Action::Diagnostics => &[Key::Char('D')],
+            Action::Units => &[Key::Char('u')],
+            Action::Raw => &[Key::Char('R')],
+            Action::Columns => &[Key::Char('c')],
+        }
+    }
+}
+
+/// Which keys do what in the live view.
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    bindings: Vec<(Key, Action)>,
+}
+
+impl Default for KeyMap {
+    fn default() -> KeyMap {
+        KeyMap {
+            bindings: Action::ALL.iter()
+                .flat_map(|&action| action.default_keys().iter().map(move |&key| (key, action)))
+                .collect(),
+        }
+    }
+}
+
+impl KeyMap {
+
+    pub fn action(&self, key: Key) -> Option<Action> {
+        self.bindings.iter().find(|(bound, _)| *bound == key).map(|&(_, action)| action)
+    }
+
+    /// The first key bound to `action`.
+    pub fn key(&self, action: Action) -> Key {
+        self.bindings.iter()
+            .find(|&&(_, bound)| bound == action)
+            .map_or(Key::Null, |&(key, _)| key)
+    }
+
+    /// The first key bound to `action`, as shown in help text.
+    pub fn label(&self, action: Action) -> String {
+        key_name(self.key(action))
+    }
+
+    /// Binds each action in `bindings` to its keys in place of its current
+    /// ones. Every rebound action gives up its keys first, so actions can
+    /// swap keys. A failure gives the index of the binding at fault.
+    fn bind(&mut self, bindings: &[(Action, Vec<Key>)]) -> Result<(), (usize, String)> {
+        self.bindings.retain(|&(_, bound)| bindings.iter().all(|&(action, _)| action != bound));
+        for (i, (action, keys)) in bindings.iter().enumerate() {
+            for &key in keys {
+                if let Key::Char(c) = key {
+                    if CHART_KEYS.contains(c) {
+                        return Err((i, format!("'{}' is used by the chart", key_name(key))));
+                    }
+                }
+                if let Some(other) = self.action(key) {
+                    return Err((i, format!("'{}' is already bound to {}", key_name(key), other.name())));
+                }
+                self.bindings.push((key, *action));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Parses a key such as `q`, `enter`, `pagedown`, `f5` or `ctrl-r`.
+pub fn parse_key(name: &str) -> Result<Key, String> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(Key::Char(c));
+    }
+    let lower = name.to_lowercase();
+    let modified = |prefix: &str| {
+        let rest = lower.strip_prefix(prefix)?;
+        let mut chars = rest.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    let key = match lower.as_str() {
+        "enter" => Key::Char('\n'),
+        "tab" => Key::Char('\t'),
+        "space" => Key::Char(' '),
+        "esc" => Key::Esc,
+        "backspace" => Key::Backspace,
+        "delete" => Key::Delete,
+        "insert" => Key::Insert,
+        "home" => Key::Home,
+        "end" => Key::End,
+        "up" => Key::Up,
+        "down" => Key::Down,
+        "left" => Key::Left,
+        "right" => Key::Right,
+        "pageup" => Key::PageUp,
+        "pagedown" => Key::PageDown,
+        _ => if let Some(c) = modified("ctrl-") {
+            Key::Ctrl(c)
+        } else if let Some(c) = modified("alt-") {
+            Key::Alt(c)
+        } else {
+            match lower.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                Some(n @ 1..=12) => Key::F(n),
+                _ => return Err(format!("unknown key '{}'", name)),
+            }
+        },
+    };
+    Ok(key)
+}
+
+/// Writes `key` the way `parse_key` reads it.
+pub fn key_name(key: Key) -> String {
+    match key {
+        Key::Char('\n') => String::from("enter"),
+        Key::Char('\t') => String::from("tab"),
+        Key::Char(' ') => String::from("space"),
+        Key::Char(c) => c.to_string(),
+        Key::Ctrl(c) => format!("ctrl-{}", c),
+        Key::Alt(c) => format!("alt-{}", c),
+        Key::F(n) => format!("f{}", n),
+        Key::Esc => String::from("esc"),
+        Key::Backspace => String::from("backspace"),
+        Key::Delete => String::from("delete"),
+        Key::Insert => String::from("insert"),
+        Key::Home => String::from("home"),
+        Key::End => String::from("end"),
+        Key::Up => String::from("up"),
+        Key::Down => String::from("down"),
+        Key::Left => String::from("left"),
+        Key::Right => String::from("right"),
+        Key::PageUp => String::from("pageup"),
+        Key::PageDown => String::from("pagedown"),
+        _ => String::from("?"),
+    }
+}
+
+/// Parses a colour name such as `cyan` or `light_red`, or `#rrggbb`.
+pub fn parse_color(name: &str) -> Result<Color, String> {
+    if let Some(hex) = name.strip_prefix('#') {
+        let channel = |i: usize| hex.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+        return match (hex.len(), channel(0), channel(2), channel(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Color::Rgb(r, g, b)),
+            _ => Err(format!("invalid colour '{}', expected #rrggbb", name)),
+        };
+    }
+    let color = match name {
+        "reset" => Color::Reset,
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" => Color::Gray,
+        "dark_gray" => Color::DarkGray,
+        "light_red" => Color::LightRed,
+        "light_green" => Color::LightGreen,
+        "light_yellow" => Color::LightYellow,
+        "light_blue" => Color::LightBlue,
+        "light_magenta" => Color::LightMagenta,
+        "light_cyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => return Err(format!("unknown colour '{}'", name)),
+    };
+    Ok(color)
+}
+
+/// A refresh interval of `seconds`, which must be at least `MIN_INTERVAL`.
+pub fn parse_interval(seconds: f64) -> Result<Duration, String> {
+    if !seconds.is_finite() || seconds < MIN_INTERVAL {
+        return Err(format!("interval must be at least {} seconds", MIN_INTERVAL));
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+/// The user's preferences for the interactive views, read from
+/// `config.toml` in the config directory:
+///
+/// ```toml
+/// interval = 2.0          # seconds between refreshes
+/// sort = "cpu"
+/// units = "decimal"
+/// precision = 1
+/// columns = ["pid", "name:24", "state", "rss", "mem", "cpu"]
+///
+/// [panels]
+/// chart = true            # draw the CPU/memory chart
+/// start = "processes"     # or leaks, diagnostics
+///
+/// [theme]
+/// cpu = "cyan"
+/// memory = "#d070d0"
+///
+/// [keys]
+/// quit = "q"
+/// up = ["up", "k"]
+/// signal = "ctrl-k"
+/// ```
+///
+/// Anything left out keeps its default; `w` in the column setup writes
+/// `columns` back here. `[keys]` rebinds the live view, and quit and the
+/// fleet's selection keys in every other view; keys handled by the chart
+/// (`1`-`4`, `[`, `]` and `f`) and by dialogs cannot be changed.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    pub interval: Duration,
+    pub sort: Column,
+    pub format: Format,
+    pub columns: Vec<ColumnSpec>,
+    pub chart: bool,
+    pub panel: PanelMode,
+    pub theme: Theme,
+    pub keys: KeyMap,
+    /// The file the settings were read from, or would be, where the column
+    /// setup saves to.
+    pub path: Option<PathBuf>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            interval: Duration::from_millis(1000),
+            sort: Column::MemPercent,
+            format: Format::default(),
+            columns: columns::default_columns(),
+            chart: true,
+            panel: PanelMode::Processes,
+            theme: Theme::default(),
+            keys: KeyMap::default(),
+            path: None,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SettingsFile {
+    interval: Option<Spanned<f64>>,
+    sort: Option<Spanned<String>>,
+    units: Option<Spanned<String>>,
+    precision: Option<Spanned<u32>>,
+    columns: Option<Spanned<Vec<String>>>,
+    #[serde(default)]
+    panels: PanelsFile,
+    #[serde(default)]
+    theme: BTreeMap<String, Spanned<String>>,
+    #[serde(default)]
+    keys: BTreeMap<String, Spanned<toml::Value>>,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PanelsFile {
+    chart: Option<bool>,
+    start: Option<Spanned<String>>,
+}
+
+/// A problem with the config file and the line it is on.
+type Invalid = (usize, String);
+
+impl Settings {
+
+    /// `config.toml` in the config directory.
+    pub fn default_path() -> Option<PathBuf> {
+        system_info::config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    /// Events for every view: ticks every interval. Input is never cut off,
+    /// so a dialog taking the quit key as text leaves the view working.
+    pub fn event_config(&self) -> Config {
+        Config {
+            exit_key: None,
+            tick_rate: self.interval,
+        }
+    }
+
+    /// Sets how `view` shows numbers, which columns and whether the chart,
+    /// and its colours.
+    pub fn apply_to(&self, view: &mut View) {
+        view.format = self.format;
+        view.columns = self.columns.clone();
+        view.chart = self.chart;
+        view.theme = self.theme;
+    }
+
+    /// Reads the settings in `path`; errors name the file and line.
+    pub fn load(path: &Path) -> io::Result<Settings> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+        Settings::parse(&text).map_err(|invalid| invalid_file(path, invalid))
+    }
+
+    /// Sets `columns` in the settings file at `path`, creating it if
+    /// needed and keeping the rest of it as written.
+    pub fn save_columns(path: &Path, columns: &[ColumnSpec]) -> io::Result<()> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(io::Error::new(e.kind(), format!("{}: {}", path.display(), e))),
+        };
+        let list: Vec<String> = columns.iter().map(|spec| format!("\"{}\"", spec)).collect();
+        let value = format!("[{}]", list.join(", "));
+        let file: SettingsFile = toml::from_str(&text).map_err(|e| invalid_file(path, toml_error(&text, &e)))?;
+        let text = match file.columns {
+            Some(old) => format!("{}{}{}", &text[..old.start()], value, &text[old.end()..]),
+            // Keys at the top belong to no table.
+            None => format!("columns = {}\n{}", value, text),
+        };
+        Settings::parse(&text).map_err(|invalid| invalid_file(path, invalid))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, text)
+    }
+
+    fn parse(text: &str) -> Result<Settings, Invalid> {
+        let file: SettingsFile = toml::from_str(text).map_err(|e| toml_error(text, &e))?;
+        let line = |start: usize| text[..start.min(text.len())].matches('\n').count() + 1;
+        let at = |start: usize| move |message: String| (line(start), message);
+
+        let mut settings = Settings::default();
+        if let Some(interval) = file.interval {
+            settings.interval = parse_interval(*interval.get_ref()).map_err(at(interval.start()))?;
+        }
+        if let Some(sort) = file.sort {
+            settings.sort = sort.get_ref().parse().map_err(at(sort.start()))?;
+        }
+        if let Some(units) = file.units {
+            settings.format.units = units.get_ref().parse().map_err(at(units.start()))?;
+        }
+        if let Some(precision) = file.precision {
+            settings.format.precision = *precision.get_ref() as usize;
+        }
+        if let Some(list) = file.columns {
+            settings.columns = columns::parse_columns(&list.get_ref().join(",")).map_err(at(list.start()))?;
+        }
+        if let Some(chart) = file.panels.chart {
+            settings.chart = chart;
+        }
+        if let Some(start) = file.panels.start {
+            settings.panel = match start.get_ref().as_str() {
+                "processes" => PanelMode::Processes,
+                "leaks" => PanelMode::Leaks,
+                "diagnostics" => PanelMode::Diagnostics,
+                other => return Err((
+                    line(start.start()),
+                    format!("unknown panel '{}', expected processes, leaks or diagnostics", other),
+                )),
+            };
+        }
+        for (name, value) in &file.theme {
+            let color = parse_color(value.get_ref()).map_err(at(value.start()))?;
+            let slot = match name.as_str() {
+                "cpu" => &mut settings.theme.cpu,
+                "memory" => &mut settings.theme.memory,
+                "title" => &mut settings.theme.title,
+                "highlight" => &mut settings.theme.highlight,
+                "text" => &mut settings.theme.text,
+                "alert" => &mut settings.theme.alert,
+                "axis" => &mut settings.theme.axis,
+                _ => return Err((line(value.start()), format!("unknown theme colour '{}'", name))),
+            };
+            *slot = color;
+        }
+        // In file order, so a conflict is reported on the later line.
+        let mut keys: Vec<_> = file.keys.iter().collect();
+        keys.sort_by_key(|(_, value)| value.start());
+        let mut bindings = vec![];
+        let mut lines = vec![];
+        for (name, value) in keys {
+            let action = Action::ALL.iter()
+                .cloned()
+                .find(|action| action.name() == name)
+                .ok_or_else(|| (line(value.start()), format!("unknown action '{}'", name)))?;
+            let names: Vec<&str> = match value.get_ref() {
+                toml::Value::String(key) => vec![key],
+                toml::Value::Array(keys) => keys.iter().filter_map(toml::Value::as_str).collect(),
+                _ => vec![],
+            };
+            if names.is_empty() || names.len() != value.get_ref().as_array().map_or(1, Vec::len) {
+                return Err((line(value.start()), format!("{} must be a key or a list of keys", name)));
+            }
+            let keys = names.into_iter()
+                .map(parse_key)
+                .collect::<Result<Vec<Key>, String>>()
+                .map_err(at(value.start()))?;
+            bindings.push((action, keys));
+            lines.push(line(value.start()));
+        }
+        settings.keys.bind(&bindings).map_err(|(i, message)| (lines[i], message))?;
+        Ok(settings)
+    }
+}
+
+fn invalid_file(path: &Path, (line, message): Invalid) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{}:{}: {}", path.display(), line, message))
+}
+
+/// Turns a TOML error into one on the right line. The parser places errors
+/// about a key's value, and unknown keys, on the line of the table holding
+/// them, so the key is looked up instead when the message names it.
+fn toml_error(text: &str, error: &toml::de::Error) -> Invalid {
+    let message = error.to_string();
+    let message = match message.find(" at line ") {
+        Some(end) => &message[..end],
+        None => &message[..],
+    };
+    let mut path: Vec<&str> = match message.find(" for key `") {
+        Some(start) => message[start + 10..].trim_end_matches('`').split('.').collect(),
+        None => vec![],
+    };
+    if let Some(field) = message.strip_prefix("unknown field `").and_then(|rest| rest.split('`').next()) {
+        path.push(field);
+    }
+    let reason = message.split(" for key `").next().unwrap_or(message).to_string();
+    let line = find_key(text, &path).or_else(|| error.line_col().map(|(line, _)| line + 1)).unwrap_or(1);
+    (line, reason)
+}
+
+/// The line `path`, such as `["keys", "quit"]`, is set on, for files that
+/// use one `[table]` header per table.
+fn find_key(text: &str, path: &[&str]) -> Option<usize> {
+    let (key, tables) = path.split_last()?;
+    let mut table = String::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            table = line.trim_matches(|c| c == '[' || c == ']').trim().to_string();
+        } else if table == tables.join(".") && line.split('=').next().map(str::trim) == Some(key) {
+            return Some(i + 1);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(text: &str) -> Invalid {
+        Settings::parse(text).unwrap_err()
+    }
+
+    #[test]
+    fn parses_settings() {
+        let settings = Settings::parse(
+            "interval = 2.5\nsort = \"cpu\"\ncolumns = [\"pid\", \"name:24\"]\n\n\
+             [panels]\nchart = false\nstart = \"leaks\"\n\n[theme]\ncpu = \"#102030\"\n",
+        ).unwrap();
+        assert_eq!(settings.interval, Duration::from_millis(2500));
+        assert_eq!(settings.sort, Column::CpuPercent);
+        assert_eq!(settings.columns, columns::parse_columns("pid,name:24").unwrap());
+        assert!(!settings.chart);
+        assert_eq!(settings.panel, PanelMode::Leaks);
+        assert_eq!(settings.theme.cpu, Color::Rgb(0x10, 0x20, 0x30));
+        assert_eq!(settings.theme.memory, Theme::default().memory);
+    }
+
+    #[test]
+    fn errors_name_the_line() {
+        assert_eq!(error("sort = \"cpu\"\ncolour = 1\n").0, 2);
+        assert_eq!(error("interval = 0.01\n").0, 1);
+        assert_eq!(error("\n[theme]\ncpu = \"cyan\"\nmemory = \"mauve\"\n"), (4, String::from("unknown colour 'mauve'")));
+        assert_eq!(error("[theme]\ncpu = \"#12345\"\n").0, 2);
+        assert_eq!(error("[theme]\nborder = \"red\"\n").0, 2);
+        assert_eq!(error("[keys]\nquit = \"q\"\njump = \"g\"\n"), (3, String::from("unknown action 'jump'")));
+        assert_eq!(error("[keys]\nquit = [\"q\", 1]\n").0, 2);
+        assert_eq!(error("[panels]\nstart = \"graphs\"\n").0, 2);
+    }
+
+    #[test]
+    fn rebinds_keys() {
+        let settings = Settings::parse("[keys]\nleaks = \"D\"\ndiagnostics = \"L\"\n").unwrap();
+        assert_eq!(settings.keys.action(Key::Char('D')), Some(Action::Leaks));
+        assert_eq!(settings.keys.action(Key::Char('L')), Some(Action::Diagnostics));
+
+        let settings = Settings::parse("[keys]\nsignal = \"k\"\nup = \"up\"\n").unwrap();
+        assert_eq!(settings.keys.action(Key::Char('k')), Some(Action::Signal));
+        assert_eq!(settings.keys.action(Key::Char('K')), None);
+        assert_eq!(settings.keys.action(Key::Up), Some(Action::Up));
+        assert_eq!(settings.keys.action(Key::Down), Some(Action::Down));
+    }
+
+    #[test]
+    fn rejects_key_conflicts() {
+        assert_eq!(
+            error("[keys]\nsignal = \"k\"\n"),
+            (2, String::from("'k' is already bound to up")),
+        );
+        assert_eq!(
+            error("[keys]\nunits = \"x\"\n\nraw = [\"R\", \"x\"]\n"),
+            (4, String::from("'x' is already bound to units")),
+        );
+        assert_eq!(error("[keys]\nquit = \"f\"\n"), (2, String::from("'f' is used by the chart")));
+    }
+
+    #[test]
+    fn saves_columns_into_the_file() {
+        let path = std::env::temp_dir().join(format!("system_info-settings-{}.toml", std::process::id()));
+        let columns = columns::parse_columns("pid,cpu:7").unwrap();
+
+        let _ = fs::remove_file(&path);
+        Settings::save_columns(&path, &columns).unwrap();
+        assert_eq!(Settings::load(&path).unwrap().columns, columns);
+
+        fs::write(&path, "# mine\nsort = \"cpu\"\n\n[theme]\ncpu = \"red\"\n").unwrap();
+        Settings::save_columns(&path, &columns).unwrap();
+        let settings = Settings::load(&path).unwrap();
+        assert_eq!((settings.columns, settings.sort), (columns.clone(), Column::CpuPercent));
+
+        fs::write(&path, "sort = \"cpu\" # mine\ncolumns = [\n  \"name\",\n] # here\n[theme]\n").unwrap();
+        Settings::save_columns(&path, &columns).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "sort = \"cpu\" # mine\ncolumns = [\"pid\", \"cpu:7\"] # here\n[theme]\n",
+        );
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,137 @@
+use std::path::PathBuf;
+
+use system_info::columns::{self, Column, ColumnSpec};
+use termion::event::Key;
+
+use crate::settings::Settings;
+use crate::ui::Dialog;
+
+/// Chooses, orders and sizes the columns of the process table.
+pub struct ColumnSetup {
+    /// Every column in display order, with whether it is shown.
+    rows: Vec<(ColumnSpec, bool)>,
+    cursor: usize,
+    /// Where `w` saves the layout.
+    path: Option<PathBuf>,
+    /// The columns to show once the setup closes with them applied.
+    applied: Option<Vec<ColumnSpec>>,
+    error: Option<String>,
+}
+
+impl ColumnSetup {
+
+    /// Opens the setup on the `current` columns, listing the hidden ones
+    /// after them.
+    pub fn open(current: &[ColumnSpec], path: Option<PathBuf>) -> ColumnSetup {
+        ColumnSetup {
+            rows: rows(current),
+            cursor: 0,
+            path,
+            applied: None,
+            error: None,
+        }
+    }
+
+    /// Handles a key meant for the setup. Returns false when it should
+    /// close, either cancelled or with the columns applied.
+    pub fn handle_key(&mut self, key: Key) -> bool {
+        let last = self.rows.len() - 1;
+        match key {
+            Key::Esc => return false,
+            Key::Up | Key::Char('k') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Down | Key::Char('j') => self.cursor = (self.cursor + 1).min(last),
+            Key::Char('K') if self.cursor > 0 => {
+                self.rows.swap(self.cursor, self.cursor - 1);
+                self.cursor -= 1;
+            }
+            Key::Char('J') if self.cursor < last => {
+                self.rows.swap(self.cursor, self.cursor + 1);
+                self.cursor += 1;
+            }
+            Key::Char(' ') => {
+                let shown = &mut self.rows[self.cursor].1;
+                *shown = !*shown;
+            }
+            Key::Char('+') | Key::Right => {
+                let spec = &mut self.rows[self.cursor].0;
+                spec.width = Some(spec.width() + 1);
+            }
+            Key::Char('-') | Key::Left => {
+                let spec = &mut self.rows[self.cursor].0;
+                spec.width = Some(spec.width().saturating_sub(1).max(1));
+            }
+            Key::Char('0') => self.rows[self.cursor].0.width = None,
+            Key::Char('d') => self.rows = rows(&columns::default_columns()),
+            Key::Char('\n') => match self.shown() {
+                Ok(columns) => {
+                    self.applied = Some(columns);
+                    return false;
+                }
+                Err(e) => self.error = Some(e),
+            },
+            Key::Char('w') => match self.shown().and_then(|columns| self.save(&columns).map(|()| columns)) {
+                Ok(columns) => {
+                    self.applied = Some(columns);
+                    return false;
+                }
+                Err(e) => self.error = Some(e),
+            },
+            _ => {}
+        }
+        true
+    }
+
+    fn shown(&self) -> Result<Vec<ColumnSpec>, String> {
+        let columns: Vec<ColumnSpec> = self.rows.iter()
+            .filter(|(_, shown)| *shown)
+            .map(|(spec, _)| *spec)
+            .collect();
+        if columns.is_empty() {
+            return Err(String::from("choose at least one column"));
+        }
+        Ok(columns)
+    }
+
+    fn save(&self, columns: &[ColumnSpec]) -> Result<(), String> {
+        let path = self.path.as_ref().ok_or("no config directory, set $XDG_CONFIG_HOME or $HOME")?;
+        Settings::save_columns(path, columns).map_err(|e| e.to_string())
+    }
+
+    /// The columns chosen, if the setup was closed by applying them.
+    pub fn applied(self) -> Option<Vec<ColumnSpec>> {
+        self.applied
+    }
+
+    pub fn view(&self) -> Dialog {
+        let mut lines = vec![format!("  Show  {:<10} {:<14} Width", "Column", "Title")];
+        lines.extend(self.rows.iter().enumerate().map(|(i, (spec, shown))| {
+            format!(
+                "{} [{}]   {:<10} {:<14} {}",
+                if i == self.cursor { ">" } else { " " },
+                if *shown { 'x' } else { ' ' },
+                spec.column.name(),
+                spec.column.title(),
+                spec.width.map_or_else(|| String::from("auto"), |width| width.to_string()),
+            )
+        }));
+        lines.push(String::new());
+        lines.push(String::from("[space] show/hide [J/K] move down/up [+/-] width [0] auto"));
+        lines.push(String::from("[d] defaults [enter] apply [w] apply and save [esc] cancel"));
+        if let Some(ref error) = self.error {
+            lines.push(error.clone());
+        }
+        Dialog {
+            title: String::from("Process table columns"),
+            lines,
+        }
+    }
+}
+
+/// The setup rows for `current`: its columns shown in order, then the rest
+/// hidden.
+fn rows(current: &[ColumnSpec]) -> Vec<(ColumnSpec, bool)> {
+    let hidden = Column::ALL.iter()
+        .filter(|&&column| current.iter().all(|spec| spec.column != column))
+        .map(|&column| (ColumnSpec::auto(column), false));
+    current.iter().map(|&spec| (spec, true)).chain(hidden).collect()
+}
@@ -0,0 +1,135 @@
+use std::path::Path;
+
+use system_info::signals::{self, Action, Scope, Target};
+use system_info::Process;
+use termion::event::Key;
+
+use crate::ui::Dialog;
+
+/// Targets listed in the preview before the rest are counted.
+const PREVIEW_ROWS: usize = 15;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Field {
+    Scope,
+    Action,
+}
+
+/// Sends a signal to a process and the processes around it, after a
+/// preview of exactly which PIDs it will reach.
+pub struct SignalDialog {
+    pid: i32,
+    name: String,
+    /// Start time of the process when the dialog opened, so a later
+    /// process reusing its PID is not signalled.
+    start: Option<u64>,
+    scope: Scope,
+    action: Action,
+    field: Field,
+    targets: Vec<Target>,
+    /// Why the preview is empty, or what went wrong sending.
+    error: Option<String>,
+}
+
+impl SignalDialog {
+
+    /// Opens the dialog for `process`.
+    pub fn open(proc_path: &Path, process: &Process) -> SignalDialog {
+        let mut dialog = SignalDialog {
+            pid: process.pid,
+            name: process.process_name.clone(),
+            start: None,
+            scope: Scope::Process,
+            action: Action::Terminate,
+            field: Field::Scope,
+            targets: vec![],
+            error: None,
+        };
+        dialog.preview(proc_path);
+        dialog
+    }
+
+    fn preview(&mut self, proc_path: &Path) {
+        match signals::targets(proc_path, self.pid, self.start, self.scope) {
+            Ok(targets) => {
+                if self.start.is_none() {
+                    self.start = targets.iter().find(|t| t.pid == self.pid).map(|t| t.start);
+                }
+                self.targets = targets;
+                self.error = None;
+            }
+            Err(e) => {
+                self.targets = vec![];
+                self.error = Some(e.to_string());
+            }
+        }
+    }
+
+    /// Handles a key meant for the dialog. Returns false when it should
+    /// close, either cancelled or after every target was signalled.
+    pub fn handle_key(&mut self, key: Key, proc_path: &Path) -> bool {
+        match key {
+            Key::Esc => return false,
+            Key::Char('\t') | Key::Up | Key::Down => {
+                self.field = if self.field == Field::Scope { Field::Action } else { Field::Scope };
+            }
+            Key::Left | Key::Right => {
+                let step = |i: usize, len: usize| if key == Key::Left { (i + len - 1) % len } else { (i + 1) % len };
+                match self.field {
+                    Field::Scope => {
+                        let i = Scope::ALL.iter().position(|&s| s == self.scope).unwrap_or(0);
+                        self.scope = Scope::ALL[step(i, Scope::ALL.len())];
+                        self.preview(proc_path);
+                    }
+                    Field::Action => {
+                        let i = Action::ALL.iter().position(|&a| a == self.action).unwrap_or(0);
+                        self.action = Action::ALL[step(i, Action::ALL.len())];
+                    }
+                }
+            }
+            Key::Char('\n') if !self.targets.is_empty() => {
+                // Processes may have exited or forked since the preview.
+                self.preview(proc_path);
+                if self.targets.is_empty() {
+                    return true;
+                }
+                let failed = signals::send(proc_path, &self.targets, self.action);
+                if failed.is_empty() {
+                    return false;
+                }
+                let (pid, ref error) = failed[0];
+                self.error = Some(format!(
+                    "{} of {} not signalled, e.g. {}: {}",
+                    failed.len(), self.targets.len(), pid, error,
+                ));
+            }
+            _ => {}
+        }
+        true
+    }
+
+    pub fn view(&self) -> Dialog {
+        let marker = |field: Field| if self.field == field { ">" } else { " " };
+        let mut lines = vec![
+            format!("{} Scope:  {}", marker(Field::Scope), self.scope),
+            format!("{} Action: {} (signal {})", marker(Field::Action), self.action, self.action.signal()),
+            String::new(),
+            format!("Would signal {} processes:", self.targets.len()),
+        ];
+        lines.extend(self.targets.iter()
+            .take(PREVIEW_ROWS)
+            .map(|target| format!("  {:>7}  {}", target.pid, target.name)));
+        if self.targets.len() > PREVIEW_ROWS {
+            lines.push(format!("  ... and {} more", self.targets.len() - PREVIEW_ROWS));
+        }
+        lines.push(String::new());
+        lines.push(String::from("[tab] field [left/right] change [enter] send [esc] cancel"));
+        if let Some(ref error) = self.error {
+            lines.push(error.clone());
+        }
+        Dialog {
+            title: format!("Signal {} ({}) and related processes", self.pid, self.name),
+            lines,
+        }
+    }
+}
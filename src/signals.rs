@@ -0,0 +1,283 @@
+//! Signals sent to a set of related processes at once.
+//!
+//! `targets` resolves a scope around one process, such as its process group
+//! or its whole subtree, to the processes it covers, so the set can be
+//! previewed before `send` signals it. Our own process is never a target.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::details::StatFields;
+
+/// Which processes around the chosen one a signal goes to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Process,
+    Group,
+    Session,
+    /// The process and all its descendants.
+    Subtree,
+    /// Every process in the same cgroups.
+    Cgroup,
+}
+
+impl Scope {
+
+    pub const ALL: [Scope; 5] = [Scope::Process, Scope::Group, Scope::Session, Scope::Subtree, Scope::Cgroup];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scope::Process => "process",
+            Scope::Group => "group",
+            Scope::Session => "session",
+            Scope::Subtree => "subtree",
+            Scope::Cgroup => "cgroup",
+        }
+    }
+}
+
+impl fmt::Display for Scope {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Scope {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Scope, String> {
+        Scope::ALL.iter()
+            .cloned()
+            .find(|scope| scope.name() == text)
+            .ok_or_else(|| format!("unknown scope '{}'", text))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Stop,
+    Continue,
+    Terminate,
+    Kill,
+}
+
+impl Action {
+
+    pub const ALL: [Action; 4] = [Action::Stop, Action::Continue, Action::Terminate, Action::Kill];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::Stop => "stop",
+            Action::Continue => "continue",
+            Action::Terminate => "terminate",
+            Action::Kill => "kill",
+        }
+    }
+
+    pub fn signal(self) -> i32 {
+        match self {
+            Action::Stop => libc::SIGSTOP,
+            Action::Continue => libc::SIGCONT,
+            Action::Terminate => libc::SIGTERM,
+            Action::Kill => libc::SIGKILL,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Action, String> {
+        Action::ALL.iter()
+            .cloned()
+            .find(|action| action.name() == text)
+            .ok_or_else(|| format!("unknown action '{}'", text))
+    }
+}
+
+/// A process a signal would go to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub pid: i32,
+    pub name: String,
+    /// Start time, field 22 of `stat`, telling the process apart from a
+    /// later one reusing its PID.
+    pub start: u64,
+}
+
+/// Name, parent, process group, session and start time from `stat`.
+struct Ids {
+    pid: i32,
+    name: String,
+    ppid: i32,
+    pgrp: i32,
+    session: i32,
+    start: u64,
+}
+
+fn read_ids(dir: &Path) -> Option<Ids> {
+    let stat = StatFields::read(dir).ok()?;
+    Some(Ids {
+        pid: stat.pid,
+        ppid: stat.value(4).ok()?,
+        pgrp: stat.value(5).ok()?,
+        session: stat.value(6).ok()?,
+        start: stat.value(22).ok()?,
+        name: stat.name,
+    })
+}
+
+fn read_cgroup(proc_path: &Path, pid: i32) -> Option<String> {
+    fs::read_to_string(proc_path.join(pid.to_string()).join("cgroup")).ok()
+}
+
+/// The running processes that `scope` around `pid` covers, read afresh
+/// from `proc_path`. With `start`, fails unless `pid` still started then.
+/// Subtrees list parents before their children, so stopping them in order
+/// leaves no running parent to fork new ones.
+pub fn targets(proc_path: &Path, pid: i32, start: Option<u64>, scope: Scope) -> io::Result<Vec<Target>> {
+    let gone = || io::Error::new(io::ErrorKind::NotFound, format!("process {} has exited", pid));
+    let chosen = read_ids(&proc_path.join(pid.to_string()))
+        .filter(|ids| start.is_none_or(|start| ids.start == start))
+        .ok_or_else(gone)?;
+    let ids: Vec<Ids> = fs::read_dir(proc_path)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            entry.file_name().to_str()?.parse::<i32>().ok()?;
+            read_ids(&entry.path())
+        })
+        .collect();
+    let mut pids: Vec<i32> = match scope {
+        Scope::Process => vec![pid],
+        Scope::Group => ids.iter().filter(|i| i.pgrp == chosen.pgrp).map(|i| i.pid).collect(),
+        Scope::Session => ids.iter().filter(|i| i.session == chosen.session).map(|i| i.pid).collect(),
+        Scope::Subtree => {
+            let mut children: HashMap<i32, Vec<i32>> = HashMap::new();
+            for i in &ids {
+                children.entry(i.ppid).or_default().push(i.pid);
+            }
+            let mut subtree = vec![pid];
+            let mut next = 0;
+            while next < subtree.len() {
+                if let Some(pids) = children.get(&subtree[next]) {
+                    subtree.extend(pids);
+                }
+                next += 1;
+            }
+            subtree
+        }
+        Scope::Cgroup => {
+            let cgroup = read_cgroup(proc_path, pid).ok_or_else(gone)?;
+            ids.iter()
+                .filter(|i| read_cgroup(proc_path, i.pid).as_ref() == Some(&cgroup))
+                .map(|i| i.pid)
+                .collect()
+        }
+    };
+    if scope != Scope::Subtree {
+        pids.sort_unstable();
+    }
+    let own = std::process::id() as i32;
+    Ok(pids.into_iter()
+        .filter(|&p| p != own)
+        .filter_map(|pid| {
+            let ids = if pid == chosen.pid { &chosen } else { ids.iter().find(|i| i.pid == pid)? };
+            Some(Target { pid, name: ids.name.clone(), start: ids.start })
+        })
+        .collect())
+}
+
+/// Sends `action` to every target still running under its PID. Returns
+/// the PIDs that could not be signalled and why; processes that exited
+/// meanwhile, or whose PID now belongs to another process, are skipped.
+pub fn send(proc_path: &Path, targets: &[Target], action: Action) -> Vec<(i32, io::Error)> {
+    targets.iter()
+        .filter(|target| read_ids(&proc_path.join(target.pid.to_string()))
+            .is_some_and(|ids| ids.start == target.start))
+        .filter_map(|target| {
+            // SAFETY: kill has no memory safety preconditions.
+            if unsafe { libc::kill(target.pid, action.signal()) } == 0 {
+                return None;
+            }
+            let error = io::Error::last_os_error();
+            if error.raw_os_error() == Some(libc::ESRCH) {
+                None
+            } else {
+                Some((target.pid, error))
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A scratch directory laid out like `/proc`.
+    struct FakeProc(PathBuf);
+
+    impl FakeProc {
+        fn new(name: &str) -> FakeProc {
+            let path = std::env::temp_dir().join(format!("system_info-signals-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            FakeProc(path)
+        }
+
+        fn add(&self, pid: i32, ppid: i32, pgrp: i32, start: u64) {
+            let dir = self.0.join(pid.to_string());
+            fs::create_dir_all(&dir).unwrap();
+            let stat = format!("{} (p{}) S {} {} 1 0 0 0 0 0 0 0 0 0 0 0 20 0 1 0 {} 0 10", pid, pid, ppid, pgrp, start);
+            fs::write(dir.join("stat"), stat).unwrap();
+        }
+    }
+
+    impl Drop for FakeProc {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn pids(targets: &[Target]) -> Vec<i32> {
+        targets.iter().map(|t| t.pid).collect()
+    }
+
+    #[test]
+    fn resolves_scopes_from_a_fresh_read() {
+        let proc = FakeProc::new("scopes");
+        proc.add(1, 0, 1, 1);
+        proc.add(10, 1, 10, 100);
+        proc.add(11, 10, 10, 110);
+        proc.add(20, 1, 20, 200);
+        let subtree = targets(&proc.0, 10, None, Scope::Subtree).unwrap();
+        assert_eq!(pids(&subtree), [10, 11]);
+        assert_eq!(subtree[1].name, "p11");
+        assert_eq!(subtree[1].start, 110);
+
+        proc.add(12, 11, 10, 120);
+        assert_eq!(pids(&targets(&proc.0, 10, Some(100), Scope::Subtree).unwrap()), [10, 11, 12]);
+        assert_eq!(pids(&targets(&proc.0, 11, None, Scope::Group).unwrap()), [10, 11, 12]);
+        assert_eq!(pids(&targets(&proc.0, 20, None, Scope::Process).unwrap()), [20]);
+    }
+
+    #[test]
+    fn refuses_a_reused_pid() {
+        let proc = FakeProc::new("reused");
+        proc.add(10, 1, 10, 100);
+        assert!(targets(&proc.0, 10, Some(100), Scope::Process).is_ok());
+        proc.add(10, 1, 10, 900);
+        assert!(targets(&proc.0, 10, Some(100), Scope::Process).is_err());
+        assert!(targets(&proc.0, 30, None, Scope::Process).is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{get_load_average, CPUUsage, CpuBreakdown, LoadAverage, MemBreakdown, MemInfo, Process, ProcessInfo};
+
+/// Everything the monitor knows at one tick: system totals and the full
+/// process list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub cpu_percent: f64,
+    pub mem_percent: f64,
+    pub total_mem: f64,
+    pub cpu_breakdown: CpuBreakdown,
+    pub mem_breakdown: MemBreakdown,
+    pub load_average: LoadAverage,
+    pub processes: Vec<Process>,
+}
+
+impl Snapshot {
+
+    pub fn capture(cpu_usage: &CPUUsage, mem_info: &MemInfo, process_info: &ProcessInfo) -> io::Result<Snapshot> {
+        Ok(Snapshot {
+            timestamp: now_millis(),
+            cpu_percent: cpu_usage.get_current_cpu(),
+            mem_percent: mem_info.get_current_mem(),
+            total_mem: process_info.get_total_mem(),
+            cpu_breakdown: cpu_usage.get_breakdown(),
+            mem_breakdown: mem_info.get_breakdown(),
+            load_average: get_load_average()?,
+            processes: process_info.get_processes().clone(),
+        })
+    }
+}
+
+/// Samples all collectors once and captures the result.
+pub fn collect(
+    cpu_usage: &mut CPUUsage,
+    mem_info: &mut MemInfo,
+    process_info: &mut ProcessInfo,
+    proc_path: &Path,
+) -> io::Result<Snapshot> {
+    cpu_usage.add_cpu_data()?;
+    mem_info.add_mem_data()?;
+    process_info.update(proc_path)?;
+    Snapshot::capture(cpu_usage, mem_info, process_info)
+}
+
+/// Writes the snapshot as a single line of JSON, suitable for NDJSON streams.
+pub fn write_ndjson<W: Write>(out: &mut W, snapshot: &Snapshot) -> io::Result<()> {
+    serde_json::to_writer(&mut *out, snapshot)?;
+    writeln!(out)?;
+    out.flush()
+}
+
+pub fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(timestamp: u64) -> Snapshot {
+        Snapshot {
+            timestamp,
+            cpu_percent: 12.5,
+            mem_percent: 40.0,
+            total_mem: 1024.0,
+            cpu_breakdown: CpuBreakdown::default(),
+            mem_breakdown: MemBreakdown::default(),
+            load_average: LoadAverage::default(),
+            processes: vec![Process::new(1, String::from("S"), String::from("init\nd"), 1.0, 2.0, 512.0)],
+        }
+    }
+
+    #[test]
+    fn writes_one_snapshot_per_line() {
+        let mut out = vec![];
+        write_ndjson(&mut out, &snapshot(1_000)).unwrap();
+        write_ndjson(&mut out, &snapshot(2_000)).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(text.ends_with('\n'));
+
+        let read: Vec<Snapshot> = lines.iter().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(read[0].timestamp, 1_000);
+        assert_eq!(read[1].timestamp, 2_000);
+        assert_eq!(read[1].cpu_percent, 12.5);
+        assert_eq!(read[1].processes[0].process_name, "init\nd");
+        assert_eq!(read[1].processes[0].rss, 512.0);
+    }
+}
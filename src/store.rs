@@ -0,0 +1,275 @@
+//! Long-term metric storage in a local SQLite database.
+//!
+//! Every snapshot adds one row to `system_samples` and a row per selected
+//! process to `process_samples`, at full resolution. `Store::maintain`
+//! averages full resolution rows older than the raw retention into one
+//! minute buckets and drops downsampled rows older than the long retention,
+//! so the database stays bounded.
+
+use std::io;
+use std::path::Path;
+
+use rusqlite::types::ToSql;
+use rusqlite::{params, Connection, OpenFlags};
+
+use crate::columns::Column;
+use crate::snapshot::Snapshot;
+use crate::Process;
+
+/// Resolution stored for rows written straight from snapshots.
+pub const RAW_RESOLUTION: i64 = 0;
+/// Bucket width in ms used when downsampling.
+pub const DOWNSAMPLED_RESOLUTION: i64 = 60_000;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS system_samples (
+        timestamp INTEGER NOT NULL,
+        resolution INTEGER NOT NULL,
+        cpu_percent REAL NOT NULL,
+        mem_percent REAL NOT NULL,
+        load1 REAL NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS system_samples_time ON system_samples (resolution, timestamp);
+    CREATE TABLE IF NOT EXISTS process_samples (
+        timestamp INTEGER NOT NULL,
+        resolution INTEGER NOT NULL,
+        pid INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        cpu_percent REAL NOT NULL,
+        mem_percent REAL NOT NULL,
+        rss REAL NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS process_samples_time ON process_samples (timestamp);
+    CREATE INDEX IF NOT EXISTS process_samples_name ON process_samples (name, timestamp);
+    CREATE INDEX IF NOT EXISTS process_samples_pid ON process_samples (pid, timestamp);
+";
+
+/// How long rows are kept, in ms.
+#[derive(Debug, Clone, Copy)]
+pub struct Retention {
+    /// Age after which full resolution rows are downsampled.
+    pub raw: u64,
+    /// Age after which downsampled rows are deleted.
+    pub downsampled: u64,
+}
+
+impl Default for Retention {
+    fn default() -> Retention {
+        Retention {
+            raw: 24 * 3_600_000,
+            downsampled: 30 * 24 * 3_600_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SystemRecord {
+    pub timestamp: u64,
+    pub cpu_percent: f64,
+    pub mem_percent: f64,
+    pub load1: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProcessRecord {
+    pub timestamp: u64,
+    pub pid: i32,
+    pub name: String,
+    pub cpu_percent: f64,
+    pub mem_percent: f64,
+    pub rss: f64,
+}
+
+/// Peak and average memory use of one process name over a query range.
+#[derive(Debug, Clone)]
+pub struct MemoryConsumer {
+    pub name: String,
+    pub peak_rss: f64,
+    pub average_mem_percent: f64,
+}
+
+/// Selects the processes a query is about.
+#[derive(Debug, Clone)]
+pub enum ProcessFilter {
+    Pid(i32),
+    Name(String),
+}
+
+pub struct Store {
+    connection: Connection,
+    top: usize,
+}
+
+impl Store {
+
+    /// Opens or creates the database at `path`. Each snapshot stores the
+    /// `top` busiest processes by CPU and the `top` largest by memory.
+    pub fn open(path: &Path, top: usize) -> io::Result<Store> {
+        let connection = Connection::open(path).map_err(to_io)?;
+        connection.execute_batch(SCHEMA).map_err(to_io)?;
+        Ok(Store {
+            connection,
+            top,
+        })
+    }
+
+    /// Opens an existing database for queries only, failing rather than
+    /// creating one when `path` does not exist.
+    pub fn open_read_only(path: &Path) -> io::Result<Store> {
+        if !path.is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{}: no such metrics database", path.display()),
+            ));
+        }
+        let connection = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY).map_err(to_io)?;
+        Ok(Store {
+            connection,
+            top: 0,
+        })
+    }
+
+    pub fn insert_snapshot(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        let transaction = self.connection.transaction().map_err(to_io)?;
+        transaction.execute(
+            "INSERT INTO system_samples VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                snapshot.timestamp as i64,
+                RAW_RESOLUTION,
+                finite(snapshot.cpu_percent),
+                finite(snapshot.mem_percent),
+                snapshot.load_average.one,
+            ],
+        ).map_err(to_io)?;
+        for process in select_processes(&snapshot.processes, self.top) {
+            transaction.execute(
+                "INSERT INTO process_samples VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    snapshot.timestamp as i64,
+                    RAW_RESOLUTION,
+                    process.pid,
+                    process.process_name,
+                    finite(process.cpu_percent),
+                    finite(process.mem_percent),
+                    process.rss,
+                ],
+            ).map_err(to_io)?;
+        }
+        transaction.commit().map_err(to_io)
+    }
+
+    /// Applies `retention` relative to `now`: old full resolution rows are
+    /// replaced by per-minute averages and expired averages are deleted.
+    pub fn maintain(&mut self, retention: Retention, now: u64) -> io::Result<()> {
+        // Whole minutes only, so a minute is never split between two passes
+        // and averaged into two rows.
+        let raw_cutoff = now.saturating_sub(retention.raw) as i64;
+        let raw_cutoff = raw_cutoff - raw_cutoff % DOWNSAMPLED_RESOLUTION;
+        let cutoff = now.saturating_sub(retention.downsampled) as i64;
+        let transaction = self.connection.transaction().map_err(to_io)?;
+        transaction.execute(
+            "INSERT INTO system_samples
+             SELECT timestamp - timestamp % ?1, ?1, AVG(cpu_percent), AVG(mem_percent), AVG(load1)
+             FROM system_samples WHERE resolution = ?2 AND timestamp < ?3
+             GROUP BY timestamp - timestamp % ?1",
+            params![DOWNSAMPLED_RESOLUTION, RAW_RESOLUTION, raw_cutoff],
+        ).map_err(to_io)?;
+        transaction.execute(
+            "INSERT INTO process_samples
+             SELECT timestamp - timestamp % ?1, ?1, pid, name, AVG(cpu_percent), AVG(mem_percent), MAX(rss)
+             FROM process_samples WHERE resolution = ?2 AND timestamp < ?3
+             GROUP BY timestamp - timestamp % ?1, pid, name",
+            params![DOWNSAMPLED_RESOLUTION, RAW_RESOLUTION, raw_cutoff],
+        ).map_err(to_io)?;
+        for table in ["system_samples", "process_samples"].iter() {
+            transaction.execute(
+                &format!("DELETE FROM {} WHERE resolution = ?1 AND timestamp < ?2", table),
+                params![RAW_RESOLUTION, raw_cutoff],
+            ).map_err(to_io)?;
+            transaction.execute(
+                &format!("DELETE FROM {} WHERE timestamp < ?1", table),
+                params![cutoff],
+            ).map_err(to_io)?;
+        }
+        transaction.commit().map_err(to_io)
+    }
+
+    pub fn system_range(&self, start: u64, end: u64) -> io::Result<Vec<SystemRecord>> {
+        let mut statement = self.connection.prepare(
+            "SELECT timestamp, cpu_percent, mem_percent, load1 FROM system_samples
+             WHERE timestamp BETWEEN ?1 AND ?2 ORDER BY timestamp",
+        ).map_err(to_io)?;
+        let rows = statement.query_map(params![start as i64, end as i64], |row| {
+            Ok(SystemRecord {
+                timestamp: row.get::<_, i64>(0)? as u64,
+                cpu_percent: row.get(1)?,
+                mem_percent: row.get(2)?,
+                load1: row.get(3)?,
+            })
+        }).map_err(to_io)?;
+        rows.collect::<Result<_, _>>().map_err(to_io)
+    }
+
+    pub fn process_range(&self, filter: &ProcessFilter, start: u64, end: u64) -> io::Result<Vec<ProcessRecord>> {
+        let (condition, value): (&str, &dyn ToSql) = match filter {
+            ProcessFilter::Pid(pid) => ("pid = ?3", pid),
+            ProcessFilter::Name(name) => ("name = ?3", name),
+        };
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT timestamp, pid, name, cpu_percent, mem_percent, rss FROM process_samples
+             WHERE timestamp BETWEEN ?1 AND ?2 AND {} ORDER BY timestamp",
+            condition,
+        )).map_err(to_io)?;
+        let rows = statement.query_map(params![start as i64, end as i64, value], |row| {
+            Ok(ProcessRecord {
+                timestamp: row.get::<_, i64>(0)? as u64,
+                pid: row.get(1)?,
+                name: row.get(2)?,
+                cpu_percent: row.get(3)?,
+                mem_percent: row.get(4)?,
+                rss: row.get(5)?,
+            })
+        }).map_err(to_io)?;
+        rows.collect::<Result<_, _>>().map_err(to_io)
+    }
+
+    /// The process names with the highest peak RSS between `start` and `end`.
+    pub fn top_memory(&self, start: u64, end: u64, limit: usize) -> io::Result<Vec<MemoryConsumer>> {
+        let mut statement = self.connection.prepare(
+            "SELECT name, MAX(rss), AVG(mem_percent) FROM process_samples
+             WHERE timestamp BETWEEN ?1 AND ?2
+             GROUP BY name ORDER BY MAX(rss) DESC LIMIT ?3",
+        ).map_err(to_io)?;
+        let rows = statement.query_map(params![start as i64, end as i64, limit as i64], |row| {
+            Ok(MemoryConsumer {
+                name: row.get(0)?,
+                peak_rss: row.get(1)?,
+                average_mem_percent: row.get(2)?,
+            })
+        }).map_err(to_io)?;
+        rows.collect::<Result<_, _>>().map_err(to_io)
+    }
+}
+
+/// The union of the `top` busiest processes by CPU and by memory.
+fn select_processes(processes: &[Process], top: usize) -> Vec<&Process> {
+    let mut selected: Vec<&Process> = vec![];
+    for column in [Column::CpuPercent, Column::Rss].iter() {
+        let mut sorted: Vec<&Process> = processes.iter().collect();
+        sorted.sort_by(|a, b| column.compare(a, b));
+        for process in sorted.into_iter().take(top) {
+            if !selected.iter().any(|p| p.pid == process.pid) {
+                selected.push(process);
+            }
+        }
+    }
+    selected
+}
+
+fn finite(value: f64) -> f64 {
+    if value.is_finite() { value } else { 0.0 }
+}
+
+fn to_io(error: rusqlite::Error) -> io::Error {
+    io::Error::other(error)
+}
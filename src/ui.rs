@@ -0,0 +1,464 @@
+use std::io::{self, Stdout};
+
+use crate::chart::{self, ChartWindow, Measure};
+use system_info::columns::{self, ColumnSpec};
+use system_info::format::Format;
+use system_info::Process;
+use termion::input::MouseTerminal;
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::screen::AlternateScreen;
+use tui::backend::{Backend, TermionBackend};
+use tui::layout::{Constraint, Direction, Layout, Rect};
+use tui::style::{Color, Modifier, Style};
+use tui::widgets::{Axis, Block, Borders, Chart, Dataset, Marker, Paragraph, Row, Sparkline, Table, Text, Widget};
+use tui::{Frame, Terminal};
+
+pub type Screen = Terminal<TermionBackend<AlternateScreen<MouseTerminal<RawTerminal<Stdout>>>>>;
+
+/// One line on the chart.
+pub struct Series {
+    pub name: String,
+    pub color: Color,
+    pub points: Vec<(f64, f64)>,
+    /// Sizes are drawn on a chart of their own below the percentages.
+    pub measure: Measure,
+}
+
+/// Colours of the interface.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Theme {
+    pub cpu: Color,
+    pub memory: Color,
+    /// Titles of the chart, fleet and detail views.
+    pub title: Color,
+    /// The selected row, table headers and dialog titles.
+    pub highlight: Color,
+    pub text: Color,
+    /// The alert banner and hosts that are down.
+    pub alert: Color,
+    pub axis: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            cpu: Color::Cyan,
+            memory: Color::Magenta,
+            title: Color::Cyan,
+            highlight: Color::Yellow,
+            text: Color::White,
+            alert: Color::Red,
+            axis: Color::Gray,
+        }
+    }
+}
+
+/// The data shown by one frame of the main view, whether it comes from the
+/// live collectors or from a recording.
+pub struct View<'a> {
+    pub title: String,
+    pub series: Vec<Series>,
+    pub x_bounds: [f64; 2],
+    pub x_labels: Vec<String>,
+    pub processes: &'a [Process],
+    /// When `processes` were read, the time their ages count to.
+    pub sampled: u64,
+    /// Lines shown in a highlighted banner above the chart.
+    pub alerts: Vec<String>,
+    /// Shown instead of the process table when set.
+    pub panel: Option<Panel>,
+    /// Index of the highlighted row of the process table.
+    pub selected: Option<usize>,
+    /// Drawn over the middle of the view when set.
+    pub dialog: Option<Dialog>,
+    pub format: Format,
+    /// Columns of the process table, in order.
+    pub columns: Vec<ColumnSpec>,
+    /// Whether the chart is drawn above the process table.
+    pub chart: bool,
+    pub theme: Theme,
+}
+
+/// A small box of text drawn over the view.
+pub struct Dialog {
+    pub title: String,
+    pub lines: Vec<String>,
+}
+
+/// A table shown in place of the process list.
+pub struct Panel {
+    pub title: String,
+    pub header: Vec<&'static str>,
+    pub widths: Vec<u16>,
+    pub rows: Vec<Vec<String>>,
+}
+
+impl<'a> View<'a> {
+
+    /// Lays out `series` over the chart window ending at `now`.
+    pub fn new(
+        title: String,
+        window: &ChartWindow,
+        now: u64,
+        series: Vec<Series>,
+        processes: &'a [Process],
+    ) -> View<'a> {
+        View {
+            title: format!("{} ({})", title, window.describe()),
+            series,
+            x_bounds: window.x_bounds(),
+            x_labels: window.x_labels(now),
+            processes,
+            sampled: now,
+            alerts: vec![],
+            panel: None,
+            selected: None,
+            dialog: None,
+            format: Format::default(),
+            columns: columns::default_columns(),
+            chart: true,
+            theme: Theme::default(),
+        }
+    }
+}
+
+pub fn open_terminal() -> io::Result<Screen> {
+    let stdout = io::stdout().into_raw_mode()?;
+    let stdout = MouseTerminal::from(stdout);
+    let stdout = AlternateScreen::from(stdout);
+    let backend = TermionBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+    terminal.hide_cursor()?;
+    Ok(terminal)
+}
+
+/// Series the chart's built-in legend has room for.
+const CHART_LEGEND_SERIES: usize = 2;
+
+/// Rows of the chart, and of the chart of sizes below it when there is one.
+const CHART_HEIGHT: u16 = 20;
+const SIZE_CHART_HEIGHT: u16 = 10;
+
+/// Alerts beyond this many are left out of the banner.
+const MAX_BANNER_LINES: usize = 3;
+
+pub fn draw<B: Backend>(terminal: &mut Terminal<B>, view: &View) -> io::Result<()> {
+    terminal.draw(|mut f| {
+        let banner_height = view.alerts.len().min(MAX_BANNER_LINES) as u16;
+        // The chart's own legend only fits two lines; more go below it, a
+        // pair of lines per row.
+        let legend_height = if view.chart && view.series.len() > CHART_LEGEND_SERIES {
+            view.series.len().div_ceil(2) as u16
+        } else {
+            0
+        };
+        let sizes = view.series.iter().any(|series| series.measure == Measure::Kilobytes);
+        let chart_height = match (view.chart, sizes) {
+            (false, _) => 0,
+            (true, false) => CHART_HEIGHT,
+            (true, true) => CHART_HEIGHT + SIZE_CHART_HEIGHT,
+        };
+        let mut chunks = Layout::default()
+            .constraints([
+                Constraint::Length(banner_height),
+                Constraint::Length(chart_height),
+                Constraint::Length(legend_height),
+                Constraint::Min(0),
+            ].as_ref())
+            .split(f.size());
+        if banner_height > 0 {
+            let banner_style = Style::default().fg(Color::White).bg(view.theme.alert).modifier(Modifier::BOLD);
+            let lines: Vec<Text> = view.alerts.iter()
+                .take(MAX_BANNER_LINES)
+                .map(|alert| Text::styled(format!("{}\n", alert), banner_style))
+                .collect();
+            Paragraph::new(lines.iter())
+                .style(banner_style)
+                .render(&mut f, chunks[0]);
+        }
+        chunks.remove(0);
+        if view.chart && sizes {
+            let charts = Layout::default()
+                .constraints([Constraint::Min(0), Constraint::Length(SIZE_CHART_HEIGHT)].as_ref())
+                .split(chunks[0]);
+            draw_chart(&mut f, charts[0], view, Measure::Percent);
+            draw_chart(&mut f, charts[1], view, Measure::Kilobytes);
+        } else if view.chart {
+            draw_chart(&mut f, chunks[0], view, Measure::Percent);
+        }
+        if legend_height > 0 {
+            let text: Vec<Text> = view.series.chunks(2)
+                .flat_map(|pair| {
+                    let mut entries: Vec<Text> = pair.iter()
+                        .map(|series| {
+                            Text::styled(format!(" ■ {:<40}", series.name), Style::default().fg(series.color))
+                        })
+                        .collect();
+                    entries.push(Text::raw("\n"));
+                    entries
+                })
+                .collect();
+            Paragraph::new(text.iter()).render(&mut f, chunks[1]);
+        }
+        chunks.remove(1);
+
+        let normal_style = Style::default().fg(view.theme.text);
+        if let Some(ref panel) = view.panel {
+            let rows = panel.rows.iter().map(|row| Row::StyledData(row.iter(), normal_style));
+            Table::new(panel.header.iter(), rows)
+                .block(Block::default().borders(Borders::ALL).title(&panel.title))
+                .widths(&panel.widths)
+                .render(&mut f, chunks[1]);
+        } else {
+            draw_processes(&mut f, chunks[1], view);
+        }
+        if let Some(ref dialog) = view.dialog {
+            draw_dialog(&mut f, dialog, &view.theme);
+        }
+    })
+}
+
+/// The chart of the series in `view` measuring `measure`, with a y axis
+/// scaled to them. Only the percentage chart has the view's title.
+fn draw_chart<B: Backend>(f: &mut Frame<B>, area: Rect, view: &View, measure: Measure) {
+    // The legend is drawn below the chart when it has too many lines.
+    let legend = view.series.len() <= CHART_LEGEND_SERIES;
+    let series: Vec<&Series> = view.series.iter().filter(|series| series.measure == measure).collect();
+    let points: Vec<&[(f64, f64)]> = series.iter().map(|series| &series.points[..]).collect();
+    let (y_bounds, y_labels) = chart::y_axis(&points, measure, &view.format);
+    let x_labels: Vec<&str> = view.x_labels.iter().map(String::as_str).collect();
+    let y_labels: Vec<&str> = y_labels.iter().map(String::as_str).collect();
+    let title = if measure == Measure::Percent { &view.title[..] } else { "" };
+    let datasets: Vec<Dataset> = series.iter()
+        .map(|series| {
+            Dataset::default()
+                .name(if legend { &series.name } else { "" })
+                .marker(Marker::Dot)
+                .style(Style::default().fg(series.color))
+                .data(&series.points)
+        })
+        .collect();
+    Chart::default()
+        .block(
+            Block::default()
+                .title(title)
+                .title_style(Style::default().fg(view.theme.title).modifier(Modifier::BOLD))
+                .borders(Borders::ALL),
+        )
+        .x_axis(
+            Axis::default()
+                .title("Time")
+                .style(Style::default().fg(view.theme.axis))
+                .labels_style(Style::default().modifier(Modifier::ITALIC))
+                .bounds(view.x_bounds)
+                .labels(&x_labels),
+        )
+        .y_axis(
+            Axis::default()
+                .title(measure.title())
+                .style(Style::default().fg(view.theme.axis))
+                .labels_style(Style::default().modifier(Modifier::ITALIC))
+                .bounds(y_bounds)
+                .labels(&y_labels),
+        )
+        .datasets(&datasets)
+        .render(f, area);
+}
+
+fn draw_processes<B: Backend>(f: &mut Frame<B>, area: Rect, view: &View) {
+    let normal_style = Style::default().fg(view.theme.text);
+    let selected_style = Style::default().fg(view.theme.highlight).modifier(Modifier::BOLD);
+    // Borders, header and the gap below it leave this many rows visible;
+    // scroll just enough to keep the selection on screen.
+    let visible = area.height.saturating_sub(4).max(1) as usize;
+    let offset = view.selected.map_or(0, |i| (i + 1).saturating_sub(visible));
+    let rows = view.processes
+        .iter()
+        .enumerate()
+        .skip(offset)
+        .map(|(i, process)| {
+            let process_vec: Vec<String> = view.columns.iter()
+                .map(|spec| spec.column.value(process, view.sampled, &view.format))
+                .collect();
+            if view.selected == Some(i) {
+                Row::StyledData(process_vec.into_iter(), selected_style)
+            } else {
+                Row::StyledData(process_vec.into_iter(), normal_style)
+            }
+        });
+    let available = area.width.saturating_sub(2) as usize;
+    // The table only drops columns that start past the edge, so leave out
+    // any that would not end before it.
+    let mut end = 0;
+    let widths: Vec<u16> = columns::fit_widths(&view.columns, view.processes, view.sampled, &view.format, available)
+        .into_iter()
+        .take_while(|width| {
+            end += width + 1;
+            end <= available + 1
+        })
+        .map(|width| width as u16)
+        .collect();
+    // Unlike cells, titles are not cut to the column width.
+    let header: Vec<String> = view.columns.iter()
+        .zip(&widths)
+        .map(|(spec, &width)| spec.column.title().chars().take(width as usize).collect())
+        .collect();
+    Table::new(header.iter(), rows)
+        .block(Block::default().borders(Borders::ALL).title(if view.chart { "Processes" } else { &view.title }))
+        .widths(&widths)
+        .render(f, area);
+}
+
+/// Widest a dialog gets.
+const DIALOG_WIDTH: u16 = 70;
+
+fn draw_dialog<B: Backend>(f: &mut Frame<B>, dialog: &Dialog, theme: &Theme) {
+    let size = f.size();
+    let width = DIALOG_WIDTH.min(size.width);
+    let height = (dialog.lines.len() as u16 + 2).min(size.height);
+    let area = Rect {
+        x: size.x + (size.width - width) / 2,
+        y: size.y + (size.height - height) / 2,
+        width,
+        height,
+    };
+    // tui has no way to clear an area, so every line is padded to the full
+    // width to hide what is underneath.
+    let inner = width.saturating_sub(2) as usize;
+    let text: Vec<Text> = dialog.lines.iter()
+        .map(|line| Text::raw(format!("{:<width$}\n", line, width = inner)))
+        .collect();
+    Paragraph::new(text.iter())
+        .block(
+            Block::default()
+                .title(&dialog.title)
+                .title_style(Style::default().fg(theme.highlight).modifier(Modifier::BOLD))
+                .borders(Borders::ALL),
+        )
+        .render(f, area);
+}
+
+/// One host in the fleet overview.
+pub struct FleetRow {
+    pub name: String,
+    pub status: String,
+    pub up: bool,
+    pub cpu: Option<f64>,
+    pub mem: Option<f64>,
+    pub load: Option<[f64; 3]>,
+    pub top_process: String,
+    /// Recent CPU percentages, oldest first.
+    pub sparkline: Vec<u64>,
+}
+
+const FLEET_TEXT_WIDTH: u16 = 84;
+
+pub fn draw_fleet<B: Backend>(
+    terminal: &mut Terminal<B>,
+    title: &str,
+    rows: &[FleetRow],
+    selected: usize,
+    message: &str,
+    format: &Format,
+    theme: &Theme,
+) -> io::Result<()> {
+    terminal.draw(|mut f| {
+        let chunks = Layout::default()
+            .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+            .split(f.size());
+        let mut block = Block::default()
+            .title(title)
+            .title_style(Style::default().fg(theme.title).modifier(Modifier::BOLD))
+            .borders(Borders::ALL);
+        let inner = block.inner(chunks[0]);
+        block.render(&mut f, chunks[0]);
+
+        let header = format!(
+            "{:<16} {:<10} {:>7} {:>7} {:>16}  {:<20}",
+            "Host", "Status", "CPU %", "Mem %", "Load", "Top Process",
+        );
+        let header_style = Style::default().fg(theme.highlight);
+        Paragraph::new([Text::styled(header, header_style)].iter())
+            .render(&mut f, Rect { height: 1.min(inner.height), ..inner });
+
+        let percent = |value: Option<f64>| value.map_or(String::from("-"), |v| format.percent(v));
+        for (i, row) in rows.iter().enumerate() {
+            let y = inner.y + 1 + i as u16;
+            if y >= inner.y + inner.height {
+                break;
+            }
+            let area = Rect { y, height: 1, ..inner };
+            let parts = Layout::default()
+                .direction(Direction::Horizontal)
+                .constraints([Constraint::Length(FLEET_TEXT_WIDTH), Constraint::Min(0)].as_ref())
+                .split(area);
+            let load = row.load.map_or(String::from("-"), |l| format!("{:.2} {:.2} {:.2}", l[0], l[1], l[2]));
+            let text = format!(
+                "{:<16} {:<10} {:>7} {:>7} {:>16}  {:<20}",
+                row.name, row.status, percent(row.cpu), percent(row.mem), load, row.top_process,
+            );
+            let mut style = Style::default().fg(if row.up { theme.text } else { theme.alert });
+            if i == selected {
+                style = style.modifier(Modifier::REVERSED);
+            }
+            Paragraph::new([Text::styled(text, style)].iter()).render(&mut f, parts[0]);
+            let width = parts[1].width as usize;
+            let start = row.sparkline.len().saturating_sub(width);
+            Sparkline::default()
+                .data(&row.sparkline[start..])
+                .max(100)
+                .style(Style::default().fg(theme.cpu))
+                .render(&mut f, parts[1]);
+        }
+
+        Paragraph::new([Text::raw(message)].iter()).render(&mut f, chunks[1]);
+    })
+}
+
+/// The full-screen view of one process.
+pub struct DetailView {
+    pub title: String,
+    pub cpu_title: String,
+    /// CPU percentages, oldest first.
+    pub cpu: Vec<u64>,
+    pub rss_title: String,
+    /// RSS in kB, oldest first.
+    pub rss: Vec<u64>,
+    pub lines: Vec<String>,
+    pub scroll: u16,
+}
+
+pub fn draw_details<B: Backend>(terminal: &mut Terminal<B>, view: &DetailView, theme: &Theme) -> io::Result<()> {
+    terminal.draw(|mut f| {
+        let chunks = Layout::default()
+            .constraints([Constraint::Length(5), Constraint::Length(5), Constraint::Min(0)].as_ref())
+            .split(f.size());
+        let sparklines = [
+            (&view.cpu_title, &view.cpu, Some(100), theme.cpu),
+            (&view.rss_title, &view.rss, None, theme.memory),
+        ];
+        for (chunk, (title, data, max, color)) in chunks.iter().zip(sparklines.iter()) {
+            let width = chunk.width.saturating_sub(2) as usize;
+            let start = data.len().saturating_sub(width);
+            let data = &data[start..];
+            Sparkline::default()
+                .block(Block::default().borders(Borders::ALL).title(title))
+                .data(data)
+                .max(max.unwrap_or_else(|| data.iter().copied().max().unwrap_or(1).max(1)))
+                .style(Style::default().fg(*color))
+                .render(&mut f, *chunk);
+        }
+        let text: Vec<Text> = view.lines.iter()
+            .map(|line| Text::raw(format!("{}\n", line)))
+            .collect();
+        Paragraph::new(text.iter())
+            .block(
+                Block::default()
+                    .title(&view.title)
+                    .title_style(Style::default().fg(theme.title).modifier(Modifier::BOLD))
+                    .borders(Borders::ALL),
+            )
+            .scroll(view.scroll)
+            .render(&mut f, chunks[2]);
+    })
+}
@@ -0,0 +1,52 @@
+//! Looks users up in `/etc/passwd`, by uid or by name.
+
+use std::fs;
+use std::io;
+
+/// The name and uid of every user in `/etc/passwd`.
+fn read_passwd() -> io::Result<Vec<(String, u32)>> {
+    Ok(parse_passwd(&fs::read_to_string("/etc/passwd")?))
+}
+
+fn parse_passwd(text: &str) -> Vec<(String, u32)> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let name = fields.next()?;
+            let uid = fields.nth(1)?.parse().ok()?;
+            Some((name.to_string(), uid))
+        })
+        .collect()
+}
+
+/// The login name of `uid`.
+pub fn user_name(uid: u32) -> Option<String> {
+    read_passwd().ok()?
+        .into_iter()
+        .find(|&(_, id)| id == uid)
+        .map(|(name, _)| name)
+}
+
+/// The uid of `user`, given as a number or a login name.
+pub fn resolve_user(user: &str) -> Result<u32, String> {
+    if let Ok(uid) = user.parse() {
+        return Ok(uid);
+    }
+    read_passwd()
+        .map_err(|e| format!("/etc/passwd: {}", e))?
+        .into_iter()
+        .find(|(name, _)| name == user)
+        .map(|(_, uid)| uid)
+        .ok_or_else(|| format!("unknown user '{}'", user))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_passwd() {
+        let users = parse_passwd("root:x:0:0:root:/root:/bin/sh\n# note\nbroken:x:nan:1\nalice:x:1000:1000::/home/alice:/bin/sh\n");
+        assert_eq!(users, [(String::from("root"), 0), (String::from("alice"), 1000)]);
+    }
+}
@@ -15,70 +15,85 @@ pub enum Event<I> {
 /// type is handled in its own thread and returned to a common `Receiver`
 #[allow(dead_code)]
 pub struct Events {
+    tx: mpsc::Sender<Event<Key>>,
     rx: mpsc::Receiver<Event<Key>>,
     input_handle: thread::JoinHandle<()>,
-    tick_handle: thread::JoinHandle<()>,
+    tick_handle: Option<thread::JoinHandle<()>>,
 }
 
 #[derive(Debug, Clone, Copy)]
 pub struct Config {
-    pub exit_key: Key,
+    /// Input stops after this key; `None` keeps reading until the program
+    /// exits.
+    pub exit_key: Option<Key>,
     pub tick_rate: Duration,
 }
 
 impl Default for Config {
     fn default() -> Config {
         Config {
-            exit_key: Key::Char('q'),
+            exit_key: Some(Key::Char('q')),
             tick_rate: Duration::from_millis(1000),
         }
     }
 }
 
 impl Events {
-    pub fn new() -> Events {
-        Events::with_config(Config::default())
+    pub fn with_config(config: Config) -> Events {
+        let mut events = Events::input_only(config);
+        events.tick_handle = Some(events.spawn_ticks(config));
+        events
     }
 
-    pub fn with_config(config: Config) -> Events {
+    /// Only forwards key presses, for callers that drive their own clock
+    /// through `next_timeout`.
+    pub fn input_only(config: Config) -> Events {
         let (tx, rx) = mpsc::channel();
         let input_handle = {
             let tx = tx.clone();
             thread::spawn(move || {
                 let stdin = io::stdin();
-                for evt in stdin.keys() {
-                    match evt {
-                        Ok(key) => {
-                            if let Err(_) = tx.send(Event::Input(key)) {
-                                return;
-                            }
-                            if key == config.exit_key {
-                                return;
-                            }
-                        }
-                        Err(_) => {}
+                for key in stdin.keys().flatten() {
+                    if tx.send(Event::Input(key)).is_err() {
+                        return;
+                    }
+                    if Some(key) == config.exit_key {
+                        return;
                     }
-                }
-            })
-        };
-        let tick_handle = {
-            let tx = tx.clone();
-            thread::spawn(move || {
-                let tx = tx.clone();
-                loop {
-                    tx.send(Event::Tick).unwrap();
-                    thread::sleep(config.tick_rate);
                 }
             })
         };
         Events {
+            tx,
             rx,
             input_handle,
-            tick_handle,
+            tick_handle: None,
         }
     }
 
+    fn spawn_ticks(&self, config: Config) -> thread::JoinHandle<()> {
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            loop {
+                if tx.send(Event::Tick).is_err() {
+                    return;
+                }
+                thread::sleep(config.tick_rate);
+            }
+        })
+    }
+
     pub fn next(&self) -> Result<Event<Key>, mpsc::RecvError> {
         self.rx.recv()
     }
+
+    /// Waits at most `timeout` for the next event. Returns `Ok(None)` if
+    /// nothing arrived in time.
+    pub fn next_timeout(&self, timeout: Duration) -> Result<Option<Event<Key>>, mpsc::RecvError> {
+        match self.rx.recv_timeout(timeout) {
+            Ok(event) => Ok(Some(event)),
+            Err(mpsc::RecvTimeoutError::Timeout) => Ok(None),
+            Err(mpsc::RecvTimeoutError::Disconnected) => Err(mpsc::RecvError),
+        }
+    }
 }
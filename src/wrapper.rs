@@ -0,0 +1,555 @@
+//! Wrapper mode: run a command and account for its whole process tree.
+//!
+//! `Wrapped::spawn` starts the command and samples `/proc` in the
+//! background to follow every descendant, including ones reparented after
+//! their parent exited. Peaks and per-process figures come from those
+//! samples, so processes living less than `SAMPLE_INTERVAL` may be missed;
+//! the CPU time and I/O totals come from `getrusage` once the command has
+//! been reaped and cover every descendant that was waited for.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::{Child, Command, ExitStatus};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::details::{clock_ticks_per_second, StatFields};
+use crate::format::Format;
+
+/// How often the process tree is sampled.
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Processes listed in the text summary; the JSON one lists them all.
+const CHILDREN_SHOWN: usize = 20;
+
+/// What was observed of one process in the tree.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChildSummary {
+    pub pid: i32,
+    pub ppid: i32,
+    pub name: String,
+    pub cmdline: String,
+    /// User and system CPU time in seconds, as of the last sample.
+    pub cpu_time: f64,
+    /// Peak RSS in kB.
+    pub peak_rss: u64,
+    pub peak_threads: u64,
+    /// Bytes fetched from and sent to storage, from `/proc/[pid]/io`.
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    /// When the process was first seen, in seconds after the command started.
+    pub first_seen: f64,
+    /// How long the process was seen running, in seconds.
+    pub seen_for: f64,
+}
+
+/// Follows the descendants of one process over time.
+#[derive(Debug)]
+pub struct SubtreeTracker {
+    root: i32,
+    started: Instant,
+    ticks_per_second: f64,
+    page_kb: u64,
+    /// Every process seen, keyed by PID and start time so reused PIDs are
+    /// told apart.
+    members: BTreeMap<(i32, u64), ChildSummary>,
+    current: Vec<i32>,
+    peak_total_rss: u64,
+    peak_threads: u64,
+}
+
+/// The fields of `stat` the tracker needs.
+struct StatEntry {
+    pid: i32,
+    ppid: i32,
+    name: String,
+    start: u64,
+    cpu_ticks: u64,
+    threads: u64,
+    rss_pages: u64,
+}
+
+impl SubtreeTracker {
+
+    pub fn new(root: i32) -> SubtreeTracker {
+        // SAFETY: sysconf has no preconditions.
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+        SubtreeTracker {
+            root,
+            started: Instant::now(),
+            ticks_per_second: clock_ticks_per_second() as f64,
+            page_kb: if page_size > 0 { page_size as u64 / 1024 } else { 4 },
+            members: BTreeMap::new(),
+            current: vec![],
+            peak_total_rss: 0,
+            peak_threads: 0,
+        }
+    }
+
+    /// The running processes of the tree at the last sample.
+    pub fn current(&self) -> &[i32] {
+        &self.current
+    }
+
+    /// Reads every process in `proc_path` and updates the tree: the root,
+    /// processes already known, and any child of those.
+    pub fn sample(&mut self, proc_path: &Path) -> io::Result<()> {
+        let entries: Vec<StatEntry> = fs::read_dir(proc_path)?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                entry.file_name().to_str()?.parse::<i32>().ok()?;
+                read_stat(&entry.path())
+            })
+            .collect();
+        let mut children: HashMap<i32, Vec<usize>> = HashMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            children.entry(entry.ppid).or_default().push(i);
+        }
+        let mut pending: Vec<usize> = entries.iter()
+            .enumerate()
+            .filter(|(_, e)| e.pid == self.root || self.members.contains_key(&(e.pid, e.start)))
+            .map(|(i, _)| i)
+            .collect();
+        let mut tree = HashSet::new();
+        while let Some(i) = pending.pop() {
+            if tree.insert(i) {
+                pending.extend(children.get(&entries[i].pid).into_iter().flatten());
+            }
+        }
+
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let (mut total_rss, mut total_threads) = (0, 0);
+        self.current.clear();
+        for &i in &tree {
+            let entry = &entries[i];
+            let dir = proc_path.join(entry.pid.to_string());
+            let member = self.members.entry((entry.pid, entry.start)).or_insert_with(|| ChildSummary {
+                pid: entry.pid,
+                ppid: entry.ppid,
+                name: String::new(),
+                cmdline: String::new(),
+                cpu_time: 0.0,
+                peak_rss: 0,
+                peak_threads: 0,
+                read_bytes: 0,
+                write_bytes: 0,
+                first_seen: elapsed,
+                seen_for: 0.0,
+            });
+            // The name changes on exec, and with it the command line.
+            if member.name != entry.name {
+                member.name = entry.name.clone();
+                member.cmdline = read_cmdline(&dir).unwrap_or_default();
+            }
+            let rss = entry.rss_pages * self.page_kb;
+            member.cpu_time = entry.cpu_ticks as f64 / self.ticks_per_second;
+            member.peak_rss = member.peak_rss.max(rss);
+            member.peak_threads = member.peak_threads.max(entry.threads);
+            if let Some((read, written)) = read_io(&dir) {
+                member.read_bytes = read;
+                member.write_bytes = written;
+            }
+            member.seen_for = elapsed - member.first_seen;
+            total_rss += rss;
+            total_threads += entry.threads;
+            self.current.push(entry.pid);
+        }
+        self.peak_total_rss = self.peak_total_rss.max(total_rss);
+        self.peak_threads = self.peak_threads.max(total_threads);
+        Ok(())
+    }
+
+    /// Combines the samples with the resource usage of reaped children.
+    fn summarize(&self, command: Vec<String>, status: &ExitStatus, wall_time: Duration, usage: &Usage) -> RunSummary {
+        use std::os::unix::process::ExitStatusExt;
+
+        let mut children: Vec<ChildSummary> = self.members.values().cloned().collect();
+        children.sort_by(|a, b| b.cpu_time.partial_cmp(&a.cpu_time).unwrap());
+        // A process's I/O counters include its reaped children, so the
+        // sampled figures are not summed.
+        let sampled_read = children.iter().map(|c| c.read_bytes).max().unwrap_or(0);
+        let sampled_written = children.iter().map(|c| c.write_bytes).max().unwrap_or(0);
+        let sampled_rss = children.iter().map(|c| c.peak_rss).max().unwrap_or(0);
+        RunSummary {
+            command,
+            exit_code: status.code(),
+            signal: status.signal(),
+            wall_time: wall_time.as_secs_f64(),
+            user_time: usage.user_time,
+            system_time: usage.system_time,
+            cpu_time: usage.user_time + usage.system_time,
+            peak_rss: usage.max_rss.max(sampled_rss),
+            peak_total_rss: self.peak_total_rss,
+            peak_threads: self.peak_threads,
+            read_bytes: usage.read_bytes.max(sampled_read),
+            write_bytes: usage.write_bytes.max(sampled_written),
+            processes: children.len(),
+            children,
+        }
+    }
+}
+
+fn read_stat(dir: &Path) -> Option<StatEntry> {
+    let stat = StatFields::read(dir).ok()?;
+    let number = |n: usize| stat.field(n)?.parse::<u64>().ok();
+    Some(StatEntry {
+        pid: dir.file_name()?.to_str()?.parse().ok()?,
+        ppid: stat.field(4)?.parse().ok()?,
+        start: number(22)?,
+        cpu_ticks: number(14)? + number(15)?,
+        threads: number(20)?,
+        rss_pages: number(24)?,
+        name: stat.name,
+    })
+}
+
+fn read_cmdline(dir: &Path) -> Option<String> {
+    let bytes = fs::read(dir.join("cmdline")).ok()?;
+    let parts: Vec<String> = bytes.split(|&b| b == 0)
+        .filter(|part| !part.is_empty())
+        .map(|part| String::from_utf8_lossy(part).into_owned())
+        .collect();
+    Some(parts.join(" "))
+}
+
+/// `read_bytes` and `write_bytes` of `/proc/[pid]/io`.
+fn read_io(dir: &Path) -> Option<(u64, u64)> {
+    let io = fs::read_to_string(dir.join("io")).ok()?;
+    let value = |key: &str| {
+        io.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| *name == key)?
+            .1.trim().parse().ok()
+    };
+    Some((value("read_bytes")?, value("write_bytes")?))
+}
+
+/// Totals `getrusage` reports for reaped children.
+#[derive(Debug, Clone, Copy, Default)]
+struct Usage {
+    user_time: f64,
+    system_time: f64,
+    /// Largest RSS of any single child, in kB.
+    max_rss: u64,
+    read_bytes: u64,
+    write_bytes: u64,
+}
+
+fn children_usage() -> io::Result<Usage> {
+    // SAFETY: rusage is plain data and getrusage only writes to it.
+    let mut usage: libc::rusage = unsafe { std::mem::zeroed() };
+    if unsafe { libc::getrusage(libc::RUSAGE_CHILDREN, &mut usage) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let seconds = |time: libc::timeval| time.tv_sec as f64 + time.tv_usec as f64 / 1e6;
+    // Block counts are in 512 byte units, see getrusage(2).
+    Ok(Usage {
+        user_time: seconds(usage.ru_utime),
+        system_time: seconds(usage.ru_stime),
+        max_rss: usage.ru_maxrss.max(0) as u64,
+        read_bytes: usage.ru_inblock.max(0) as u64 * 512,
+        write_bytes: usage.ru_oublock.max(0) as u64 * 512,
+    })
+}
+
+/// The resources a wrapped command used, like an extended `time -v`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub command: Vec<String>,
+    pub exit_code: Option<i32>,
+    /// The signal that killed the command, if one did.
+    pub signal: Option<i32>,
+    /// Times in seconds.
+    pub wall_time: f64,
+    pub user_time: f64,
+    pub system_time: f64,
+    pub cpu_time: f64,
+    /// Peak RSS of the largest single process, in kB.
+    pub peak_rss: u64,
+    /// Peak RSS of the whole tree at one sample, in kB.
+    pub peak_total_rss: u64,
+    /// Peak thread count of the whole tree at one sample.
+    pub peak_threads: u64,
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+    /// Number of processes seen in the tree.
+    pub processes: usize,
+    /// Every process seen, busiest first.
+    pub children: Vec<ChildSummary>,
+}
+
+impl RunSummary {
+
+    pub fn write_text<W: Write>(&self, out: &mut W, format: &Format) -> io::Result<()> {
+        let status = match (self.exit_code, self.signal) {
+            (Some(code), _) => code.to_string(),
+            (None, Some(signal)) => format!("killed by signal {}", signal),
+            (None, None) => String::from("unknown"),
+        };
+        writeln!(out, "Command:          {}", self.command.join(" "))?;
+        writeln!(out, "Exit status:      {}", status)?;
+        writeln!(out, "Wall time:        {:.2} s", self.wall_time)?;
+        writeln!(out, "CPU time:         {:.2} s (user {:.2} s, system {:.2} s)",
+            self.cpu_time, self.user_time, self.system_time)?;
+        writeln!(out, "Peak RSS:         {} (largest process), {} (whole tree)",
+            format.kilobytes(self.peak_rss as f64), format.kilobytes(self.peak_total_rss as f64))?;
+        writeln!(out, "Peak threads:     {}", self.peak_threads)?;
+        writeln!(out, "Bytes read:       {}", format.bytes(self.read_bytes as f64))?;
+        writeln!(out, "Bytes written:    {}", format.bytes(self.write_bytes as f64))?;
+        writeln!(out, "Processes:        {}", self.processes)?;
+        writeln!(out)?;
+        writeln!(out, "{:>7} {:>7} {:>9} {:>12} {:>7} {:>12} {:>12}  Command",
+            "PID", "PPID", "CPU s", "Peak RSS", "Threads", "Read", "Written")?;
+        for child in self.children.iter().take(CHILDREN_SHOWN) {
+            let command = if child.cmdline.is_empty() { format!("[{}]", child.name) } else { child.cmdline.clone() };
+            writeln!(out, "{:>7} {:>7} {:>9.2} {:>12} {:>7} {:>12} {:>12}  {}",
+                child.pid, child.ppid, child.cpu_time, format.kilobytes(child.peak_rss as f64), child.peak_threads,
+                format.bytes(child.read_bytes as f64), format.bytes(child.write_bytes as f64), command)?;
+        }
+        if self.children.len() > CHILDREN_SHOWN {
+            writeln!(out, "... and {} more", self.children.len() - CHILDREN_SHOWN)?;
+        }
+        Ok(())
+    }
+
+    /// The exit code to pass on: the command's own, or 128 plus the signal
+    /// that killed it, as shells do.
+    pub fn exit_code(&self) -> i32 {
+        match (self.exit_code, self.signal) {
+            (Some(code), _) => code,
+            (None, Some(signal)) => 128 + signal,
+            (None, None) => 1,
+        }
+    }
+}
+
+/// A running command whose process tree is being sampled.
+pub struct Wrapped {
+    pid: i32,
+    tracker: Arc<Mutex<SubtreeTracker>>,
+    done: Receiver<io::Result<RunSummary>>,
+}
+
+impl Wrapped {
+
+    /// Spawns `command` and starts sampling its tree. `args` is how the
+    /// command is named in the summary.
+    pub fn spawn(mut command: Command, args: Vec<String>, proc_path: &Path) -> io::Result<Wrapped> {
+        let started = Instant::now();
+        let child = command.spawn()?;
+        let pid = child.id() as i32;
+        let tracker = Arc::new(Mutex::new(SubtreeTracker::new(pid)));
+        let (tx, done) = mpsc::channel();
+        let sampled = Arc::clone(&tracker);
+        let proc_path = proc_path.to_path_buf();
+        thread::spawn(move || {
+            let _ = tx.send(sample_until_exit(child, started, args, &sampled, &proc_path));
+        });
+        Ok(Wrapped { pid, tracker, done })
+    }
+
+    pub fn pid(&self) -> i32 {
+        self.pid
+    }
+
+    /// The running processes of the tree.
+    pub fn current(&self) -> Vec<i32> {
+        self.tracker.lock().unwrap().current().to_vec()
+    }
+
+    /// Asks every process of the tree to stop with SIGTERM.
+    pub fn terminate(&self) -> io::Result<()> {
+        self.signal(libc::SIGTERM)
+    }
+
+    /// Kills every process of the tree with SIGKILL.
+    pub fn kill(&self) -> io::Result<()> {
+        self.signal(libc::SIGKILL)
+    }
+
+    fn signal(&self, signal: libc::c_int) -> io::Result<()> {
+        let mut pids = self.current();
+        if !pids.contains(&self.pid) {
+            pids.push(self.pid);
+        }
+        for pid in pids {
+            // SAFETY: kill has no memory safety preconditions. The root stays
+            // ours until the sampler thread reaps it; other PIDs may have
+            // exited since the last sample, which ESRCH reports.
+            if unsafe { libc::kill(pid, signal) } != 0 {
+                let error = io::Error::last_os_error();
+                if error.raw_os_error() != Some(libc::ESRCH) {
+                    return Err(error);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The summary if the command has exited.
+    pub fn try_finish(&self) -> Option<io::Result<RunSummary>> {
+        match self.done.try_recv() {
+            Ok(summary) => Some(summary),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(sampler_gone())),
+        }
+    }
+
+    /// Waits for the command to exit.
+    pub fn finish(self) -> io::Result<RunSummary> {
+        self.done.recv().unwrap_or_else(|_| Err(sampler_gone()))
+    }
+}
+
+fn sampler_gone() -> io::Error {
+    io::Error::other("wrapper sampling thread stopped")
+}
+
+/// Samples the tree until the root exits. Waiting happens on its own thread
+/// so the wall time is exact rather than rounded to a sample.
+fn sample_until_exit(
+    mut child: Child,
+    started: Instant,
+    args: Vec<String>,
+    tracker: &Mutex<SubtreeTracker>,
+    proc_path: &Path,
+) -> io::Result<RunSummary> {
+    let (tx, exited) = mpsc::channel();
+    thread::spawn(move || {
+        let status = child.wait();
+        let _ = tx.send(status.map(|status| (status, started.elapsed())));
+    });
+    loop {
+        tracker.lock().unwrap().sample(proc_path)?;
+        match exited.recv_timeout(SAMPLE_INTERVAL) {
+            Ok(result) => {
+                let (status, wall_time) = result?;
+                let usage = children_usage()?;
+                return Ok(tracker.lock().unwrap().summarize(args, &status, wall_time, &usage));
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return Err(sampler_gone()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A scratch directory laid out like `/proc`.
+    struct FakeProc(PathBuf);
+
+    impl FakeProc {
+        fn new(name: &str) -> FakeProc {
+            let path = std::env::temp_dir().join(format!("system_info-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&path);
+            fs::create_dir_all(&path).unwrap();
+            FakeProc(path)
+        }
+
+        fn add(&self, pid: i32, ppid: i32, name: &str, start: u64) {
+            let dir = self.0.join(pid.to_string());
+            fs::create_dir_all(&dir).unwrap();
+            let stat = format!("{} ({}) S {} 0 0 0 0 0 0 0 0 0 100 50 0 0 20 0 1 0 {} 0 10", pid, name, ppid, start);
+            fs::write(dir.join("stat"), stat).unwrap();
+        }
+
+        fn remove(&self, pid: i32) {
+            fs::remove_dir_all(self.0.join(pid.to_string())).unwrap();
+        }
+    }
+
+    impl Drop for FakeProc {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn sorted(pids: &[i32]) -> Vec<i32> {
+        let mut pids = pids.to_vec();
+        pids.sort_unstable();
+        pids
+    }
+
+    #[test]
+    fn follows_reparented_descendants() {
+        let proc = FakeProc::new("reparent");
+        proc.add(1, 0, "init", 1);
+        proc.add(100, 1, "make", 500);
+        proc.add(101, 100, "sh", 510);
+        proc.add(102, 101, "cc (wrapper)", 520);
+        proc.add(200, 1, "unrelated", 530);
+        let mut tracker = SubtreeTracker::new(100);
+        tracker.sample(&proc.0).unwrap();
+        assert_eq!(sorted(tracker.current()), vec![100, 101, 102]);
+
+        // The shell exits and its child is reparented to init.
+        proc.remove(101);
+        proc.add(102, 1, "cc (wrapper)", 520);
+        tracker.sample(&proc.0).unwrap();
+        assert_eq!(sorted(tracker.current()), vec![100, 102]);
+        assert_eq!(tracker.members[&(102, 520)].name, "cc (wrapper)");
+    }
+
+    #[test]
+    fn ignores_a_reused_pid() {
+        let proc = FakeProc::new("reuse");
+        proc.add(100, 1, "make", 500);
+        proc.add(101, 100, "cc", 510);
+        let mut tracker = SubtreeTracker::new(100);
+        tracker.sample(&proc.0).unwrap();
+
+        // 101 exits and an unrelated process gets its PID.
+        proc.remove(101);
+        proc.add(101, 1, "sshd", 900);
+        tracker.sample(&proc.0).unwrap();
+        assert_eq!(tracker.current(), &[100]);
+        assert_eq!(tracker.members.len(), 2);
+    }
+
+    #[test]
+    fn reads_io_counters() {
+        let proc = FakeProc::new("io");
+        let dir = proc.0.join("100");
+        fs::create_dir_all(&dir).unwrap();
+        assert_eq!(read_io(&dir), None);
+        fs::write(dir.join("io"), "rchar: 10\nwchar: 20\nsyscr: 1\nsyscw: 2\nread_bytes: 4096\nwrite_bytes: 512\n").unwrap();
+        assert_eq!(read_io(&dir), Some((4096, 512)));
+    }
+
+    fn summary(exit_code: Option<i32>, signal: Option<i32>) -> RunSummary {
+        RunSummary {
+            command: vec![String::from("true")],
+            exit_code,
+            signal,
+            wall_time: 0.0,
+            user_time: 0.0,
+            system_time: 0.0,
+            cpu_time: 0.0,
+            peak_rss: 0,
+            peak_total_rss: 0,
+            peak_threads: 0,
+            read_bytes: 0,
+            write_bytes: 0,
+            processes: 1,
+            children: vec![],
+        }
+    }
+
+    #[test]
+    fn exit_code_follows_the_shell() {
+        assert_eq!(summary(Some(0), None).exit_code(), 0);
+        assert_eq!(summary(Some(3), None).exit_code(), 3);
+        assert_eq!(summary(None, Some(libc::SIGKILL)).exit_code(), 137);
+        assert_eq!(summary(None, None).exit_code(), 1);
+    }
+}
@@ -0,0 +1,43 @@
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use system_info::exporter::{self, ProcessSelection};
+use system_info::snapshot::Snapshot;
+use system_info::{CpuBreakdown, LoadAverage, MemBreakdown, Process};
+
+fn get(address: &str, path: &str) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn serves_metrics_over_loopback() {
+    let snapshot = Snapshot {
+        timestamp: 1_000,
+        cpu_percent: 12.5,
+        mem_percent: 40.0,
+        total_mem: 1024.0,
+        cpu_breakdown: CpuBreakdown::default(),
+        mem_breakdown: MemBreakdown::default(),
+        load_average: LoadAverage::default(),
+        processes: vec![Process::new(1, String::from("S"), String::from("init"), 1.0, 2.0, 512.0)],
+    };
+    let metrics = exporter::render_metrics(&snapshot, &ProcessSelection::TopByCpu(10));
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    thread::spawn(move || exporter::serve(listener, Arc::new(Mutex::new(metrics))));
+
+    // An idle client must not hold up the scrape behind it.
+    let _idle = TcpStream::connect(&address).unwrap();
+    let response = get(&address, "/metrics");
+    assert!(response.starts_with("HTTP/1.1 200 OK"));
+    assert!(response.contains("system_info_cpu_usage_percent 12.5"));
+    assert!(response.contains("system_info_process_resident_memory_bytes{pid=\"1\",name=\"init\"} 524288"));
+
+    assert!(get(&address, "/other").starts_with("HTTP/1.1 404 Not Found"));
+}
@@ -0,0 +1,48 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use system_info::record::{Recorder, Recording};
+use system_info::snapshot::Snapshot;
+use system_info::{CpuBreakdown, LoadAverage, MemBreakdown, Process};
+
+fn snapshot(timestamp: u64, cpu_percent: f64) -> Snapshot {
+    Snapshot {
+        timestamp,
+        cpu_percent,
+        mem_percent: 40.0,
+        total_mem: 1024.0,
+        cpu_breakdown: CpuBreakdown::default(),
+        mem_breakdown: MemBreakdown::default(),
+        load_average: LoadAverage::default(),
+        processes: vec![Process::new(1, String::from("S"), String::from("init"), 1.0, 2.0, 512.0)],
+    }
+}
+
+fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("system_info-{}-{}.rec", name, std::process::id()))
+}
+
+#[test]
+fn appending_drops_a_truncated_frame() {
+    let path = temp_path("truncated");
+    let _ = fs::remove_file(&path);
+    {
+        let mut recorder = Recorder::open(&path).unwrap();
+        recorder.write_snapshot(&snapshot(1_000, 10.0)).unwrap();
+        recorder.write_snapshot(&snapshot(2_000, 20.0)).unwrap();
+    }
+    // A recorder killed in the middle of a frame.
+    let complete = fs::metadata(&path).unwrap().len();
+    OpenOptions::new().append(true).open(&path).unwrap().write_all(&[200, 0, 0, 0, 1, 2, 3]).unwrap();
+
+    let mut recorder = Recorder::open(&path).unwrap();
+    assert_eq!(fs::metadata(&path).unwrap().len(), complete);
+    recorder.write_snapshot(&snapshot(3_000, 30.0)).unwrap();
+
+    let mut recording = Recording::open(&path).unwrap();
+    let timestamps: Vec<u64> = recording.frames().iter().map(|frame| frame.timestamp).collect();
+    assert_eq!(timestamps, vec![1_000, 2_000, 3_000]);
+    assert_eq!(recording.read_snapshot(2).unwrap().cpu_percent, 30.0);
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,87 @@
+use std::net::TcpListener;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use system_info::daemon::{self, SharedState, Update};
+use system_info::remote;
+use system_info::snapshot::Snapshot;
+use system_info::{CpuBreakdown, LoadAverage, MemBreakdown, Process};
+
+fn snapshot(timestamp: u64, cpu_percent: f64) -> Snapshot {
+    Snapshot {
+        timestamp,
+        cpu_percent,
+        mem_percent: 40.0,
+        total_mem: 1024.0,
+        cpu_breakdown: CpuBreakdown::default(),
+        mem_breakdown: MemBreakdown::default(),
+        load_average: LoadAverage::default(),
+        processes: vec![Process::new(1, String::from("S"), String::from("init"), 1.0, 2.0, 512.0)],
+    }
+}
+
+fn start_agent(listener: TcpListener, token: Option<&str>) -> Arc<SharedState> {
+    let state = Arc::new(SharedState::new());
+    let served = Arc::clone(&state);
+    let token = token.map(String::from);
+    thread::spawn(move || remote::serve(listener, served, token));
+    state
+}
+
+#[test]
+fn loopback_agent_and_client() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let state = start_agent(listener, Some("secret"));
+    state.publish(snapshot(1_000, 12.5));
+
+    assert!(remote::connect(&address, None).is_err());
+    assert!(remote::connect(&address, Some("wrong")).is_err());
+
+    let mut client = remote::connect(&address, Some("secret")).unwrap();
+    let received = client.snapshot().unwrap();
+    assert_eq!(received.timestamp, 1_000);
+    assert_eq!(received.cpu_percent, 12.5);
+    assert_eq!(received.processes[0].process_name, "init");
+    let (cpu, mem) = client.history().unwrap();
+    assert_eq!(cpu.latest().map(|s| s.value), Some(12.5));
+    assert_eq!(mem.latest().map(|s| s.value), Some(40.0));
+
+    let mut subscription = client.subscribe().unwrap();
+    assert_eq!(subscription.next().unwrap().unwrap().timestamp, 1_000);
+    state.publish(snapshot(2_000, 50.0));
+    let pushed = subscription.next().unwrap().unwrap();
+    assert_eq!(pushed.timestamp, 2_000);
+    assert_eq!(pushed.cpu_percent, 50.0);
+}
+
+#[test]
+fn watch_reconnects_when_agent_starts() {
+    let address = {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        listener.local_addr().unwrap().to_string()
+    };
+    let connect_address = address.clone();
+    let updates = daemon::watch(move || remote::connect(&connect_address, None));
+    let timeout = Duration::from_secs(10);
+    match updates.recv_timeout(timeout).unwrap() {
+        Update::Disconnected { .. } => {}
+        update => panic!("expected a failed connection, got {:?}", update),
+    }
+
+    let state = start_agent(TcpListener::bind(&address).unwrap(), None);
+    state.publish(snapshot(3_000, 75.0));
+    match updates.recv_timeout(timeout).unwrap() {
+        Update::Connected { snapshot, .. } => assert_eq!(snapshot.timestamp, 3_000),
+        update => panic!("expected a connection, got {:?}", update),
+    }
+}
+
+#[test]
+fn agent_needs_a_token_beyond_loopback() {
+    assert!(remote::bind("127.0.0.1:0", None).is_ok());
+    let refused = remote::bind("0.0.0.0:0", None).unwrap_err();
+    assert_eq!(refused.kind(), std::io::ErrorKind::PermissionDenied);
+    assert!(remote::bind("0.0.0.0:0", Some("secret")).is_ok());
+}
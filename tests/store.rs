@@ -0,0 +1,59 @@
+use std::fs;
+
+use system_info::snapshot::Snapshot;
+use system_info::store::{Retention, Store, DOWNSAMPLED_RESOLUTION};
+use system_info::{CpuBreakdown, LoadAverage, MemBreakdown, Process};
+
+fn snapshot(timestamp: u64, cpu_percent: f64) -> Snapshot {
+    Snapshot {
+        timestamp,
+        cpu_percent,
+        mem_percent: 40.0,
+        total_mem: 1024.0,
+        cpu_breakdown: CpuBreakdown::default(),
+        mem_breakdown: MemBreakdown::default(),
+        load_average: LoadAverage::default(),
+        processes: vec![Process::new(1, String::from("S"), String::from("init"), 1.0, 2.0, 512.0)],
+    }
+}
+
+#[test]
+fn maintenance_downsamples_each_minute_once() {
+    let path = std::env::temp_dir().join(format!("system_info-store-{}.db", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let mut store = Store::open(&path, 10).unwrap();
+    let minute = DOWNSAMPLED_RESOLUTION as u64;
+    let start = 1_000 * minute;
+    for second in 0..60 {
+        store.insert_snapshot(&snapshot(start + second * 1_000, second as f64)).unwrap();
+    }
+    let retention = Retention {
+        raw: minute,
+        downsampled: 100 * minute,
+    };
+    // Two passes whose raw cutoffs fall in the middle of the same minute.
+    store.maintain(retention, start + minute + 20_000).unwrap();
+    store.maintain(retention, start + minute + 40_000).unwrap();
+    store.maintain(retention, start + 2 * minute + 1).unwrap();
+
+    let rows = store.system_range(start, start + minute).unwrap();
+    assert_eq!(rows.len(), 1);
+    assert_eq!(rows[0].timestamp, start);
+    assert_eq!(rows[0].cpu_percent, 29.5);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn queries_never_create_a_database() {
+    let path = std::env::temp_dir().join(format!("system_info-store-missing-{}.db", std::process::id()));
+    let _ = fs::remove_file(&path);
+    let missing = Store::open_read_only(&path).err().unwrap();
+    assert_eq!(missing.kind(), std::io::ErrorKind::NotFound);
+    assert!(!path.exists());
+
+    Store::open(&path, 10).unwrap().insert_snapshot(&snapshot(1_000, 12.5)).unwrap();
+    let mut store = Store::open_read_only(&path).unwrap();
+    assert_eq!(store.system_range(0, 2_000).unwrap()[0].cpu_percent, 12.5);
+    assert!(store.insert_snapshot(&snapshot(2_000, 50.0)).is_err());
+    fs::remove_file(&path).unwrap();
+}